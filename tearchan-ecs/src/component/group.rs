@@ -1,6 +1,6 @@
 use crate::component::zip::{ZipEntityBase, ZipEntityIter, ZipEntityIterMut};
 use crate::component::{Component, EntityId};
-use crate::entity::manager::ENTITY_REMAPPER;
+use crate::entity::manager::EntityRemapContext;
 use serde::de::{MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
@@ -116,9 +116,14 @@ impl<T> ComponentGroup<T> {
         }
     }
 
-    pub fn load_data(&mut self, data: ComponentGroupDeserializableData<T>) {
+    // The data keeps the entity ids of the saved world, which are remapped by the context
+    pub fn load_data(
+        &mut self,
+        data: ComponentGroupDeserializableData<T>,
+        context: &EntityRemapContext,
+    ) {
         for component in data.components {
-            self.push(component.entity_id, component.inner);
+            self.push(context.remap(component.entity_id), component.inner);
         }
     }
 
@@ -237,12 +242,7 @@ where
                     if &key != "components" {
                         continue;
                     }
-                    for component in value {
-                        components.push(Component::new(
-                            ENTITY_REMAPPER.remap(component.entity_id),
-                            component.inner,
-                        ));
-                    }
+                    components.extend(value);
                 }
                 Ok(ComponentGroupDeserializableData { components })
             }
@@ -275,7 +275,7 @@ pub struct ComponentGroupDebug<'a, T: Debug>(&'a Vec<Component<T>>);
 mod test {
     use crate::component::group::{ComponentGroup, ComponentGroupDeserializableData};
     use crate::component::zip::ZipEntity2;
-    use crate::entity::manager::{EntityManager, ENTITY_REMAPPER};
+    use crate::entity::manager::{EntityManager, EntityRemapContext};
     use serde::{Deserialize, Serialize};
     use std::cell::RefCell;
     use std::rc::Rc;
@@ -376,7 +376,6 @@ mod test {
 
     #[test]
     fn test_serialization() {
-        let _lock = ENTITY_REMAPPER.lock();
         let mut group = ComponentGroup::default();
         group.push(0, SerializationInner("entity 0".to_string()));
         group.push(1, SerializationInner("entity 1".to_string()));
@@ -388,7 +387,7 @@ mod test {
         let data: ComponentGroupDeserializableData<SerializationInner> =
             serde_json::from_str(&str).unwrap();
         let mut group = ComponentGroup::default();
-        group.load_data(data);
+        group.load_data(data, &EntityRemapContext::default());
         assert_eq!(group.get(0).as_ref().unwrap().0, "entity 0");
         assert_eq!(group.get(1).as_ref().unwrap().0, "entity 1");
         assert_eq!(group.get(2).as_ref().unwrap().0, "entity 2");
@@ -441,7 +440,13 @@ mod test {
         group.push(entity_manager.gen(), 22);
         group.push(entity_manager.gen(), 23);
 
-        let _token = entity_manager.load_data(serde_json::from_str(&world.0).unwrap());
-        group.load_data(serde_json::from_str(&world.1).unwrap());
+        let mut context = EntityRemapContext::default();
+        entity_manager.load_data(serde_json::from_str(&world.0).unwrap(), &mut context);
+        group.load_data(serde_json::from_str(&world.1).unwrap(), &context);
+        assert_eq!(
+            group.iter().map(|(_, value)| *value).collect::<Vec<_>>(),
+            vec![20, 21, 22, 23, 10, 11, 12, 13]
+        );
+        assert_eq!(group.get(context.remap(1)), Some(&10));
     }
 }
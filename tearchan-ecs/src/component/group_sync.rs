@@ -93,7 +93,7 @@ mod test {
     use crate::component::group::{ComponentGroup, ComponentGroupDeserializableData};
     use crate::component::group_sync::ComponentGroupSync;
     use crate::component::zip::ZipEntity1;
-    use crate::entity::manager::ENTITY_REMAPPER;
+    use crate::entity::manager::EntityRemapContext;
     use tearchan_util::thread::ThreadPool;
 
     #[test]
@@ -169,7 +169,6 @@ mod test {
 
     #[test]
     fn test_serialization() {
-        let _lock = ENTITY_REMAPPER.lock();
         let mut group: ComponentGroupSync<i32> = ComponentGroupSync::default();
         group.write().get_mut().push(0, 0);
         group.write().get_mut().push(1, 11);
@@ -181,7 +180,10 @@ mod test {
         let data: ComponentGroupDeserializableData<i32> = serde_json::from_str(&str).unwrap();
         let component_group = ComponentGroup::default();
         let mut component_group_sync = ComponentGroupSync::new(component_group);
-        component_group_sync.write().get_mut().load_data(data);
+        component_group_sync
+            .write()
+            .get_mut()
+            .load_data(data, &EntityRemapContext::default());
         assert_eq!(*component_group_sync.read().get().get(0).unwrap(), 0);
         assert_eq!(*component_group_sync.read().get().get(1).unwrap(), 11);
         assert_eq!(*component_group_sync.read().get().get(2).unwrap(), 22);
@@ -77,12 +77,11 @@ impl EntityManager {
         self.0.read().unwrap().entity_ids.contains(&entity_id)
     }
 
-    pub fn load_data(&self, data: EntityManagerData) -> EntityRemapperToken {
-        let mut mapping: HashMap<EntityId, EntityId> = HashMap::new(); // key = from, value = to
+    // Generates the entities of the data, and records their new ids to the context
+    pub fn load_data(&self, data: EntityManagerData, context: &mut EntityRemapContext) {
         for entity_id in data.entity_ids {
-            mapping.insert(entity_id, self.gen());
+            context.mapping.insert(entity_id, self.gen());
         }
-        EntityRemapperToken::new(Some(mapping))
     }
 
     pub fn to_data(&self) -> EntityManagerData {
@@ -132,27 +131,61 @@ pub struct EntityManagerData {
     entity_ids: BTreeSet<EntityId>,
 }
 
+impl EntityManagerData {
+    pub fn new(entity_ids: BTreeSet<EntityId>) -> Self {
+        EntityManagerData { entity_ids }
+    }
+}
+
+// The mapping of the entity ids from the loaded data to the loading world. It's passed to the
+// loading functions explicitly, so the worlds can be loaded concurrently.
+#[derive(Default, Debug, Clone)]
+pub struct EntityRemapContext {
+    mapping: HashMap<EntityId, EntityId>, // key = from, value = to
+}
+
+impl EntityRemapContext {
+    pub fn remap(&self, entity_id: EntityId) -> EntityId {
+        *self.mapping.get(&entity_id).unwrap_or(&entity_id)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.mapping.is_empty()
+    }
+
+    // Sets the context to ENTITY_REMAPPER while the token is alive, for the Deserialize impls
+    // which still depend on it
+    #[deprecated(note = "pass the EntityRemapContext to the loading functions instead")]
+    pub fn install(&self) -> EntityRemapperToken<'static> {
+        EntityRemapperToken::new(self.clone())
+    }
+}
+
+// The compatibility shim of EntityRemapContext, which is shared by the whole process. It remaps
+// by the installed context, or returns the ids as they are.
 #[derive(Default)]
 pub struct EntityRemapper {
-    mapping: Mutex<Option<HashMap<EntityId, EntityId>>>,
+    context: Mutex<Option<EntityRemapContext>>,
 }
 
 impl EntityRemapper {
     pub fn remap(&self, entity_id: EntityId) -> EntityId {
-        let mapping = self.mapping.lock().unwrap();
-        if let Some(mapping) = mapping.as_ref() {
-            return *mapping.get(&entity_id).unwrap_or(&entity_id);
+        match self.context.lock().unwrap().as_ref() {
+            Some(context) => context.remap(entity_id),
+            None => entity_id,
         }
-        entity_id
     }
 
-    pub fn lock(&self) -> EntityRemapperToken {
+    // Blocks the installations while the token is alive
+    pub fn lock(&self) -> EntityRemapperToken<'_> {
         EntityRemapperToken {
             _guard: ENTITY_REMAPPER_WRITE_LOCK.lock().unwrap(),
         }
     }
 }
 
+#[deprecated(note = "pass the EntityRemapContext to the loading functions instead")]
 pub static ENTITY_REMAPPER: Lazy<EntityRemapper> = Lazy::new(EntityRemapper::default);
 static ENTITY_REMAPPER_WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
@@ -160,23 +193,29 @@ pub struct EntityRemapperToken<'a> {
     _guard: MutexGuard<'a, ()>,
 }
 
+#[allow(deprecated)]
 impl<'a> EntityRemapperToken<'a> {
-    fn new(mapping: Option<HashMap<EntityId, EntityId>>) -> Self {
+    fn new(context: EntityRemapContext) -> Self {
         let guard = ENTITY_REMAPPER_WRITE_LOCK.lock().unwrap();
-        *ENTITY_REMAPPER.mapping.lock().unwrap() = mapping;
+        *ENTITY_REMAPPER.context.lock().unwrap() = Some(context);
         Self { _guard: guard }
     }
 }
 
+#[allow(deprecated)]
 impl<'a> Drop for EntityRemapperToken<'a> {
     fn drop(&mut self) {
-        *ENTITY_REMAPPER.mapping.lock().unwrap() = None;
+        *ENTITY_REMAPPER.context.lock().unwrap() = None;
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::entity::manager::{EntityManager, EntityManagerData, IdManager, ENTITY_REMAPPER};
+    #![allow(deprecated)]
+
+    use crate::entity::manager::{
+        EntityManager, EntityManagerData, EntityRemapContext, IdManager, ENTITY_REMAPPER,
+    };
     use std::collections::BTreeSet;
     use std::time::Duration;
 
@@ -217,13 +256,18 @@ mod test {
         let json = serde_json::to_string(&entity_manager.to_data()).unwrap();
 
         let data: EntityManagerData = serde_json::from_str(&json).unwrap();
+        let mut context = EntityRemapContext::default();
+        entity_manager.load_data(data, &mut context);
+        assert_ne!(entity_id1, context.remap(entity_id1));
+        assert_ne!(entity_id2, context.remap(entity_id2));
+        assert_ne!(entity_id3, context.remap(entity_id3));
+        assert_eq!(entity_id4, context.remap(entity_id4));
         {
-            let _token = entity_manager.load_data(data);
-            assert_ne!(entity_id1, ENTITY_REMAPPER.remap(entity_id1));
-            assert_ne!(entity_id2, ENTITY_REMAPPER.remap(entity_id2));
-            assert_ne!(entity_id3, ENTITY_REMAPPER.remap(entity_id3));
+            let _token = context.install();
+            assert_eq!(context.remap(entity_id1), ENTITY_REMAPPER.remap(entity_id1));
             assert_eq!(entity_id4, ENTITY_REMAPPER.remap(entity_id4));
         }
+        let _lock = ENTITY_REMAPPER.lock();
         assert_eq!(entity_id1, ENTITY_REMAPPER.remap(entity_id1));
     }
 
@@ -263,6 +307,26 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_concurrent_loading() {
+        let handles = (0..4)
+            .map(|first_id| {
+                std::thread::spawn(move || {
+                    let entity_manager = EntityManager::new(first_id * 10 + 1);
+                    let entity_ids = [1, 2].iter().copied().collect();
+                    let mut context = EntityRemapContext::default();
+                    entity_manager.load_data(EntityManagerData { entity_ids }, &mut context);
+                    std::thread::sleep(Duration::from_millis(50));
+                    assert_eq!(context.remap(1), first_id * 10 + 1);
+                    assert_eq!(context.remap(2), first_id * 10 + 2);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
     #[test]
     fn test_sync() {
         let h0 = std::thread::spawn(|| {
@@ -274,29 +338,13 @@ mod test {
             entity_ids.insert(1);
             entity_ids.insert(2);
 
-            let _token = entity_manager.load_data(EntityManagerData { entity_ids });
+            let mut context = EntityRemapContext::default();
+            entity_manager.load_data(EntityManagerData { entity_ids }, &mut context);
+            let _token = context.install();
             std::thread::sleep(Duration::from_millis(500));
 
-            assert_eq!(
-                ENTITY_REMAPPER
-                    .mapping
-                    .lock()
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .get(&1),
-                Some(&3)
-            );
-            assert_eq!(
-                ENTITY_REMAPPER
-                    .mapping
-                    .lock()
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .get(&2),
-                Some(&4)
-            );
+            assert_eq!(ENTITY_REMAPPER.remap(1), 3);
+            assert_eq!(ENTITY_REMAPPER.remap(2), 4);
         });
         let h1 = std::thread::spawn(|| {
             let _lock = ENTITY_REMAPPER.lock();
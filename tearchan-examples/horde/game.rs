@@ -10,11 +10,8 @@ use rapier2d::geometry::{
 use rapier2d::na::Vector2;
 use rapier2d::pipeline::{ActiveEvents, ChannelEventCollector, CollisionPipeline};
 use rapier2d::prelude::ColliderHandle;
-use serde::de::{Error, MapAccess, Visitor};
-use serde::Deserializer;
 use serde_json::value::RawValue;
 use std::collections::HashMap;
-use std::fmt::Formatter;
 use std::path::PathBuf;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, RwLock};
@@ -25,17 +22,17 @@ use tearchan_ecs::component::group::ComponentGroupSerializableData;
 use tearchan_ecs::component::group_sync::ComponentGroupSync;
 use tearchan_ecs::component::resource_sync::ResourceSync;
 use tearchan_ecs::component::EntityId;
-use tearchan_ecs::entity::manager::{EntityManager, EntityManagerData, ENTITY_REMAPPER};
+use tearchan_ecs::entity::manager::{EntityManager, EntityManagerData};
 use tearchan_horde::action::manager::TimeMilliseconds;
 use tearchan_horde::v2::action::collection::{
     TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId,
 };
-use tearchan_horde::v2::action::manager::{ActionController, EnqueueOptions, ACTION_REMAPPER};
-use tearchan_horde::v2::action::{ActionType, ArcAction};
+use tearchan_horde::v2::action::manager::{ActionController, EnqueueOptions, RemapContext};
+use tearchan_horde::v2::action::{ActionType, ArcAction, CancelReason};
 use tearchan_horde::v2::job::manager::{JobController, JobManager, JobManagerData};
 use tearchan_horde::v2::job::HordeInterface;
 use tearchan_horde::v2::serde::{Deserialize, Serialize};
-use tearchan_horde::v2::{calc_ratio_f32_from_ms, calc_ratio_f32_from_tick, define_actions, Tick};
+use tearchan_horde::v2::{calc_ratio_f32_from_ms, calc_ratio_f32_from_tick, HordeActions, Tick};
 
 const PLAYER_SPEED: f32 = 500.0f32; // ms/cell
 const SAVE_FILE_NAME: &str = "world.json";
@@ -107,13 +104,13 @@ impl From<TVec2<i32>> for DirectionState {
     }
 }
 
-define_actions!(
-    HordeAction,
-    (Walk, WalkState),
-    (Wait, WaitState),
-    (ChangeColor, ChangeColorState),
-    (Direction, DirectionState)
-);
+#[derive(Clone, Debug, Serialize, Deserialize, HordeActions)]
+pub enum HordeAction {
+    Walk(Arc<WalkState>),
+    Wait(Arc<WaitState>),
+    ChangeColor(Arc<ChangeColorState>),
+    Direction(Arc<DirectionState>),
+}
 
 #[derive(Serialize, Deserialize)]
 pub enum HordeJob {
@@ -129,44 +126,12 @@ struct PositionData {
     to: (Vec2, TickData),
 }
 
-#[derive(Serialize)]
+// The tick of the saved world, which is remapped after loading
+#[derive(Serialize, Deserialize)]
 struct TickData {
     value: Tick,
 }
 
-impl<'de> Deserialize<'de> for TickData {
-    fn deserialize<D>(deserializer: D) -> Result<Self, <D as Deserializer<'de>>::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        struct TickDataVisitor;
-
-        impl<'de> Visitor<'de> for TickDataVisitor {
-            type Value = TickData;
-
-            fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-                write!(formatter, "TickData")
-            }
-
-            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, <A as MapAccess<'de>>::Error>
-            where
-                A: MapAccess<'de>,
-            {
-                if let Some((key, value)) = map.next_entry::<String, Tick>()? {
-                    if key == "value" {
-                        return Ok(TickData {
-                            value: ACTION_REMAPPER.remap(value),
-                        });
-                    }
-                }
-                Err(<A as MapAccess<'de>>::Error::custom("not value property"))
-            }
-        }
-
-        deserializer.deserialize_map(TickDataVisitor)
-    }
-}
-
 #[derive(Serialize, Deserialize)]
 struct ScaledPositionData(TVec2<i32>);
 
@@ -274,7 +239,12 @@ impl HordeInterface for Game {
         self.run_action(Mapper { map, time });
     }
 
-    fn on_cancel_job(&mut self, _entity_id: EntityId, mut jobs: Vec<Self::Job>) {
+    fn on_cancel_job(
+        &mut self,
+        _entity_id: EntityId,
+        mut jobs: Vec<Self::Job>,
+        _reason: CancelReason,
+    ) {
         while let Some(job) = jobs.pop() {
             match job.as_ref() {
                 HordeJob::Wander => {}
@@ -1135,11 +1105,7 @@ impl Game {
     }
 
     pub fn save_world(&self, job_manager: &JobManager<Game>) {
-        let job_manager_data = job_manager.to_data(
-            convert_actions_from_typed_action_any_map,
-            convert_actions_from_typed_any_action_map,
-            convert_actions_from_any_action_vec,
-        );
+        let job_manager_data = job_manager.to_data();
         let json = serde_json::to_string(&GameSerializableData {
             entity_manager_data: self.entity_manager.to_data(),
             payload: GameSerializablePayload {
@@ -1186,40 +1152,48 @@ impl Game {
         match result {
             Ok(result) => {
                 let data: GameDeserializableData = serde_json::from_str(&result).unwrap();
-                let _entity_token = self.entity_manager.load_data(data.entity_manager_data);
+                let mut context = RemapContext::default();
+                self.entity_manager
+                    .load_data(data.entity_manager_data, context.entities_mut());
 
                 let payload: GameDeserializablePayload =
                     serde_json::from_str(data.payload.get()).unwrap();
-                self.player_id = ENTITY_REMAPPER.remap(payload.player_id);
+                self.player_id = context.remap_entity(payload.player_id);
                 self.speed = payload.speed;
 
-                let _tick_token =
-                    job_manager.load_data(payload.job_manager_data, convert_from_actions);
-
-                self.positions
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.positions.get()).unwrap());
-                self.scaled_positions
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.scaled_positions.get()).unwrap());
-                self.colors
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.colors.get()).unwrap());
-                self.paths
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.paths.get()).unwrap());
-                self.entity_types
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.entity_types.get()).unwrap());
-                self.directions
-                    .write()
-                    .get_mut()
-                    .load_data(serde_json::from_str(payload.directions.get()).unwrap());
+                job_manager
+                    .load_data(payload.job_manager_data, &mut context)
+                    .unwrap();
+
+                self.positions.write().get_mut().load_data(
+                    serde_json::from_str(payload.positions.get()).unwrap(),
+                    context.entities(),
+                );
+                // All entities were destroyed above, so every position is from the saved world
+                for (_, position) in self.positions.write().get_mut().iter_mut() {
+                    position.from.1.value = context.remap_tick(position.from.1.value);
+                    position.to.1.value = context.remap_tick(position.to.1.value);
+                }
+                self.scaled_positions.write().get_mut().load_data(
+                    serde_json::from_str(payload.scaled_positions.get()).unwrap(),
+                    context.entities(),
+                );
+                self.colors.write().get_mut().load_data(
+                    serde_json::from_str(payload.colors.get()).unwrap(),
+                    context.entities(),
+                );
+                self.paths.write().get_mut().load_data(
+                    serde_json::from_str(payload.paths.get()).unwrap(),
+                    context.entities(),
+                );
+                self.entity_types.write().get_mut().load_data(
+                    serde_json::from_str(payload.entity_types.get()).unwrap(),
+                    context.entities(),
+                );
+                self.directions.write().get_mut().load_data(
+                    serde_json::from_str(payload.directions.get()).unwrap(),
+                    context.entities(),
+                );
             }
             Err(err) => println!("{:?}", err),
         }
@@ -0,0 +1,208 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, PathArguments, Type,
+};
+
+// Implements tearchan_horde::v2::HordeActions for the enum whose variants hold the states as
+// Arc<State>, e.g. `enum MyAction { Move(Arc<MoveState>), Jump(Arc<JumpState>) }`
+#[proc_macro_derive(HordeActions)]
+pub fn derive_horde_actions(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "HordeActions cannot be derived for generic enums",
+        ));
+    }
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new(
+                Span::call_site(),
+                "HordeActions can only be derived for enums",
+            ))
+        }
+    };
+    if data.variants.is_empty() {
+        return Err(Error::new(
+            name.span(),
+            "HordeActions requires at least one variant",
+        ));
+    }
+
+    let mut variants = Vec::new();
+    let mut states: Vec<&Type> = Vec::new();
+    for variant in data.variants.iter() {
+        let field = match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0],
+            _ => {
+                return Err(Error::new(
+                    variant.span(),
+                    "HordeActions variants must have exactly one field of Arc<State>",
+                ))
+            }
+        };
+        let state = arc_inner_type(&field.ty).ok_or_else(|| {
+            Error::new(
+                field.ty.span(),
+                "HordeActions variants must have exactly one field of Arc<State>",
+            )
+        })?;
+        let key = quote!(#state).to_string();
+        if states.iter().any(|other| quote!(#other).to_string() == key) {
+            return Err(Error::new(
+                field.ty.span(),
+                "HordeActions states must be unique across the variants",
+            ));
+        }
+        variants.push(&variant.ident);
+        states.push(state);
+    }
+
+    let horde = quote!(tearchan_horde::v2);
+    let assertions = states.iter().map(|state| {
+        quote_spanned! {state.span()=>
+            assert_state::<#state>();
+        }
+    });
+    Ok(quote! {
+        const _: () = {
+            fn assert_state<T>()
+            where
+                T: #horde::serde::Serialize + #horde::serde::de::DeserializeOwned + 'static,
+            {
+            }
+            #[allow(dead_code)]
+            fn assert_states() {
+                #(#assertions)*
+            }
+        };
+
+        impl #horde::HordeActions for #name {
+            fn convert_actions_from_any_action_vec(
+                type_id: &::std::any::TypeId,
+                vec: &#horde::action::collection::AnyActionVec,
+                validator: &#horde::action::manager::ActionSessionValidator,
+            ) -> ::std::vec::Vec<#horde::action::Action<Self>> {
+                #(
+                if *type_id == ::std::any::TypeId::of::<#states>() {
+                    return vec
+                        .cast::<#states>(validator)
+                        .iter()
+                        .map(|action| action.replace(#name::#variants(action.raw().clone())))
+                        .collect();
+                }
+                )*
+                ::std::vec::Vec::new()
+            }
+
+            fn convert_actions_from_typed_any_action_map(
+                map: &#horde::action::collection::TypedAnyActionMapGroupedByEntityId,
+            ) -> ::std::vec::Vec<#horde::action::Action<Self>> {
+                let mut actions = ::std::vec::Vec::new();
+                for (type_id, vec) in map.iter() {
+                    #(
+                    if *type_id == ::std::any::TypeId::of::<#states>() {
+                        actions.extend(
+                            vec.cast::<#horde::action::ArcAction<#states>>()
+                                .iter()
+                                .map(|action| action.replace(#name::#variants(action.raw().clone()))),
+                        );
+                    }
+                    )*
+                }
+                actions
+            }
+
+            fn convert_and_enqueue_action(
+                entity_id: #horde::EntityId,
+                layer: #horde::action::ActionLayer,
+                action: Self,
+                duration: tearchan_horde::action::manager::TimeMilliseconds,
+                controller: &mut #horde::action::manager::ActionController,
+            ) {
+                match action {
+                    #(
+                    #name::#variants(state) => {
+                        controller.enqueue_to_layer(entity_id, layer, state, duration, ::std::default::Default::default());
+                    }
+                    )*
+                }
+            }
+
+            fn convert_and_enqueue_joint_action(
+                participants: &[(#horde::EntityId, #horde::action::ActionLayer)],
+                action: Self,
+                duration: tearchan_horde::action::manager::TimeMilliseconds,
+                options: #horde::action::manager::EnqueueOptions,
+                controller: &mut #horde::action::manager::ActionController,
+            ) {
+                match action {
+                    #(
+                    #name::#variants(state) => {
+                        controller.enqueue_joint_to_layers(participants, state, duration, options);
+                    }
+                    )*
+                }
+            }
+
+            fn convert_and_schedule_timer(
+                action: Self,
+                delay: tearchan_horde::action::manager::TimeMilliseconds,
+                options: #horde::action::timer::TimerOptions,
+                controller: &mut #horde::action::manager::ActionController,
+            ) -> #horde::action::timer::TimerId {
+                match action {
+                    #(
+                    #name::#variants(state) => {
+                        controller.schedule_timer_with_options(state, delay, options)
+                    }
+                    )*
+                }
+            }
+
+            fn convert_from_actions(
+                action: #horde::action::Action<Self>,
+                converter: &mut #horde::action::manager::ActionManagerConverter,
+            ) {
+                match action.raw() {
+                    #(
+                    #name::#variants(state) => {
+                        converter.load(action.replace(::std::sync::Arc::clone(state)));
+                    }
+                    )*
+                }
+            }
+        }
+    })
+}
+
+fn arc_inner_type(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != "Arc" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if arguments.args.len() == 1 => {
+            match &arguments.args[0] {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
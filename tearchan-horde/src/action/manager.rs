@@ -903,6 +903,14 @@ impl<T> ActionManagerData<T> {
     pub fn entity_ids(&self) -> &BTreeSet<EntityId> {
         &self.entity_ids
     }
+
+    pub fn actions(&self) -> &[Arc<Action<T>>] {
+        &self.actions
+    }
+
+    pub fn current_time(&self) -> TimeMilliseconds {
+        self.current_time
+    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,8 @@
 #![feature(map_first_last)]
 
+// Lets the code generated by the derive macros refer to this crate by name inside of it
+extern crate self as tearchan_horde;
+
 use crate::action::manager::{ActionController, ActionServerReader};
 use crate::action::Action;
 use crate::job::result::{JobResult, ProgressState};
@@ -1,5 +1,6 @@
 use crate::v2::action::manager::ActionSessionValidator;
-use crate::v2::action::{ActionSessionId, ArcAction};
+use crate::v2::action::timer::Timer;
+use crate::v2::action::{ActionLayer, ActionSessionId, ArcAction};
 use crate::v2::Tick;
 use std::any::{Any, TypeId};
 use std::collections::hash_map::Iter;
@@ -7,13 +8,18 @@ use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use tearchan_ecs::component::EntityId;
 
+// The type-erased action with the type and its key
+type RawAction<K> = (TypeId, K, Arc<Box<dyn Any>>);
+
+#[derive(Clone)]
 pub struct ActionMeta {
     pub entity_id: EntityId,
+    pub layer: ActionLayer,
     pub session_id: ActionSessionId,
     pub tick: Tick,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct AnyVec {
     vec: Vec<Arc<Box<dyn Any>>>,
 }
@@ -69,11 +75,11 @@ impl AnyVec {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TypedAnyActionMapGroupedByEntityId {
     map: HashMap<TypeId, AnyVec>,
-    indices: HashMap<EntityId, (TypeId, usize)>,
-    entities: HashMap<(TypeId, usize), EntityId>,
+    indices: BTreeMap<(EntityId, ActionLayer), (TypeId, usize)>,
+    entities: HashMap<(TypeId, usize), (EntityId, ActionLayer)>,
 }
 
 impl TypedAnyActionMapGroupedByEntityId {
@@ -96,13 +102,14 @@ impl TypedAnyActionMapGroupedByEntityId {
         T: 'static,
     {
         let type_id = TypeId::of::<T>();
+        let key = (entity_id, action.layer());
 
-        self.remove(entity_id);
+        self.remove_layer(key.0, key.1);
         let vec = self.map.entry(type_id).or_insert_with(AnyVec::default);
         let index = vec.len();
         vec.push(action);
-        self.entities.insert((type_id, index), entity_id);
-        self.indices.insert(entity_id, (type_id, index));
+        self.entities.insert((type_id, index), key);
+        self.indices.insert(key, (type_id, index));
     }
 
     pub fn insert_as_raw(
@@ -110,30 +117,56 @@ impl TypedAnyActionMapGroupedByEntityId {
         action: Arc<Box<dyn Any>>,
         type_id: TypeId,
         entity_id: EntityId,
+        layer: ActionLayer,
     ) {
-        self.remove(entity_id);
+        self.remove_layer(entity_id, layer);
 
         let collection = self.map.entry(type_id).or_insert_with(AnyVec::default);
         let index = collection.len();
         collection.push_as_raw(action);
-        self.entities.insert((type_id, index), entity_id);
-        self.indices.insert(entity_id, (type_id, index));
+        self.entities.insert((type_id, index), (entity_id, layer));
+        self.indices.insert((entity_id, layer), (type_id, index));
     }
 
+    // Removes the actions of all layers
     pub fn remove(&mut self, entity_id: EntityId) {
-        if let Some((type_id, index)) = self.indices.remove(&entity_id) {
+        let layers = self
+            .indices
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .map(|((_, layer), _)| *layer)
+            .collect::<Vec<_>>();
+        for layer in layers {
+            self.remove_layer(entity_id, layer);
+        }
+    }
+
+    // Removes the actions of all layers and returns them
+    pub fn take(&mut self, entity_id: EntityId) -> Vec<RawAction<ActionLayer>> {
+        let actions = self
+            .indices
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .map(|((_, layer), (type_id, index))| {
+                (*type_id, *layer, Arc::clone(&self.map[type_id].vec[*index]))
+            })
+            .collect();
+        self.remove(entity_id);
+        actions
+    }
+
+    pub fn remove_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        if let Some((type_id, index)) = self.indices.remove(&(entity_id, layer)) {
             self.entities.remove(&(type_id, index));
 
             let collection = self.map.get_mut(&type_id).unwrap();
             let last = collection.len() - 1;
             let last_item = collection.pop().unwrap();
             if index != last {
-                let last_entity_id = self.entities.remove(&(type_id, last)).unwrap();
-                self.indices.remove(&last_entity_id);
+                let last_key = self.entities.remove(&(type_id, last)).unwrap();
+                self.indices.remove(&last_key);
 
                 collection.replace(index, last_item);
-                self.entities.insert((type_id, index), last_entity_id);
-                self.indices.insert(last_entity_id, (type_id, index));
+                self.entities.insert((type_id, index), last_key);
+                self.indices.insert(last_key, (type_id, index));
             } else if index == 0 {
                 self.map.remove(&type_id);
             }
@@ -145,7 +178,7 @@ impl TypedAnyActionMapGroupedByEntityId {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct AnyActionVec {
     vec: Vec<(ActionMeta, Arc<Box<dyn Any>>)>,
 }
@@ -187,6 +220,7 @@ impl AnyActionVec {
         self.vec.push((
             ActionMeta {
                 entity_id: action.entity_id,
+                layer: action.layer,
                 session_id,
                 tick,
             },
@@ -204,6 +238,10 @@ impl AnyActionVec {
         self.vec.len()
     }
 
+    pub fn iter(&self) -> std::slice::Iter<'_, (ActionMeta, Arc<Box<dyn Any>>)> {
+        self.vec.iter()
+    }
+
     #[inline]
     fn replace(&mut self, index: usize, item: (ActionMeta, Arc<Box<dyn Any>>)) {
         if let Some(x) = self.vec.get_mut(index) {
@@ -220,12 +258,14 @@ impl AnyActionVec {
         &mut self,
         action: Arc<Box<dyn Any>>,
         entity_id: EntityId,
+        layer: ActionLayer,
         session_id: ActionSessionId,
         tick: Tick,
     ) {
         self.vec.push((
             ActionMeta {
                 entity_id,
+                layer,
                 session_id,
                 tick,
             },
@@ -234,7 +274,7 @@ impl AnyActionVec {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TypedAnyActionMap {
     map: HashMap<TypeId, AnyActionVec>,
 }
@@ -257,13 +297,14 @@ impl TypedAnyActionMap {
         type_id: TypeId,
         action: Arc<Box<dyn Any>>,
         entity_id: EntityId,
+        layer: ActionLayer,
         session_id: ActionSessionId,
         tick: Tick,
     ) {
         self.map
             .entry(type_id)
             .or_insert_with(AnyActionVec::default)
-            .push_as_raw(action, entity_id, session_id, tick);
+            .push_as_raw(action, entity_id, layer, session_id, tick);
     }
 
     pub fn get<T>(&self, validator: &ActionSessionValidator) -> Option<Vec<&ArcAction<T>>>
@@ -288,16 +329,65 @@ impl TypedAnyActionMap {
         Some(vec)
     }
 
+    // Iterates the actions of the type with their metas without validating and collecting them
+    pub fn iter_typed<T>(&self) -> impl Iterator<Item = (&ActionMeta, &ArcAction<T>)>
+    where
+        T: 'static,
+    {
+        self.map
+            .get(&TypeId::of::<T>())
+            .into_iter()
+            .flat_map(|vec| vec.vec.iter())
+            .filter_map(|(meta, action)| Some((meta, action.downcast_ref()?)))
+    }
+
+    // Moves the actions of the entity to the new map
+    pub fn take(&mut self, entity_id: EntityId) -> TypedAnyActionMap {
+        let mut taken = TypedAnyActionMap::default();
+        for (type_id, vec) in self.map.iter_mut() {
+            let (actions, others): (Vec<_>, Vec<_>) = std::mem::take(&mut vec.vec)
+                .into_iter()
+                .partition(|(meta, _)| meta.entity_id == entity_id);
+            vec.vec = others;
+            if !actions.is_empty() {
+                taken.map.insert(*type_id, AnyActionVec { vec: actions });
+            }
+        }
+        self.map.retain(|_, vec| !vec.is_empty());
+        taken
+    }
+
+    // Moves the actions of the layer to the new map
+    pub fn take_layer(&mut self, entity_id: EntityId, layer: ActionLayer) -> TypedAnyActionMap {
+        let mut taken = TypedAnyActionMap::default();
+        for (type_id, vec) in self.map.iter_mut() {
+            let (actions, others): (Vec<_>, Vec<_>) = std::mem::take(&mut vec.vec)
+                .into_iter()
+                .partition(|(meta, _)| meta.entity_id == entity_id && meta.layer == layer);
+            vec.vec = others;
+            if !actions.is_empty() {
+                taken.map.insert(*type_id, AnyActionVec { vec: actions });
+            }
+        }
+        self.map.retain(|_, vec| !vec.is_empty());
+        taken
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.values().all(AnyActionVec::is_empty)
+    }
+
     pub fn iter(&self) -> Iter<'_, TypeId, AnyActionVec> {
         self.map.iter()
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct TypedCloneableAnyActionMapGroupedByEntityId {
     map: HashMap<TypeId, AnyActionVec>,
-    indices: BTreeMap<EntityId, (TypeId, usize)>,
-    entities: HashMap<(TypeId, usize), EntityId>,
+    indices: BTreeMap<(EntityId, ActionLayer), (TypeId, usize)>,
+    entities: HashMap<(TypeId, usize), (EntityId, ActionLayer)>,
 }
 
 impl TypedCloneableAnyActionMapGroupedByEntityId {
@@ -306,17 +396,17 @@ impl TypedCloneableAnyActionMapGroupedByEntityId {
         T: 'static,
     {
         let type_id = TypeId::of::<T>();
-        let entity_id = action.entity_id();
+        let key = (action.entity_id(), action.layer());
 
-        self.remove(entity_id);
+        self.remove_layer(key.0, key.1);
         let vec = self
             .map
             .entry(type_id)
             .or_insert_with(AnyActionVec::default);
         let index = vec.len();
         vec.push(action, session_id, tick);
-        self.entities.insert((type_id, index), entity_id);
-        self.indices.insert(entity_id, (type_id, index));
+        self.entities.insert((type_id, index), key);
+        self.indices.insert(key, (type_id, index));
     }
 
     pub fn insert_as_raw(
@@ -324,35 +414,62 @@ impl TypedCloneableAnyActionMapGroupedByEntityId {
         action: Arc<Box<dyn Any>>,
         type_id: TypeId,
         entity_id: EntityId,
+        layer: ActionLayer,
         session_id: ActionSessionId,
         tick: Tick,
     ) {
-        self.remove(entity_id);
+        self.remove_layer(entity_id, layer);
 
         let collection = self
             .map
             .entry(type_id)
             .or_insert_with(AnyActionVec::default);
         let index = collection.len();
-        collection.push_as_raw(action, entity_id, session_id, tick);
-        self.entities.insert((type_id, index), entity_id);
-        self.indices.insert(entity_id, (type_id, index));
+        collection.push_as_raw(action, entity_id, layer, session_id, tick);
+        self.entities.insert((type_id, index), (entity_id, layer));
+        self.indices.insert((entity_id, layer), (type_id, index));
     }
 
+    // Removes the actions of all layers
     pub fn remove(&mut self, entity_id: EntityId) {
-        if let Some((type_id, index)) = self.indices.remove(&entity_id) {
+        let layers = self
+            .indices
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .map(|((_, layer), _)| *layer)
+            .collect::<Vec<_>>();
+        for layer in layers {
+            self.remove_layer(entity_id, layer);
+        }
+    }
+
+    // Removes the actions of all layers and returns them
+    pub fn take(&mut self, entity_id: EntityId) -> Vec<RawAction<ActionMeta>> {
+        let actions = self
+            .indices
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .map(|(_key, (type_id, index))| {
+                let (meta, action) = &self.map[type_id].vec[*index];
+                (*type_id, meta.clone(), Arc::clone(action))
+            })
+            .collect();
+        self.remove(entity_id);
+        actions
+    }
+
+    pub fn remove_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        if let Some((type_id, index)) = self.indices.remove(&(entity_id, layer)) {
             self.entities.remove(&(type_id, index));
 
             let collection = self.map.get_mut(&type_id).unwrap();
             let last = collection.len() - 1;
             let last_item = collection.pop().unwrap();
             if index != last {
-                let last_entity_id = self.entities.remove(&(type_id, last)).unwrap();
-                self.indices.remove(&last_entity_id);
+                let last_key = self.entities.remove(&(type_id, last)).unwrap();
+                self.indices.remove(&last_key);
 
                 collection.replace(index, last_item);
-                self.entities.insert((type_id, index), last_entity_id);
-                self.indices.insert(last_entity_id, (type_id, index));
+                self.entities.insert((type_id, index), last_key);
+                self.indices.insert(last_key, (type_id, index));
             } else if index == 0 {
                 self.map.remove(&type_id);
             }
@@ -360,7 +477,7 @@ impl TypedCloneableAnyActionMapGroupedByEntityId {
     }
 
     pub fn push_actions_to(&self, map: &mut TypedAnyActionMap, current_tick: Tick) {
-        for (_entity_id, (type_id, index)) in self.indices.iter() {
+        for (_key, (type_id, index)) in self.indices.iter() {
             let vec = match self.map.get(type_id) {
                 None => continue,
                 Some(vec) => vec,
@@ -371,6 +488,7 @@ impl TypedCloneableAnyActionMapGroupedByEntityId {
                     *type_id,
                     Arc::clone(action),
                     meta.entity_id,
+                    meta.layer,
                     meta.session_id,
                     meta.tick,
                 );
@@ -386,6 +504,42 @@ impl TypedCloneableAnyActionMapGroupedByEntityId {
     pub fn iter(&self) -> Iter<'_, TypeId, AnyActionVec> {
         self.map.iter()
     }
+
+    pub fn iter_meta(&self) -> impl Iterator<Item = (&TypeId, &ActionMeta)> {
+        self.indices
+            .iter()
+            .filter_map(move |(_key, (type_id, index))| {
+                let (meta, _action) = self.map.get(type_id)?.vec.get(*index)?;
+                Some((type_id, meta))
+            })
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TypedAnyTimerMap {
+    map: HashMap<TypeId, AnyVec>,
+}
+
+impl TypedAnyTimerMap {
+    pub fn get<T>(&self) -> Option<Vec<&Timer<Arc<T>>>>
+    where
+        T: 'static,
+    {
+        Some(self.map.get(&TypeId::of::<T>())?.cast())
+    }
+
+    pub fn push_as_raw(&mut self, type_id: TypeId, timer: Arc<Box<dyn Any>>) {
+        self.map.entry(type_id).or_default().push_as_raw(timer);
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, TypeId, AnyVec> {
+        self.map.iter()
+    }
 }
 
 #[cfg(test)]
@@ -409,6 +563,7 @@ mod test {
             Action {
                 raw: Arc::new(MoveState),
                 entity_id: 1,
+                layer: 0,
                 ty: ActionType::Start {
                     start: 0,
                     end: 0,
@@ -422,6 +577,7 @@ mod test {
             Action {
                 raw: Arc::new(JumpState),
                 entity_id: 2,
+                layer: 0,
                 ty: ActionType::Start {
                     start: 0,
                     end: 0,
@@ -434,6 +590,7 @@ mod test {
             Action {
                 raw: Arc::new(JumpState),
                 entity_id: 2,
+                layer: 0,
                 ty: ActionType::Start {
                     start: 0,
                     end: 0,
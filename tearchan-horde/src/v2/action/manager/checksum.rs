@@ -0,0 +1,132 @@
+use crate::v2::action::manager::{ActionManager, Event};
+use crate::v2::checksum::{StateChecksum, StateHasher};
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use tearchan_ecs::component::EntityId;
+
+impl ActionManager {
+    pub fn checksum(&self) -> StateChecksum {
+        self.checksum_with(|_, _| {})
+    }
+
+    // Session ids are not hashed because they are allocated locally (e.g. renewed by from_data),
+    // so only the events belonging to the running sessions are folded into the checksum
+    pub fn checksum_with<F>(&self, mut f: F) -> StateChecksum
+    where
+        F: FnMut(EntityId, &mut StateHasher),
+    {
+        let mut hashers = self
+            .contexts
+            .iter()
+            .map(|((entity_id, _layer), _context)| (*entity_id, StateHasher::default()))
+            .collect::<BTreeMap<_, _>>();
+        for ((entity_id, layer), context) in self.contexts.iter() {
+            let hasher = hashers.get_mut(entity_id).unwrap();
+            layer.hash(hasher);
+            context.last_tick.hash(hasher);
+            context.running_end_tick.hash(hasher);
+        }
+
+        let frozen_actions = self
+            .freezes
+            .values()
+            .flat_map(|frozen| frozen.actions.iter());
+        for (tick, item) in self.actions.iter().chain(frozen_actions) {
+            for ((entity_id, layer), (session_id, event)) in item.events.iter() {
+                let hasher = match hashers.get_mut(entity_id) {
+                    None => continue,
+                    Some(hasher) => hasher,
+                };
+                match self.contexts.get(&(*entity_id, *layer)) {
+                    Some(context) if context.session_id == *session_id => {}
+                    _ => continue,
+                }
+                tick.hash(hasher);
+                layer.hash(hasher);
+                match event {
+                    Event::Started {
+                        type_name,
+                        each_action,
+                        running_end_tick,
+                        ..
+                    } => {
+                        0u8.hash(hasher);
+                        type_name.hash(hasher);
+                        each_action.is_some().hash(hasher);
+                        running_end_tick.hash(hasher);
+                    }
+                    Event::Ended => 1u8.hash(hasher),
+                    Event::Canceled => 2u8.hash(hasher),
+                }
+            }
+        }
+
+        let frozen_each_tick_actions = self
+            .freezes
+            .values()
+            .flat_map(|frozen| frozen.each_tick_actions.iter_meta());
+        for (type_id, meta) in self
+            .each_tick_actions
+            .iter_meta()
+            .chain(frozen_each_tick_actions)
+        {
+            if let Some(hasher) = hashers.get_mut(&meta.entity_id) {
+                3u8.hash(hasher);
+                meta.layer.hash(hasher);
+                self.type_name_of(type_id).hash(hasher);
+                meta.tick.hash(hasher);
+            }
+        }
+
+        for joint in self.joints.iter() {
+            for ((entity_id, layer), session_id) in joint.participants.iter() {
+                let hasher = match hashers.get_mut(entity_id) {
+                    None => continue,
+                    Some(hasher) => hasher,
+                };
+                match self.contexts.get(&(*entity_id, *layer)) {
+                    Some(context) if context.session_id == *session_id => {}
+                    _ => continue,
+                }
+                4u8.hash(hasher);
+                layer.hash(hasher);
+                joint.start_tick.hash(hasher);
+                joint.end_tick.hash(hasher);
+                joint.participants.len().hash(hasher);
+            }
+        }
+
+        for (entity_id, frozen) in self.freezes.iter() {
+            if let Some(hasher) = hashers.get_mut(entity_id) {
+                5u8.hash(hasher);
+                frozen.tick.hash(hasher);
+            }
+        }
+
+        // The timers don't belong to any entity, so they are folded into the separate hash
+        let mut timers = StateHasher::default();
+        for (timer_id, timer) in self.timers.iter() {
+            timer_id.hash(&mut timers);
+            timer.type_name.hash(&mut timers);
+            timer.tick.hash(&mut timers);
+            timer.interval.hash(&mut timers);
+        }
+
+        let entities = hashers
+            .into_iter()
+            .map(|(entity_id, mut hasher)| {
+                f(entity_id, &mut hasher);
+                (entity_id, hasher.finish())
+            })
+            .collect();
+        StateChecksum::new(self.current_tick, timers.finish(), entities)
+    }
+
+    fn type_name_of(&self, type_id: &TypeId) -> &'static str {
+        self.retimers
+            .get(type_id)
+            .expect("The retimer is registered on scheduling")
+            .type_name
+    }
+}
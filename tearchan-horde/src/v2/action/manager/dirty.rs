@@ -0,0 +1,138 @@
+use crate::v2::action::manager::{ActionController, ActionManager};
+use crate::v2::action::ActionLayer;
+use std::collections::BTreeSet;
+use tearchan_ecs::component::EntityId;
+
+// The layers whose saved data have been changed, and the entities changed by them or by the
+// changes of the whole entity such as freezing
+#[derive(Default, Clone)]
+pub(super) struct DirtyLayers {
+    layers: BTreeSet<(EntityId, ActionLayer)>,
+    entities: BTreeSet<EntityId>,
+}
+
+impl DirtyLayers {
+    pub(super) fn insert(&mut self, key: (EntityId, ActionLayer)) {
+        self.layers.insert(key);
+        self.entities.insert(key.0);
+    }
+
+    pub(super) fn insert_entity(&mut self, entity_id: EntityId) {
+        self.entities.insert(entity_id);
+    }
+}
+
+impl ActionManager {
+    // Records the changed entities and layers for the delta snapshots until disabled
+    pub fn enable_dirty_tracking(&mut self) {
+        if self.dirty_entities.is_none() {
+            self.dirty_entities = Some(DirtyLayers::default());
+        }
+    }
+
+    pub fn disable_dirty_tracking(&mut self) {
+        self.dirty_entities = None;
+    }
+
+    #[inline]
+    pub fn is_dirty_tracking(&self) -> bool {
+        self.dirty_entities.is_some()
+    }
+
+    // Returns the entities changed since the last pull, whose saved data may differ
+    pub fn pull_dirty_entities(&mut self) -> BTreeSet<EntityId> {
+        self.dirty_entities
+            .as_mut()
+            .map(|dirty| std::mem::take(dirty).entities)
+            .unwrap_or_default()
+    }
+
+    pub fn pull_dirty_layers(&mut self) -> BTreeSet<(EntityId, ActionLayer)> {
+        self.dirty_entities
+            .as_mut()
+            .map(|dirty| std::mem::take(dirty).layers)
+            .unwrap_or_default()
+    }
+
+    // Every attached and frozen entity, used when the whole state is replaced
+    pub(super) fn mark_all_dirty(&mut self) {
+        if let Some(dirty) = &mut self.dirty_entities {
+            for key in self.contexts.keys() {
+                dirty.insert(*key);
+            }
+            for entity_id in self.freezes.keys() {
+                dirty.insert_entity(*entity_id);
+            }
+        }
+    }
+}
+
+impl<'a> ActionController<'a> {
+    pub(super) fn mark_dirty(&mut self, key: (EntityId, ActionLayer)) {
+        if let Some(dirty) = self.dirty_entities {
+            dirty.insert(key);
+        }
+    }
+
+    pub(super) fn mark_dirty_entity(&mut self, entity_id: EntityId) {
+        if let Some(dirty) = self.dirty_entities {
+            dirty.insert_entity(entity_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{JumpState, MoveState};
+    use crate::v2::action::manager::ActionManager;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_dirty_tracking() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+        assert!(manager.pull_dirty_entities().is_empty());
+
+        manager.enable_dirty_tracking();
+        manager.enqueue(1, Arc::new(MoveState), 300); // tick: 0-3
+        manager.enqueue(2, Arc::new(JumpState), 1000); // tick: 0-10
+        manager.attach_layer(2, 1);
+        manager.pull_vacated_entities();
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(
+            manager.pull_dirty_layers().into_iter().collect::<Vec<_>>(),
+            vec![(1, 0), (2, 0), (2, 1)]
+        );
+
+        // Only the entity whose actions are pulled is changed
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        assert!(manager.pull_dirty_entities().is_empty());
+        manager.update(300);
+        while manager.pull_actions().is_some() {}
+        manager.pull_vacated_entities();
+        assert_eq!(
+            manager
+                .pull_dirty_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        manager.freeze(2);
+        assert_eq!(
+            manager
+                .pull_dirty_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        manager.disable_dirty_tracking();
+        manager.unfreeze(2);
+        assert!(manager.pull_dirty_entities().is_empty());
+    }
+}
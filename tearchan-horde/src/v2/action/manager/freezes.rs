@@ -0,0 +1,395 @@
+use crate::v2::action::manager::{
+    ActionController, ActionManager, BundleForeachTick, Event, FreezeData, FrozenEntity,
+    ReplicationRecord, Tick,
+};
+use crate::v2::action::ActionLayer;
+use std::any::{Any, TypeId};
+use std::sync::Arc;
+use tearchan_ecs::component::EntityId;
+
+impl ActionManager {
+    pub fn freeze(&mut self, entity_id: EntityId) {
+        self.controller().freeze(entity_id);
+    }
+
+    pub fn unfreeze(&mut self, entity_id: EntityId) {
+        self.controller().unfreeze(entity_id);
+    }
+
+    #[inline]
+    pub fn is_frozen(&self, entity_id: EntityId) -> bool {
+        self.freezes.contains_key(&entity_id)
+    }
+
+    // Stops the clocks of the loaded entities, and restores their pending cancellations before
+    // their actions are loaded, so the cancellations precede the actions enqueued after them
+    pub(super) fn load_freezes<T>(
+        &mut self,
+        freezes: &[FreezeData<T>],
+        remap_entity: impl Fn(EntityId) -> EntityId,
+        remap_tick: impl Fn(Tick) -> Tick,
+    ) {
+        for freeze in freezes.iter() {
+            let entity_id = remap_entity(freeze.entity_id);
+            let mut frozen = FrozenEntity {
+                tick: remap_tick(freeze.tick),
+                ..Default::default()
+            };
+            for action in freeze.actions.iter() {
+                self.create_context(entity_id, action.layer, frozen.tick);
+            }
+            for cancel in freeze.cancels.iter() {
+                self.create_context(entity_id, cancel.layer, frozen.tick);
+                let tick = remap_tick(cancel.tick);
+                let context = self.contexts.get_mut(&(entity_id, cancel.layer)).unwrap();
+                context.last_tick = context.last_tick.max(tick);
+                context.running_end_tick = context.running_end_tick.min(tick);
+                let item = frozen.actions.entry(tick).or_default();
+                item.cancels
+                    .insert((entity_id, cancel.layer), cancel.reason.clone());
+                item.events.push_back((
+                    (entity_id, cancel.layer),
+                    (context.session_id, Event::Canceled),
+                ));
+            }
+            self.freezes.insert(entity_id, frozen);
+        }
+    }
+}
+
+impl<'a> ActionController<'a> {
+    // Stops the clock of the entity. The pending actions don't progress until unfreezing,
+    // and the joint actions of the entity are unlinked because they cannot end together
+    pub fn freeze(&mut self, entity_id: EntityId) {
+        if self.freezes.contains_key(&entity_id) {
+            return;
+        }
+        let mut frozen = FrozenEntity {
+            tick: self.current_tick,
+            ..Default::default()
+        };
+        for (tick, item) in self.actions.iter_mut() {
+            let (events, others) = std::mem::take(&mut item.events)
+                .into_iter()
+                .partition(|((id, _layer), _)| *id == entity_id);
+            item.events = others;
+            let bundle = BundleForeachTick {
+                map: item.map.take(entity_id),
+                events,
+                cancels: item
+                    .cancels
+                    .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+                    .map(|(key, reason)| (*key, reason.clone()))
+                    .collect(),
+                timers: Vec::new(),
+            };
+            item.cancels
+                .retain(|(id, _layer), _reason| *id != entity_id);
+            if !bundle.is_empty() {
+                frozen.actions.insert(*tick, bundle);
+            }
+        }
+        self.actions.retain(|_tick, item| !item.is_empty());
+        for (type_id, meta, action) in self.each_tick_actions.take(entity_id) {
+            frozen.each_tick_actions.insert_as_raw(
+                action,
+                type_id,
+                meta.entity_id,
+                meta.layer,
+                meta.session_id,
+                meta.tick,
+            );
+        }
+        self.joints.retain(|joint| {
+            joint
+                .participants
+                .iter()
+                .all(|((id, _layer), _)| *id != entity_id)
+        });
+        self.freezes.insert(entity_id, frozen);
+        self.mark_dirty_entity(entity_id);
+        if let Some(records) = self.replication {
+            records.push(ReplicationRecord::Freeze(entity_id));
+        }
+    }
+
+    // Resumes the clock of the entity, and shifts its pending actions by the frozen ticks
+    pub fn unfreeze(&mut self, entity_id: EntityId) {
+        let mut frozen = match self.freezes.remove(&entity_id) {
+            None => return,
+            Some(frozen) => frozen,
+        };
+        self.mark_dirty_entity(entity_id);
+        if let Some(records) = self.replication {
+            records.push(ReplicationRecord::Unfreeze(entity_id));
+        }
+        let shift = self.current_tick - frozen.tick;
+        let tick_duration = self.tick_duration;
+        let retimers = &*self.retimers;
+        let retime = |type_id: &TypeId, action: &Arc<Box<dyn Any>>| {
+            let retimer = retimers
+                .get(type_id)
+                .expect("The retimer is registered on scheduling");
+            (retimer.retime)(action, &|ty| ty.remap(shift, true, tick_duration))
+        };
+
+        for (tick, bundle) in frozen.actions {
+            let item = self.actions.entry(tick + shift).or_default();
+            for (type_id, actions) in bundle.map.iter() {
+                for (meta, action) in actions.iter() {
+                    item.map.push_as_raw(
+                        *type_id,
+                        retime(type_id, action),
+                        meta.entity_id,
+                        meta.layer,
+                        meta.session_id,
+                        meta.tick + shift,
+                    );
+                }
+            }
+            for (key, (session_id, event)) in bundle.events {
+                let event = match event {
+                    Event::Started {
+                        type_id,
+                        type_name,
+                        update_action,
+                        each_action,
+                        running_end_tick,
+                    } => Event::Started {
+                        type_id,
+                        type_name,
+                        update_action: retime(&type_id, &update_action),
+                        each_action: each_action.map(|action| retime(&type_id, &action)),
+                        running_end_tick: running_end_tick + shift,
+                    },
+                    event => event,
+                };
+                item.events.push_back((key, (session_id, event)));
+            }
+            item.cancels.extend(bundle.cancels);
+        }
+        for (type_id, meta, action) in frozen.each_tick_actions.take(entity_id) {
+            self.each_tick_actions.insert_as_raw(
+                retime(&type_id, &action),
+                type_id,
+                meta.entity_id,
+                meta.layer,
+                meta.session_id,
+                meta.tick + shift,
+            );
+        }
+        for (type_id, layer, action) in self.update_actions.take(entity_id) {
+            self.update_actions
+                .insert_as_raw(retime(&type_id, &action), type_id, entity_id, layer);
+        }
+        for (_key, context) in self
+            .contexts
+            .range_mut((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+        {
+            context.last_tick += shift;
+            context.running_end_tick += shift;
+            if context.session_expired_at >= frozen.tick {
+                context.session_expired_at += shift;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{assert_managers, JumpState, MoveState, TestAction};
+    use crate::v2::action::manager::{ActionManager, ActionManagerData, EnqueueOptions};
+    use crate::v2::action::{ActionType, CancelReason};
+    use crate::v2::HordeActions;
+    use std::sync::Arc;
+
+    fn reload(manager: &ActionManager) -> ActionManager {
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        ActionManager::from_data(data, TestAction::convert_from_actions).unwrap()
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+
+        manager.enqueue_with_options(1, Arc::new(MoveState), 1000, EnqueueOptions { each: true }); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 1000); // tick: 30
+
+        manager.update(500); // tick: 7
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        assert!(manager.is_frozen(1));
+
+        // The frozen entity accepts the actions in the stopped clock
+        manager.enqueue(1, Arc::new(JumpState), 330); // tick: 15(20)
+
+        manager.update(1000); // tick: 22
+        while let Some(result) = manager.pull_actions() {
+            assert!(result
+                .map
+                .get::<MoveState>(&manager.validator())
+                .unwrap_or_default()
+                .iter()
+                .all(|action| action.entity_id() == 2));
+            assert!(result.map.get::<JumpState>(&manager.validator()).is_none());
+        }
+        assert_eq!(manager.current_tick(), 22);
+        assert!(manager.pull_vacated_entities().is_empty());
+        assert!(manager.has_some_actions(1));
+
+        manager.unfreeze(1); // shift: 15
+        assert!(!manager.is_frozen(1));
+        let updates = manager.pull_updates().get::<MoveState>().unwrap();
+        let update = updates
+            .iter()
+            .find(|action| action.entity_id() == 1)
+            .unwrap();
+        assert_eq!(
+            update.ty(),
+            &ActionType::Update {
+                start: 990,
+                end: 1980
+            }
+        );
+
+        manager.update(528); // tick: 30
+        let mut ended = Vec::new();
+        while let Some(result) = manager.pull_actions() {
+            let validator = manager.validator();
+            for action in result.map.get::<MoveState>(&validator).unwrap_or_default() {
+                if let (1, ActionType::End { .. }) = (action.entity_id(), action.ty()) {
+                    ended.push((manager.current_tick(), *action.ty()));
+                }
+            }
+        }
+        assert_eq!(ended, vec![(30, ActionType::End { start: 15, end: 30 })]);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        manager.update(330); // tick: 35
+        while manager.pull_actions().is_some() {}
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_serialization_with_freeze() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+
+        manager.enqueue_with_options(1, Arc::new(MoveState), 1000, EnqueueOptions { each: true }); // tick: 15
+        manager.enqueue(1, Arc::new(JumpState), 1000); // tick: 30
+        manager.enqueue(2, Arc::new(MoveState), 2000); // tick: 30
+        manager.set_time_scale(1500);
+
+        manager.update(333); // tick: 7
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        manager.update(333); // tick: 15
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        let mut new_manager =
+            ActionManager::from_data(data, TestAction::convert_from_actions).unwrap();
+        assert!(new_manager.is_frozen(1));
+        assert_eq!(new_manager.time_scale(), 1500);
+        assert_eq!(manager.checksum(), new_manager.checksum());
+
+        manager.unfreeze(1);
+        new_manager.unfreeze(1);
+        assert_eq!(manager.checksum(), new_manager.checksum());
+        manager.update(1000);
+        new_manager.update(1000);
+        assert_managers(&mut manager, &mut new_manager);
+    }
+
+    #[test]
+    fn test_serialization_with_cancel_while_frozen() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.update(500); // tick: 7
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        manager.cancel_with_reason(1, true, CancelReason::user([3]));
+        manager.update(500); // tick: 15
+        while manager.pull_actions().is_some() {}
+        assert!(manager.verify().is_ok());
+
+        let mut new_manager = reload(&manager);
+        assert!(new_manager.is_frozen(1));
+        assert_eq!(manager.checksum(), new_manager.checksum());
+
+        manager.unfreeze(1);
+        new_manager.unfreeze(1);
+        assert_eq!(manager.checksum(), new_manager.checksum());
+        let result = new_manager.pull_actions().unwrap();
+        assert_eq!(
+            result.canceled_layers.into_iter().collect::<Vec<_>>(),
+            vec![((1, 0), CancelReason::user([3]))]
+        );
+        assert_eq!(manager.pull_actions().unwrap().canceled_layers.len(), 1);
+        assert_eq!(
+            manager.pull_vacated_entities(),
+            new_manager.pull_vacated_entities()
+        );
+        assert_managers(&mut manager, &mut new_manager);
+    }
+
+    #[test]
+    fn test_serialization_with_interrupt_while_frozen() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 2000); // tick: 30
+        manager.update(500); // tick: 7
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        manager.interrupt(1, Arc::new(JumpState), 500); // tick: 7..14 in the stopped clock
+        manager.update(500); // tick: 15
+        while manager.pull_actions().is_some() {}
+        assert!(manager.verify().is_ok());
+
+        let mut new_manager = reload(&manager);
+        assert_eq!(manager.checksum(), new_manager.checksum());
+
+        manager.unfreeze(1);
+        new_manager.unfreeze(1);
+        manager.update(1000); // tick: 30
+        new_manager.update(1000);
+        assert_managers(&mut manager, &mut new_manager);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{
+    ActionController, ActionManager, ActionManagerConverter, ActionManagerError, EnqueueOptions,
+    JointAction, JointActionData, Tick,
+};
+use crate::v2::action::{ActionLayer, ActionSessionId, DEFAULT_ACTION_LAYER};
+use std::sync::Arc;
+use tearchan_ecs::component::EntityId;
+
+impl ActionManager {
+    pub fn enqueue_joint<T>(
+        &mut self,
+        entity_ids: &[EntityId],
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+    ) where
+        T: 'static,
+    {
+        self.controller().enqueue_joint(entity_ids, raw, duration);
+    }
+}
+
+impl<'a> ActionController<'a> {
+    #[inline]
+    pub fn enqueue_joint<T>(
+        &mut self,
+        entity_ids: &[EntityId],
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+    ) where
+        T: 'static,
+    {
+        let participants = entity_ids
+            .iter()
+            .map(|entity_id| (*entity_id, DEFAULT_ACTION_LAYER))
+            .collect::<Vec<_>>();
+        self.enqueue_joint_to_layers(&participants, raw, duration, EnqueueOptions::default());
+    }
+
+    // Enqueues the action to all participants so that it starts and ends on the same tick.
+    // The start is aligned to the latest participant, and canceling one of them cancels the others.
+    // Panics if any participant is frozen, which would start the action out of sync.
+    pub fn enqueue_joint_to_layers<T>(
+        &mut self,
+        participants: &[(EntityId, ActionLayer)],
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static,
+    {
+        assert!(
+            participants
+                .iter()
+                .all(|(entity_id, _)| !self.freezes.contains_key(entity_id)),
+            "The frozen entities cannot join the joint actions"
+        );
+        let start_tick = participants
+            .iter()
+            .map(|(entity_id, layer)| {
+                self.contexts
+                    .get(&(*entity_id, *layer))
+                    .unwrap_or_else(|| {
+                        panic!("layer {} of entity {} is not attached", layer, entity_id)
+                    })
+                    .last_tick
+            })
+            .max()
+            .unwrap_or(self.current_tick);
+        let end_tick = start_tick + duration / self.tick_duration;
+        for (entity_id, layer) in participants.iter() {
+            self.schedule(
+                *entity_id,
+                *layer,
+                Arc::clone(&raw),
+                start_tick,
+                end_tick,
+                options.each,
+            );
+        }
+        self.joints.push(JointAction {
+            participants: participants
+                .iter()
+                .map(|key| (*key, self.contexts[key].session_id))
+                .collect(),
+            start_tick,
+            end_tick,
+        });
+    }
+
+    // Returns the other participants of the joint actions which don't end by the tick
+    pub(super) fn find_joint_participants(
+        &self,
+        key: (EntityId, ActionLayer),
+        session_id: ActionSessionId,
+        tick: Tick,
+    ) -> Vec<((EntityId, ActionLayer), ActionSessionId)> {
+        self.joints
+            .iter()
+            .filter(|joint| {
+                tick < joint.end_tick && joint.participants.contains(&(key, session_id))
+            })
+            .flat_map(|joint| joint.participants.iter())
+            .filter(|(participant, _)| *participant != key)
+            .copied()
+            .collect()
+    }
+}
+
+impl<'a> ActionManagerConverter<'a> {
+    pub(super) fn load_joints(
+        &mut self,
+        joints: Vec<JointActionData>,
+    ) -> Result<(), ActionManagerError> {
+        for joint in joints {
+            let mut participants = Vec::new();
+            for (entity_id, layer) in joint.participants {
+                let key = (self.entities.remap(entity_id), layer);
+                let context = self
+                    .contexts
+                    .get(&key)
+                    .ok_or(ActionManagerError::InvalidDataNoJointParticipant)?;
+                participants.push((key, context.session_id));
+            }
+            self.joints.push(JointAction {
+                participants,
+                start_tick: self.remap_tick(joint.start_tick),
+                end_tick: self.remap_tick(joint.end_tick),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{
+        assert_managers, JumpState, MoveState, TalkState, TestAction,
+    };
+    use crate::v2::action::manager::{ActionManager, ActionManagerData};
+
+    use crate::v2::action::CancelReason;
+    use crate::v2::HordeActions;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_joint() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.attach(3);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+        manager.enqueue(3, Arc::new(MoveState), 2000); // tick: 30
+        manager.enqueue_joint(&[1, 2], Arc::new(TalkState), 500); // tick: 15(22)
+        manager.enqueue_joint(&[2, 3], Arc::new(JumpState), 200); // tick: 30(33)
+
+        manager.update(1000); // tick: 15
+        let mut started = Vec::new();
+        while let Some(result) = manager.pull_actions() {
+            for action in result
+                .map
+                .get::<TalkState>(&manager.validator())
+                .unwrap_or_default()
+            {
+                started.push((manager.current_tick(), action.entity_id()));
+            }
+        }
+        assert_eq!(started, vec![(15, 1), (15, 2)]);
+        assert!(manager.pull_vacated_entities().is_empty());
+
+        // Canceling the participant cancels the joint actions transitively
+        manager.cancel_with_reason(1, true, CancelReason::user([7]));
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(
+            result.canceled_layers.into_iter().collect::<Vec<_>>(),
+            vec![
+                ((1, 0), CancelReason::user([7])),
+                ((2, 0), CancelReason::JointPartnerCanceled),
+                ((3, 0), CancelReason::JointPartnerCanceled),
+            ]
+        );
+        assert!(manager.pull_updates().get::<TalkState>().is_none());
+        assert!(manager.pull_updates().get::<MoveState>().is_none());
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        // The joint action which has ended isn't canceled
+        manager.enqueue_joint(&[1, 2], Arc::new(TalkState), 66); // tick: 16
+        manager.update(66);
+        while manager.pull_actions().is_some() {}
+        manager.pull_vacated_entities();
+        manager.enqueue(2, Arc::new(MoveState), 1000);
+        manager.cancel(1, true);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![1]);
+        assert!(manager.has_some_actions(2));
+    }
+
+    #[test]
+    fn test_serialization_with_joint() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.attach(3);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+        manager.enqueue(3, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue_joint(&[1, 2], Arc::new(TalkState), 500); // tick: 15(22)
+        manager.enqueue(3, Arc::new(JumpState), 1000); // tick: 30
+
+        manager.update(500); // tick: 7
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        let mut new_manager =
+            ActionManager::from_data(data, TestAction::convert_from_actions).unwrap();
+        assert_eq!(manager.checksum(), new_manager.checksum());
+
+        manager.update(600); // tick: 16
+        new_manager.update(600);
+        assert_managers(&mut manager, &mut new_manager);
+
+        manager.cancel(2, true);
+        new_manager.cancel(2, true);
+        let result0 = manager.pull_actions().unwrap();
+        let result1 = new_manager.pull_actions().unwrap();
+        assert_eq!(
+            result1
+                .canceled_layers
+                .clone()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![
+                ((1, 0), CancelReason::JointPartnerCanceled),
+                ((2, 0), CancelReason::Unspecified),
+            ]
+        );
+        assert_eq!(result0.cancels, result1.cancels);
+        assert_eq!(result0.canceled_layers, result1.canceled_layers);
+    }
+
+    #[test]
+    #[should_panic(expected = "The frozen entities cannot join the joint actions")]
+    fn test_joint_with_frozen_entity() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+        manager.freeze(2);
+        manager.enqueue_joint(&[1, 2], Arc::new(TalkState), 500);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::v2::action::manager::{
+    entity_tick, ActionContext, ActionController, ActionManager, CancelTiming, ReplicationRecord,
+    Tick,
+};
+use crate::v2::action::{ActionLayer, CancelReason, DEFAULT_ACTION_LAYER};
+use std::collections::BTreeSet;
+use tearchan_ecs::component::EntityId;
+
+// The vacated layers, and the entities having them for the API of the default layer
+#[derive(Default, Clone)]
+pub(super) struct VacatedLayers {
+    layers: BTreeSet<(EntityId, ActionLayer)>,
+    entities: BTreeSet<EntityId>,
+}
+
+impl VacatedLayers {
+    pub(super) fn insert(&mut self, key: (EntityId, ActionLayer)) {
+        self.layers.insert(key);
+        self.entities.insert(key.0);
+    }
+
+    pub(super) fn remove(&mut self, key: &(EntityId, ActionLayer)) {
+        let (entity_id, _layer) = *key;
+        if self.layers.remove(key)
+            && self
+                .layers
+                .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+                .next()
+                .is_none()
+        {
+            self.entities.remove(&entity_id);
+        }
+    }
+
+    #[inline]
+    pub(super) fn iter(&self) -> impl Iterator<Item = &(EntityId, ActionLayer)> {
+        self.layers.iter()
+    }
+
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl ActionManager {
+    pub fn attach(&mut self, entity_id: EntityId) {
+        self.controller().attach(entity_id);
+    }
+
+    pub fn detach(&mut self, entity_id: EntityId) {
+        self.controller().detach(entity_id);
+    }
+
+    pub fn attach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.controller().attach_layer(entity_id, layer);
+    }
+
+    pub fn detach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.controller().detach_layer(entity_id, layer);
+    }
+
+    pub fn attached_layers(&self) -> impl Iterator<Item = (EntityId, ActionLayer)> + '_ {
+        self.contexts.keys().copied()
+    }
+
+    #[inline]
+    pub fn is_attached(&self, entity_id: EntityId, layer: ActionLayer) -> bool {
+        self.contexts.contains_key(&(entity_id, layer))
+    }
+
+    // The tick where the next action enqueued to the layer starts
+    pub fn last_tick_on_layer(&self, entity_id: EntityId, layer: ActionLayer) -> Option<Tick> {
+        self.contexts
+            .get(&(entity_id, layer))
+            .map(|context| context.last_tick)
+    }
+
+    // Returns the entities which have one or more vacated layers
+    pub fn pull_vacated_entities(&mut self) -> BTreeSet<EntityId> {
+        std::mem::take(&mut self.vacated_entities).entities
+    }
+
+    pub fn pull_vacated_layers(&mut self) -> BTreeSet<(EntityId, ActionLayer)> {
+        std::mem::take(&mut self.vacated_entities).layers
+    }
+
+    pub fn get_vacated_entities(&self) -> &BTreeSet<EntityId> {
+        &self.vacated_entities.entities
+    }
+
+    pub fn get_vacated_layers(&self) -> &BTreeSet<(EntityId, ActionLayer)> {
+        &self.vacated_entities.layers
+    }
+
+    // Returns true if any layer of the entity has some actions
+    pub fn has_some_actions(&self, entity_id: EntityId) -> bool {
+        let tick = entity_tick(&self.freezes, self.current_tick, entity_id);
+        self.contexts
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .any(|(_, context)| context.last_tick != tick)
+    }
+
+    pub fn has_some_actions_on_layer(&self, entity_id: EntityId, layer: ActionLayer) -> bool {
+        let context = match self.contexts.get(&(entity_id, layer)) {
+            None => return false,
+            Some(context) => context,
+        };
+        context.last_tick != entity_tick(&self.freezes, self.current_tick, entity_id)
+    }
+}
+
+impl<'a> ActionController<'a> {
+    #[inline]
+    pub fn attach(&mut self, entity_id: EntityId) {
+        self.attach_layer(entity_id, DEFAULT_ACTION_LAYER);
+    }
+
+    // Removes all layers of the entity
+    #[inline]
+    pub fn detach(&mut self, entity_id: EntityId) {
+        let layers = self
+            .contexts
+            .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            .map(|((_, layer), _)| *layer)
+            .collect::<Vec<_>>();
+        for layer in layers {
+            self.detach_layer(entity_id, layer);
+        }
+        if self.freezes.remove(&entity_id).is_some() {
+            self.mark_dirty_entity(entity_id);
+        }
+    }
+
+    pub fn attach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        debug_assert!(!self.contexts.contains_key(&(entity_id, layer)));
+
+        let tick = entity_tick(self.freezes, self.current_tick, entity_id);
+        self.contexts.insert(
+            (entity_id, layer),
+            ActionContext {
+                last_tick: tick,
+                running_end_tick: tick,
+                session_id: self.session_id_manager.gen(),
+                session_expired_at: tick,
+            },
+        );
+        self.vacated_entities.insert((entity_id, layer));
+        self.mark_dirty((entity_id, layer));
+        if let Some(records) = self.replication {
+            records.push(ReplicationRecord::AttachLayer(entity_id, layer));
+        }
+    }
+
+    // The other participants of the joint actions are canceled immediately
+    pub fn detach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        if let Some(context) = self.contexts.remove(&(entity_id, layer)) {
+            let participants = self.find_joint_participants(
+                (entity_id, layer),
+                context.session_id,
+                self.current_tick,
+            );
+            self.cancel_sessions(
+                participants,
+                CancelTiming::Immediate,
+                CancelReason::JointPartnerCanceled,
+            );
+            self.mark_dirty((entity_id, layer));
+            if let Some(records) = self.replication {
+                records.push(ReplicationRecord::DetachLayer(entity_id, layer));
+            }
+        }
+        self.vacated_entities.remove(&(entity_id, layer));
+        self.update_actions.remove_layer(entity_id, layer);
+        self.each_tick_actions.remove_layer(entity_id, layer);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{
+        assert_managers, JumpState, MoveState, TalkState, TestAction,
+    };
+    use crate::v2::action::manager::{ActionManager, ActionManagerData, EnqueueOptions};
+
+    use crate::v2::HordeActions;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_vacated_layers() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach_layer(1, 1);
+        manager.attach(2);
+
+        // The entity stays vacated until all of its layers are filled
+        manager.enqueue(1, Arc::new(MoveState), 1000);
+        manager.enqueue(2, Arc::new(MoveState), 1000);
+        assert_eq!(
+            manager
+                .get_vacated_layers()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![(1, 1)]
+        );
+        assert_eq!(
+            manager
+                .get_vacated_entities()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        manager.enqueue_to_layer(1, 1, Arc::new(JumpState), 500, EnqueueOptions::default());
+        assert!(manager.get_vacated_layers().is_empty());
+        assert!(manager.get_vacated_entities().is_empty());
+
+        manager.detach_layer(1, 1);
+        manager.cancel(2, true);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert!(manager.get_vacated_layers().is_empty());
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach_layer(1, 1);
+        assert_eq!(
+            manager
+                .get_vacated_entities()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(
+            manager
+                .pull_vacated_layers()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 0), (1, 1)]
+        );
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue_to_layer(1, 1, Arc::new(JumpState), 500, EnqueueOptions::default()); // tick: 7
+
+        manager.update(66); // tick: 1
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.pull_updates().get::<MoveState>().unwrap().len(), 1);
+        assert_eq!(manager.pull_updates().get::<JumpState>().unwrap().len(), 1);
+
+        manager.update(434); // tick: 7
+        while manager.pull_actions().is_some() {}
+        assert!(manager.pull_updates().get::<JumpState>().is_none());
+        assert!(manager.has_some_actions(1));
+        assert!(manager.has_some_actions_on_layer(1, 0));
+        assert!(!manager.has_some_actions_on_layer(1, 1));
+        assert_eq!(
+            manager
+                .pull_vacated_layers()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 1)]
+        );
+
+        manager.enqueue_to_layer(1, 1, Arc::new(TalkState), 1000, EnqueueOptions::default()); // tick: 22
+        manager.cancel_layer(1, 0, true);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            result.canceled_layers.into_keys().collect::<Vec<_>>(),
+            vec![(1, 0)]
+        );
+        assert!(manager.pull_updates().get::<MoveState>().is_none());
+        assert!(manager.has_some_actions_on_layer(1, 1));
+        assert_eq!(
+            manager
+                .pull_vacated_layers()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(1, 0)]
+        );
+
+        manager.detach(1);
+        assert!(!manager.has_some_actions(1));
+        assert!(manager.pull_updates().get::<TalkState>().is_none());
+    }
+
+    #[test]
+    fn test_serialization_with_layers() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach_layer(1, 2);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 3000);
+        manager.enqueue(1, Arc::new(JumpState), 500);
+        manager.enqueue_to_layer(
+            1,
+            2,
+            Arc::new(TalkState),
+            1000,
+            EnqueueOptions { each: true },
+        );
+        manager.enqueue_to_layer(1, 2, Arc::new(JumpState), 2000, EnqueueOptions::default());
+        manager.enqueue(2, Arc::new(MoveState), 1500);
+        manager.pull_vacated_entities();
+
+        manager.update(500); // tick: 7
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        let mut new_manager =
+            ActionManager::from_data(data, TestAction::convert_from_actions).unwrap();
+        assert!(new_manager.has_some_actions_on_layer(1, 2));
+        assert_eq!(manager.checksum(), new_manager.checksum());
+
+        manager.update(10000);
+        new_manager.update(10000);
+        assert_managers(&mut manager, &mut new_manager);
+    }
+}
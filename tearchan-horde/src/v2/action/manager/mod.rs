@@ -0,0 +1,2854 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::collection::{
+    ActionMeta, AnyActionVec, TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId,
+    TypedAnyTimerMap, TypedCloneableAnyActionMapGroupedByEntityId,
+};
+use crate::v2::action::timer::TimerId;
+use crate::v2::action::tween::{Tween, Tweenable};
+use crate::v2::action::{
+    rescale_tick, Action, ActionLayer, ActionSessionId, ActionType, ArcAction, CancelReason,
+    DEFAULT_ACTION_LAYER, VALID_SESSION_ID,
+};
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+use tearchan_ecs::component::EntityId;
+use tearchan_ecs::entity::manager::EntityRemapContext;
+use tearchan_util::id_manager::IdManager;
+
+mod checksum;
+mod dirty;
+mod freezes;
+mod joints;
+mod layers;
+mod remapping;
+mod replication;
+mod reschedule;
+mod timers;
+mod verify;
+
+use crate::v2::action::manager::dirty::DirtyLayers;
+use crate::v2::action::manager::layers::VacatedLayers;
+#[allow(deprecated)]
+pub use crate::v2::action::manager::remapping::{
+    ActionRemapper, ActionRemapperToken, RemapContext, RemapContextToken, ACTION_REMAPPER,
+};
+pub use crate::v2::action::manager::verify::ActionManagerViolation;
+
+#[derive(Default)]
+pub struct ActionSessionValidator<'a> {
+    contexts: Option<&'a BTreeMap<(EntityId, ActionLayer), ActionContext>>,
+}
+
+impl<'a> ActionSessionValidator<'a> {
+    pub fn validate(&self, action: &ActionMeta) -> bool {
+        let contexts = match self.contexts {
+            None => return true,
+            Some(contexts) => contexts,
+        };
+        contexts
+            .get(&(action.entity_id, action.layer))
+            .map(|context| {
+                action.session_id == context.session_id
+                    || action.session_id == VALID_SESSION_ID
+                    || action.tick <= context.session_expired_at
+            })
+            .unwrap_or(false)
+    }
+}
+// x1/4 -> 60 / 4 = 15
+// 1 tick is 66ms
+pub const DEFAULT_TICK_DURATION: TimeMilliseconds = 1000 / 15;
+pub const DEFAULT_TIME_SCALE: TimeScale = 1000;
+type Tick = u64;
+// Per mille of the elapsed time, 500 is the half speed and 2000 is the double speed
+pub type TimeScale = u32;
+// The policy of the ticks over the catch-up limit of an update
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CatchUpPolicy {
+    Drop,      // Discards the backlog, so the clock falls behind the elapsed time
+    CarryOver, // Processes the backlog in the following updates
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CatchUpLimit {
+    pub max_ticks: Tick, // The maximum number of ticks advanced by an update
+    pub policy: CatchUpPolicy,
+}
+type RetimeAction = fn(&Arc<Box<dyn Any>>, &dyn Fn(&ActionType) -> ActionType) -> Arc<Box<dyn Any>>;
+
+// Registered for each action type when it's scheduled or loaded. The type name is the stable key
+// of the type in the checksum, unlike TypeId.
+#[derive(Clone, Copy)]
+struct ActionRetimer {
+    type_name: &'static str,
+    retime: RetimeAction,
+}
+
+impl ActionRetimer {
+    fn of<T>() -> Self
+    where
+        T: 'static,
+    {
+        ActionRetimer {
+            type_name: std::any::type_name::<T>(),
+            retime: retime_action::<T>,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Event {
+    Started {
+        type_id: TypeId,
+        type_name: &'static str,
+        update_action: Arc<Box<dyn Any>>,
+        each_action: Option<Arc<Box<dyn Any>>>,
+        running_end_tick: Tick,
+    },
+    Ended,
+    Canceled,
+}
+
+#[derive(Clone)]
+struct ActionContext {
+    last_tick: Tick,             // The last time of stacking all actions
+    running_end_tick: Tick,      // The end time of running action
+    session_id: ActionSessionId, // Use for validation action
+    session_expired_at: Tick,    // Invalid session is expired at this
+}
+
+#[derive(Default, Clone)]
+struct BundleForeachTick {
+    map: TypedAnyActionMap,
+    events: VecDeque<((EntityId, ActionLayer), (ActionSessionId, Event))>,
+    cancels: BTreeMap<(EntityId, ActionLayer), CancelReason>,
+    timers: Vec<TimerId>,
+}
+
+impl BundleForeachTick {
+    fn is_empty(&self) -> bool {
+        self.map.is_empty()
+            && self.events.is_empty()
+            && self.cancels.is_empty()
+            && self.timers.is_empty()
+    }
+}
+
+// The pending actions of the frozen entity, which are shifted by the frozen ticks on unfreezing
+#[derive(Default, Clone)]
+struct FrozenEntity {
+    tick: Tick, // The clock of the entity stops at this tick
+    actions: BTreeMap<Tick, BundleForeachTick>,
+    each_tick_actions: TypedCloneableAnyActionMapGroupedByEntityId,
+}
+
+#[derive(Clone)]
+struct JointAction {
+    participants: Vec<((EntityId, ActionLayer), ActionSessionId)>,
+    start_tick: Tick,
+    end_tick: Tick,
+}
+
+// The command recorded for the replication, whose actions are converted on committing
+enum ReplicationRecord {
+    AttachLayer(EntityId, ActionLayer),
+    DetachLayer(EntityId, ActionLayer),
+    Schedule(TypeId, Arc<Box<dyn Any>>), // ArcAction<T> of Start
+    Cancel(EntityId, ActionLayer, Tick, CancelReason),
+    Reschedule(EntityId, ActionLayer, Tick),
+    Freeze(EntityId),
+    Unfreeze(EntityId),
+}
+
+#[derive(Copy, Clone)]
+enum CancelTiming {
+    Immediate,
+    RunningEnd,
+    At(Tick), // Clamped between the current tick of the entity and the end of the running action
+}
+
+#[derive(Clone)]
+struct ScheduledTimer {
+    type_id: TypeId,
+    type_name: &'static str,
+    tick: Tick,
+    interval: Option<Tick>,
+    timer: Arc<Box<dyn Any>>,  // Timer<Arc<T>>
+    action: Arc<Box<dyn Any>>, // ArcAction<T> for serialization
+}
+
+pub struct PullActionResult {
+    pub map: TypedAnyActionMap,
+    pub cancels: BTreeSet<EntityId>,
+    pub canceled_layers: BTreeMap<(EntityId, ActionLayer), CancelReason>,
+    pub timers: TypedAnyTimerMap,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ActionTraceKind {
+    Start { type_name: &'static str },
+    End,
+    Cancel,
+}
+
+// The action event which is applied by pulling the actions
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ActionTrace {
+    pub tick: Tick,
+    pub entity_id: EntityId,
+    pub layer: ActionLayer,
+    pub session_id: ActionSessionId,
+    pub kind: ActionTraceKind,
+}
+
+// The buffer of the traces while tracing is enabled. Without the trace and metrics features,
+// nothing reads the traces, so it's a stub which never records.
+#[cfg(any(feature = "trace", feature = "metrics"))]
+#[derive(Default)]
+struct ActionTraces(Option<Vec<ActionTrace>>);
+
+#[cfg(any(feature = "trace", feature = "metrics"))]
+impl ActionTraces {
+    fn enable(&mut self) {
+        if self.0.is_none() {
+            self.0 = Some(Vec::new());
+        }
+    }
+
+    fn disable(&mut self) {
+        self.0 = None;
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    #[inline]
+    fn push_with<F>(&mut self, f: F)
+    where
+        F: FnOnce() -> ActionTrace,
+    {
+        if let Some(traces) = &mut self.0 {
+            traces.push(f());
+        }
+    }
+
+    fn take(&mut self) -> Vec<ActionTrace> {
+        self.0.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+}
+
+#[cfg(not(any(feature = "trace", feature = "metrics")))]
+#[derive(Default)]
+struct ActionTraces;
+
+#[cfg(not(any(feature = "trace", feature = "metrics")))]
+impl ActionTraces {
+    #[inline]
+    fn enable(&mut self) {}
+
+    #[inline]
+    fn disable(&mut self) {}
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn push_with<F>(&mut self, _f: F)
+    where
+        F: FnOnce() -> ActionTrace,
+    {
+    }
+
+    #[inline]
+    fn take(&mut self) -> Vec<ActionTrace> {
+        Vec::new()
+    }
+}
+
+pub struct ActionManager {
+    tick_duration: TimeMilliseconds,
+    time_scale: TimeScale,
+    time_scale_remainder: u64, // The scaled time less than 1ms
+    paused: bool,
+    catch_up_limit: Option<CatchUpLimit>,
+    backlog: TimeMilliseconds, // The time carried over by the catch-up limit
+    next_time: TimeMilliseconds,
+    next_tick: Tick,
+    current_tick: Tick,
+    actions: BTreeMap<Tick, BundleForeachTick>,
+    update_actions: TypedAnyActionMapGroupedByEntityId,
+    each_tick_actions: TypedCloneableAnyActionMapGroupedByEntityId,
+    contexts: BTreeMap<(EntityId, ActionLayer), ActionContext>,
+    vacated_entities: VacatedLayers,
+    joints: Vec<JointAction>,
+    timers: BTreeMap<TimerId, ScheduledTimer>,
+    next_timer_id: TimerId,
+    freezes: BTreeMap<EntityId, FrozenEntity>,
+    retimers: HashMap<TypeId, ActionRetimer>,
+    session_id_manager: IdManager<ActionSessionId>,
+    traces: ActionTraces,
+    replication: Option<Vec<ReplicationRecord>>,
+    dirty_entities: Option<DirtyLayers>,
+}
+
+impl Default for ActionManager {
+    fn default() -> Self {
+        ActionManager {
+            tick_duration: DEFAULT_TICK_DURATION,
+            time_scale: DEFAULT_TIME_SCALE,
+            time_scale_remainder: 0,
+            paused: false,
+            catch_up_limit: None,
+            backlog: 0,
+            next_time: 0,
+            next_tick: 0,
+            current_tick: 0,
+            actions: Default::default(),
+            update_actions: Default::default(),
+            each_tick_actions: Default::default(),
+            contexts: Default::default(),
+            vacated_entities: Default::default(),
+            joints: Default::default(),
+            timers: Default::default(),
+            next_timer_id: TimerId::default(),
+            freezes: Default::default(),
+            retimers: Default::default(),
+            session_id_manager: IdManager::new(ActionSessionId::default(), |id| id.next()),
+            traces: ActionTraces::default(),
+            replication: None,
+            dirty_entities: None,
+        }
+    }
+}
+
+impl ActionManager {
+    pub fn with_tick_duration(tick_duration: TimeMilliseconds) -> Self {
+        assert_ne!(tick_duration, 0, "Tick duration must be greater than 0");
+        ActionManager {
+            tick_duration,
+            ..Default::default()
+        }
+    }
+
+    pub fn update(&mut self, delta: TimeMilliseconds) {
+        debug_assert!(self.vacated_entities.is_empty());
+
+        if self.paused {
+            return;
+        }
+        let scaled = delta * self.time_scale as u64 + self.time_scale_remainder;
+        self.time_scale_remainder = scaled % DEFAULT_TIME_SCALE as u64;
+        let mut next_time = self.next_time + scaled / DEFAULT_TIME_SCALE as u64 + self.backlog;
+        self.backlog = 0;
+        if let Some(limit) = self.catch_up_limit {
+            // Keeps the fraction of the tick, so the interpolation doesn't jump
+            let max_time = (self.next_tick + limit.max_ticks) * self.tick_duration
+                + next_time % self.tick_duration;
+            if max_time < next_time {
+                if limit.policy == CatchUpPolicy::CarryOver {
+                    self.backlog = next_time - max_time;
+                }
+                next_time = max_time;
+            }
+        }
+        self.next_time = next_time;
+        self.next_tick = self.next_time / self.tick_duration;
+    }
+
+    // Same as update, except that the clock doesn't pass the tick. The rest of the time is carried
+    // over to the following updates like CatchUpPolicy::CarryOver.
+    pub fn update_until(&mut self, delta: TimeMilliseconds, max_tick: Tick) {
+        let max_tick = max_tick.max(self.next_tick);
+        self.update(delta);
+        if max_tick < self.next_tick {
+            let max_time = max_tick * self.tick_duration + self.next_time % self.tick_duration;
+            self.backlog += self.next_time - max_time;
+            self.next_time = max_time;
+            self.next_tick = max_tick;
+        }
+    }
+
+    pub fn enable_catch_up_limit(&mut self, max_ticks: Tick, policy: CatchUpPolicy) {
+        assert_ne!(max_ticks, 0, "Max ticks must be greater than 0");
+        self.catch_up_limit = Some(CatchUpLimit { max_ticks, policy });
+    }
+
+    // The carried over backlog is processed in the next update
+    pub fn disable_catch_up_limit(&mut self) {
+        self.catch_up_limit = None;
+    }
+
+    #[inline]
+    pub fn catch_up_limit(&self) -> Option<CatchUpLimit> {
+        self.catch_up_limit
+    }
+
+    #[inline]
+    pub fn backlog(&self) -> TimeMilliseconds {
+        self.backlog
+    }
+
+    // The fraction of the way from the last tick into the next tick, used to interpolate the
+    // rendering between the fixed steps
+    pub fn interpolation_alpha(&self) -> f32 {
+        (self.next_time % self.tick_duration) as f32 / self.tick_duration as f32
+    }
+
+    // Evaluates the tween of the action at next_time
+    #[inline]
+    pub fn evaluate_tween<T>(&self, action: &ArcAction<Tween<T>>) -> T
+    where
+        T: Tweenable,
+    {
+        action
+            .raw()
+            .evaluate(action.ty(), self.next_time, self.tick_duration)
+    }
+
+    #[inline]
+    pub fn time_scale(&self) -> TimeScale {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: TimeScale) {
+        self.time_scale = time_scale;
+    }
+
+    // Stops advancing the ticks while the updates can be still rendered
+    #[inline]
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    #[inline]
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn enqueue<T>(&mut self, entity_id: EntityId, raw: Arc<T>, duration: TimeMilliseconds)
+    where
+        T: 'static,
+    {
+        self.controller().enqueue(entity_id, raw, duration);
+    }
+
+    pub fn enqueue_with_options<T>(
+        &mut self,
+        entity_id: EntityId,
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static,
+    {
+        self.controller()
+            .enqueue_with_options(entity_id, raw, duration, options);
+    }
+
+    pub fn enqueue_to_layer<T>(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static,
+    {
+        self.controller()
+            .enqueue_to_layer(entity_id, layer, raw, duration, options);
+    }
+
+    pub fn interrupt<T>(&mut self, entity_id: EntityId, raw: Arc<T>, duration: TimeMilliseconds)
+    where
+        T: 'static,
+    {
+        self.cancel(entity_id, true);
+        self.enqueue(entity_id, raw, duration);
+    }
+
+    pub fn pull_actions(&mut self) -> Option<PullActionResult> {
+        let (tick, mut item) = match self.actions.pop_first() {
+            Some(entry) => entry,
+            None => {
+                // Catches up with the time even if nothing is queued. Otherwise the layers
+                // attached, frozen or enqueued afterwards start at the tick of the last pulled
+                // bundle, which is behind the time.
+                self.current_tick = self.next_tick;
+                return None;
+            }
+        };
+
+        let current_tick_plus_1 = self.next_tick.min(self.current_tick + 1);
+        if !self.each_tick_actions.is_empty() && current_tick_plus_1 < tick {
+            self.actions.insert(tick, item);
+            if self.current_tick == current_tick_plus_1 {
+                return None;
+            }
+            self.current_tick = current_tick_plus_1;
+            let mut map = TypedAnyActionMap::default();
+            self.each_tick_actions
+                .push_actions_to(&mut map, self.current_tick);
+            return Some(PullActionResult {
+                map,
+                cancels: BTreeSet::new(),
+                canceled_layers: BTreeMap::new(),
+                timers: TypedAnyTimerMap::default(),
+            });
+        }
+
+        if self.next_tick < tick {
+            self.current_tick = self.next_tick;
+            self.actions.insert(tick, item);
+            return None;
+        }
+
+        self.current_tick = tick;
+        self.joints.retain(|joint| tick < joint.end_tick);
+        if let Some(dirty) = &mut self.dirty_entities {
+            for (_type_id, vec) in item.map.iter() {
+                for (meta, _action) in vec.iter() {
+                    dirty.insert((meta.entity_id, meta.layer));
+                }
+            }
+            for (key, _event) in item.events.iter() {
+                dirty.insert(*key);
+            }
+        }
+
+        self.each_tick_actions
+            .push_actions_to(&mut item.map, self.current_tick);
+
+        let mut canceled_layers = BTreeSet::new();
+        while let Some(((entity_id, layer), (session_id, event))) = item.events.pop_front() {
+            let context = match self.contexts.get_mut(&(entity_id, layer)) {
+                Some(context) => context,
+                None => continue,
+            };
+            if context.session_id != session_id {
+                continue;
+            }
+            self.traces.push_with(|| ActionTrace {
+                tick,
+                entity_id,
+                layer,
+                session_id,
+                kind: match &event {
+                    Event::Started { type_name, .. } => ActionTraceKind::Start { type_name },
+                    Event::Ended => ActionTraceKind::End,
+                    Event::Canceled => ActionTraceKind::Cancel,
+                },
+            });
+            match event {
+                Event::Started {
+                    type_id,
+                    update_action,
+                    each_action,
+                    running_end_tick,
+                    ..
+                } => {
+                    self.update_actions
+                        .insert_as_raw(update_action, type_id, entity_id, layer);
+                    if let Some(each_action) = each_action {
+                        self.each_tick_actions.insert_as_raw(
+                            each_action,
+                            type_id,
+                            entity_id,
+                            layer,
+                            context.session_id,
+                            running_end_tick,
+                        );
+                    }
+                    context.running_end_tick = running_end_tick;
+                }
+                Event::Ended => {
+                    debug_assert!(self.current_tick <= context.last_tick);
+                    let current_tick = self.current_tick;
+                    if context.last_tick == current_tick {
+                        self.vacated_entities.insert((entity_id, layer));
+                    } else if let Some(end_tick) = self
+                        .joints
+                        .iter()
+                        .filter(|joint| {
+                            current_tick < joint.start_tick
+                                && joint
+                                    .participants
+                                    .contains(&((entity_id, layer), session_id))
+                        })
+                        .map(|joint| joint.end_tick)
+                        .min()
+                    {
+                        // Waiting for the other participants of the joint action
+                        context.running_end_tick = end_tick;
+                    }
+                    self.each_tick_actions.remove_layer(entity_id, layer);
+                    self.update_actions.remove_layer(entity_id, layer);
+                }
+                Event::Canceled => {
+                    canceled_layers.insert((entity_id, layer));
+                    self.vacated_entities.insert((entity_id, layer));
+                    self.update_actions.remove_layer(entity_id, layer);
+                    self.each_tick_actions.remove_layer(entity_id, layer);
+                }
+            }
+        }
+
+        // The cancellations of the detached layers and the superseded sessions aren't reported
+        item.cancels
+            .retain(|key, _reason| canceled_layers.contains(key));
+
+        let mut timers = TypedAnyTimerMap::default();
+        for timer_id in item.timers {
+            // The canceled timers are left in the bundle
+            let timer = match self.timers.get_mut(&timer_id) {
+                Some(timer) if timer.tick == tick => timer,
+                _ => continue,
+            };
+            timers.push_as_raw(timer.type_id, Arc::clone(&timer.timer));
+            match timer.interval {
+                Some(interval) => {
+                    timer.tick = tick + interval;
+                    self.actions
+                        .entry(timer.tick)
+                        .or_default()
+                        .timers
+                        .push(timer_id);
+                }
+                None => {
+                    self.timers.remove(&timer_id);
+                }
+            }
+        }
+
+        Some(PullActionResult {
+            map: item.map,
+            cancels: item
+                .cancels
+                .keys()
+                .map(|(entity_id, _layer)| *entity_id)
+                .collect(),
+            canceled_layers: item.cancels,
+            timers,
+        })
+    }
+
+    pub fn pull_updates(&self) -> &TypedAnyActionMapGroupedByEntityId {
+        &self.update_actions
+    }
+
+    // The tick where the entity is frozen, or the current tick
+    #[inline]
+    pub fn entity_tick(&self, entity_id: EntityId) -> Tick {
+        entity_tick(&self.freezes, self.current_tick, entity_id)
+    }
+
+    // Counts the queued actions which haven't started by the start tick, except the actions of
+    // the frozen entities
+    pub fn pending_actions(&self) -> impl Iterator<Item = (Tick, usize)> + '_ {
+        self.actions.iter().filter_map(move |(tick, item)| {
+            let count = item
+                .events
+                .iter()
+                .filter(|(key, (session_id, event))| {
+                    matches!(event, Event::Started { .. })
+                        && self
+                            .contexts
+                            .get(key)
+                            .map(|context| context.session_id == *session_id)
+                            .unwrap_or(false)
+                })
+                .count();
+            if count == 0 {
+                None
+            } else {
+                Some((*tick, count))
+            }
+        })
+    }
+
+    // Records the started, ended and canceled actions until disabled. It does nothing without the
+    // trace and metrics features.
+    #[inline]
+    pub fn enable_tracing(&mut self) {
+        self.traces.enable();
+    }
+
+    #[inline]
+    pub fn disable_tracing(&mut self) {
+        self.traces.disable();
+    }
+
+    #[inline]
+    pub fn is_tracing(&self) -> bool {
+        self.traces.is_enabled()
+    }
+
+    #[inline]
+    pub fn pull_traces(&mut self) -> Vec<ActionTrace> {
+        self.traces.take()
+    }
+
+    pub fn cancel(&mut self, entity_id: EntityId, immediate: bool) {
+        self.controller().cancel(entity_id, immediate);
+    }
+
+    pub fn cancel_layer(&mut self, entity_id: EntityId, layer: ActionLayer, immediate: bool) {
+        self.controller().cancel_layer(entity_id, layer, immediate);
+    }
+
+    pub fn cancel_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        self.controller()
+            .cancel_with_reason(entity_id, immediate, reason);
+    }
+
+    pub fn cancel_layer_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        self.controller()
+            .cancel_layer_with_reason(entity_id, layer, immediate, reason);
+    }
+
+    // Iterates the queued actions of the entity which haven't started yet in order of the start
+    // tick. The ticks of the frozen entity are on its stopped clock.
+    pub fn upcoming_actions<T>(&self, entity_id: EntityId) -> impl Iterator<Item = &ArcAction<T>>
+    where
+        T: 'static,
+    {
+        let contexts = &self.contexts;
+        let actions = match self.freezes.get(&entity_id) {
+            Some(frozen) => &frozen.actions,
+            None => &self.actions,
+        };
+        actions
+            .values()
+            .flat_map(|bundle| bundle.map.iter_typed::<T>())
+            .filter(move |(meta, action)| {
+                // Unlike the validator, the actions starting at the canceled tick are excluded
+                meta.entity_id == entity_id
+                    && matches!(action.ty(), ActionType::Start { .. })
+                    && contexts
+                        .get(&(meta.entity_id, meta.layer))
+                        .map(|context| context.session_id == meta.session_id)
+                        .unwrap_or(false)
+            })
+            .map(|(_, action)| action)
+    }
+
+    pub fn validator(&self) -> ActionSessionValidator {
+        ActionSessionValidator {
+            contexts: Some(&self.contexts),
+        }
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+
+    #[inline]
+    pub fn next_time(&self) -> TimeMilliseconds {
+        self.next_time
+    }
+
+    #[inline]
+    pub fn tick_duration(&self) -> TimeMilliseconds {
+        self.tick_duration
+    }
+
+    pub fn to_data<T>(
+        &self,
+        converter0: fn(&TypedAnyActionMap, &ActionSessionValidator) -> Vec<Action<T>>,
+        converter1: fn(&TypedAnyActionMapGroupedByEntityId) -> Vec<Action<T>>,
+        converter2: fn(&TypeId, &AnyActionVec, &ActionSessionValidator) -> Vec<Action<T>>,
+    ) -> ActionManagerData<T> {
+        debug_assert!(
+            self.vacated_entities.is_empty(),
+            "Must process vacated entities until busy"
+        );
+        if let Some((tick, _)) = self.actions.iter().next() {
+            debug_assert!(self.current_tick < *tick, "Must process current actions");
+        }
+
+        let validator = ActionSessionValidator {
+            contexts: Some(&self.contexts),
+        };
+        let mut actions = self
+            .actions
+            .iter()
+            .flat_map(|(_tick, action)| converter0(&action.map, &validator))
+            .collect::<Vec<_>>();
+        let mut each_tick_actions = self
+            .each_tick_actions
+            .iter()
+            .flat_map(|(type_id, actions)| converter2(type_id, actions, &validator))
+            .collect::<Vec<_>>();
+        actions.append(&mut each_tick_actions);
+
+        // The Start actions of the superseded sessions pass the validator until the latest expiry,
+        // but they never start
+        let starts = self
+            .actions
+            .iter()
+            .chain(
+                self.freezes
+                    .values()
+                    .flat_map(|frozen| frozen.actions.iter()),
+            )
+            .flat_map(|(tick, item)| {
+                item.events
+                    .iter()
+                    .filter_map(move |(key, (session_id, event))| match event {
+                        Event::Started {
+                            running_end_tick, ..
+                        } if self
+                            .contexts
+                            .get(key)
+                            .is_some_and(|context| context.session_id == *session_id) =>
+                        {
+                            Some((key.0, key.1, *tick, *running_end_tick))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect::<BTreeSet<_>>();
+        let mut update_actions = converter1(&self.update_actions);
+        retain_scheduled_actions(&mut actions, &update_actions, &starts, self.tick_duration);
+        sort_actions(&mut actions);
+        update_actions.sort_by(|a, b| (a.entity_id, a.layer).cmp(&(b.entity_id, b.layer)));
+
+        // The joint actions that one of the participants has been canceled are already broken
+        let joints = self
+            .joints
+            .iter()
+            .filter(|joint| {
+                self.current_tick < joint.end_tick
+                    && joint.participants.iter().all(|(key, session_id)| {
+                        self.contexts
+                            .get(key)
+                            .map(|context| context.session_id == *session_id)
+                            .unwrap_or(false)
+                    })
+            })
+            .map(|joint| JointActionData {
+                participants: joint.participants.iter().map(|(key, _)| *key).collect(),
+                start_tick: joint.start_tick,
+                end_tick: joint.end_tick,
+            })
+            .collect();
+
+        let timers = self
+            .timers
+            .iter()
+            .filter_map(|(timer_id, timer)| {
+                let mut map = TypedAnyActionMap::default();
+                map.push_as_raw(
+                    timer.type_id,
+                    Arc::clone(&timer.action),
+                    0,
+                    DEFAULT_ACTION_LAYER,
+                    VALID_SESSION_ID,
+                    timer.tick,
+                );
+                let action = converter0(&map, &ActionSessionValidator::default()).pop()?;
+                Some(TimerData {
+                    id: *timer_id,
+                    raw: action.raw,
+                    tick: timer.tick,
+                    interval: timer.interval,
+                })
+            })
+            .collect();
+
+        // The actions of the frozen entities are saved with the ticks in their stopped clock
+        let freezes = self
+            .freezes
+            .iter()
+            .map(|(entity_id, frozen)| {
+                let mut actions = frozen
+                    .actions
+                    .values()
+                    .flat_map(|item| converter0(&item.map, &validator))
+                    .collect::<Vec<_>>();
+                actions.extend(
+                    frozen
+                        .each_tick_actions
+                        .iter()
+                        .flat_map(|(type_id, actions)| converter2(type_id, actions, &validator)),
+                );
+                retain_scheduled_actions(
+                    &mut actions,
+                    &update_actions,
+                    &starts,
+                    self.tick_duration,
+                );
+                sort_actions(&mut actions);
+                // The cancellations of the running sessions are pending until unfreezing
+                let cancels = frozen
+                    .actions
+                    .iter()
+                    .flat_map(|(tick, item)| {
+                        item.cancels
+                            .iter()
+                            .filter(move |(key, _reason)| {
+                                item.events.iter().any(|(event_key, (session_id, event))| {
+                                    event_key == *key
+                                        && matches!(event, Event::Canceled)
+                                        && self.contexts[key].session_id == *session_id
+                                })
+                            })
+                            .map(move |((_entity_id, layer), reason)| CancelData {
+                                layer: *layer,
+                                tick: *tick,
+                                reason: reason.clone(),
+                            })
+                    })
+                    .collect();
+                FreezeData {
+                    entity_id: *entity_id,
+                    tick: frozen.tick,
+                    actions,
+                    cancels,
+                }
+            })
+            .collect();
+        update_actions.append(&mut actions);
+        ActionManagerData {
+            actions: update_actions,
+            tick_duration: self.tick_duration,
+            current_tick: self.current_tick,
+            next_time: self.next_time,
+            joints,
+            timers,
+            time_scale: self.time_scale,
+            time_scale_remainder: self.time_scale_remainder,
+            paused: self.paused,
+            freezes,
+        }
+    }
+
+    pub fn from_data<T>(
+        data: ActionManagerData<T>,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+    ) -> Result<Self, ActionManagerError> {
+        let tick_duration = data.tick_duration;
+        ActionManager::from_data_with_tick_duration(data, tick_duration, converter)
+    }
+
+    pub fn from_data_with_tick_duration<T>(
+        data: ActionManagerData<T>,
+        tick_duration: TimeMilliseconds,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+    ) -> Result<Self, ActionManagerError> {
+        validate_rescaling(&data, tick_duration)?;
+        let current_tick = rescale_tick(data.current_tick, data.tick_duration, tick_duration);
+        let mut manager = ActionManager {
+            time_scale: data.time_scale,
+            time_scale_remainder: data.time_scale_remainder,
+            paused: data.paused,
+            next_time: data.next_time,
+            next_tick: current_tick,
+            current_tick,
+            ..ActionManager::with_tick_duration(tick_duration)
+        };
+        validate_sorted_actions(&data.actions, data.current_tick)?;
+        for freeze in data.freezes.iter() {
+            validate_sorted_actions(&freeze.actions, freeze.tick)?;
+        }
+        manager.load_freezes(
+            &data.freezes,
+            |entity_id| entity_id,
+            |tick| rescale_tick(tick, data.tick_duration, tick_duration),
+        );
+        for action in data.actions.iter() {
+            manager.create_context(action.entity_id, action.layer, current_tick);
+        }
+
+        let entities = EntityRemapContext::default();
+        let mut c = ActionManagerConverter {
+            entities: &entities,
+            remapping_tick: 0,
+            remapping_tick_positive: true,
+            source_tick_duration: data.tick_duration,
+            tick_duration: manager.tick_duration,
+            freezes: &mut manager.freezes,
+            retimers: &mut manager.retimers,
+            actions: &mut manager.actions,
+            contexts: &mut manager.contexts,
+            update_actions: &mut manager.update_actions,
+            each_tick_actions: &mut manager.each_tick_actions,
+            joints: &mut manager.joints,
+            timers: &mut manager.timers,
+            next_timer_id: &mut manager.next_timer_id,
+            timer: None,
+        };
+        for action in data.actions.into_iter() {
+            converter(action, &mut c);
+        }
+        for freeze in data.freezes.into_iter() {
+            for action in freeze.actions.into_iter() {
+                converter(action, &mut c);
+            }
+        }
+        c.load_joints(data.joints)?;
+        c.load_timers(data.timers, converter);
+
+        manager.complete_loaded_contexts()?;
+        Ok(manager)
+    }
+
+    // Checks the End action of every loaded layer, and moves the running end of the layer back to
+    // the current tick if the loaded action hasn't started yet, as the running manager does. The
+    // layer waiting for the joint action keeps the end of it.
+    fn complete_loaded_contexts(&mut self) -> Result<(), ActionManagerError> {
+        for (key, context) in self.contexts.iter_mut() {
+            if context.running_end_tick == Tick::MAX {
+                return Err(ActionManagerError::InvalidDataNoEndAction);
+            }
+            let (entity_id, layer) = key;
+            let current_tick = entity_tick(&self.freezes, self.current_tick, *entity_id);
+            let waiting = self.joints.iter().any(|joint| {
+                current_tick < joint.start_tick
+                    && joint
+                        .participants
+                        .iter()
+                        .any(|(participant, _session_id)| participant == key)
+            });
+            if waiting {
+                continue;
+            }
+            let actions = match self.freezes.get(entity_id) {
+                Some(frozen) => &frozen.actions,
+                None => &self.actions,
+            };
+            let pending = actions
+                .range(..context.running_end_tick)
+                .any(|(_tick, item)| {
+                    item.events.iter().any(|(key, (_session_id, event))| {
+                        *key == (*entity_id, *layer) && matches!(event, Event::Started { .. })
+                    })
+                });
+            if pending {
+                context.running_end_tick = current_tick;
+            }
+        }
+        Ok(())
+    }
+
+    // Creates the context of the loading action, which is completed by the loaded End action
+    fn create_context(&mut self, entity_id: EntityId, layer: ActionLayer, tick: Tick) {
+        if let std::collections::btree_map::Entry::Vacant(entry) =
+            self.contexts.entry((entity_id, layer))
+        {
+            entry.insert(ActionContext {
+                last_tick: tick,
+                running_end_tick: Tick::MAX,
+                session_id: self.session_id_manager.gen(),
+                session_expired_at: tick,
+            });
+        }
+    }
+
+    pub fn controller(&mut self) -> ActionController {
+        ActionController {
+            tick_duration: self.tick_duration,
+            current_tick: self.current_tick,
+            actions: &mut self.actions,
+            update_actions: &mut self.update_actions,
+            each_tick_actions: &mut self.each_tick_actions,
+            contexts: &mut self.contexts,
+            vacated_entities: &mut self.vacated_entities,
+            joints: &mut self.joints,
+            timers: &mut self.timers,
+            next_timer_id: &mut self.next_timer_id,
+            freezes: &mut self.freezes,
+            retimers: &mut self.retimers,
+            session_id_manager: &mut self.session_id_manager,
+            replication: &mut self.replication,
+            dirty_entities: &mut self.dirty_entities,
+        }
+    }
+
+    pub fn snapshot(&self) -> ActionManagerSnapshot {
+        ActionManagerSnapshot {
+            next_time: self.next_time,
+            next_tick: self.next_tick,
+            current_tick: self.current_tick,
+            actions: self.actions.clone(),
+            update_actions: self.update_actions.clone(),
+            each_tick_actions: self.each_tick_actions.clone(),
+            contexts: self.contexts.clone(),
+            vacated_entities: self.vacated_entities.clone(),
+            joints: self.joints.clone(),
+            timers: self.timers.clone(),
+            next_timer_id: self.next_timer_id,
+            time_scale: self.time_scale,
+            time_scale_remainder: self.time_scale_remainder,
+            paused: self.paused,
+            freezes: self.freezes.clone(),
+            retimers: self.retimers.clone(),
+            next_session_id: *self.session_id_manager.current(),
+        }
+    }
+
+    // Unlike from_data, the session ids are restored as is, so the restored manager generates
+    // the same sessions as the manager when the snapshot was taken
+    pub fn restore(&mut self, snapshot: &ActionManagerSnapshot) {
+        self.mark_all_dirty();
+        self.backlog = 0; // The backlog is the elapsed time of the discarded timeline
+        self.next_time = snapshot.next_time;
+        self.next_tick = snapshot.next_tick;
+        self.current_tick = snapshot.current_tick;
+        self.actions = snapshot.actions.clone();
+        self.update_actions = snapshot.update_actions.clone();
+        self.each_tick_actions = snapshot.each_tick_actions.clone();
+        self.contexts = snapshot.contexts.clone();
+        self.vacated_entities = snapshot.vacated_entities.clone();
+        self.joints = snapshot.joints.clone();
+        self.timers = snapshot.timers.clone();
+        self.next_timer_id = snapshot.next_timer_id;
+        self.time_scale = snapshot.time_scale;
+        self.time_scale_remainder = snapshot.time_scale_remainder;
+        self.paused = snapshot.paused;
+        self.freezes = snapshot.freezes.clone();
+        self.retimers.extend(snapshot.retimers.iter());
+        self.session_id_manager.reset(snapshot.next_session_id);
+        self.mark_all_dirty();
+    }
+}
+
+// In-memory copy of the whole state including the running sessions and vacated entities.
+// The action states are shared with the manager, so taking a snapshot doesn't clone them.
+#[derive(Clone)]
+pub struct ActionManagerSnapshot {
+    next_time: TimeMilliseconds,
+    next_tick: Tick,
+    current_tick: Tick,
+    actions: BTreeMap<Tick, BundleForeachTick>,
+    update_actions: TypedAnyActionMapGroupedByEntityId,
+    each_tick_actions: TypedCloneableAnyActionMapGroupedByEntityId,
+    contexts: BTreeMap<(EntityId, ActionLayer), ActionContext>,
+    vacated_entities: VacatedLayers,
+    joints: Vec<JointAction>,
+    timers: BTreeMap<TimerId, ScheduledTimer>,
+    next_timer_id: TimerId,
+    time_scale: TimeScale,
+    time_scale_remainder: u64,
+    paused: bool,
+    freezes: BTreeMap<EntityId, FrozenEntity>,
+    retimers: HashMap<TypeId, ActionRetimer>,
+    next_session_id: ActionSessionId,
+}
+
+impl ActionManagerSnapshot {
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+}
+
+#[derive(Default)]
+pub struct EnqueueOptions {
+    pub each: bool,
+}
+
+pub struct ActionManagerConverter<'a> {
+    entities: &'a EntityRemapContext,
+    remapping_tick: Tick,
+    remapping_tick_positive: bool,
+    source_tick_duration: TimeMilliseconds, // The tick duration of the saved data
+    tick_duration: TimeMilliseconds,
+    freezes: &'a mut BTreeMap<EntityId, FrozenEntity>,
+    retimers: &'a mut HashMap<TypeId, ActionRetimer>,
+    actions: &'a mut BTreeMap<Tick, BundleForeachTick>,
+    contexts: &'a mut BTreeMap<(EntityId, ActionLayer), ActionContext>,
+    update_actions: &'a mut TypedAnyActionMapGroupedByEntityId,
+    each_tick_actions: &'a mut TypedCloneableAnyActionMapGroupedByEntityId,
+    joints: &'a mut Vec<JointAction>,
+    timers: &'a mut BTreeMap<TimerId, ScheduledTimer>,
+    next_timer_id: &'a mut TimerId,
+    timer: Option<TimerData<()>>, // Loads the actions as this timer, which is given by load_timers
+}
+
+pub struct ActionController<'a> {
+    tick_duration: TimeMilliseconds,
+    current_tick: Tick,
+    actions: &'a mut BTreeMap<Tick, BundleForeachTick>,
+    update_actions: &'a mut TypedAnyActionMapGroupedByEntityId,
+    each_tick_actions: &'a mut TypedCloneableAnyActionMapGroupedByEntityId,
+    contexts: &'a mut BTreeMap<(EntityId, ActionLayer), ActionContext>,
+    vacated_entities: &'a mut VacatedLayers,
+    joints: &'a mut Vec<JointAction>,
+    timers: &'a mut BTreeMap<TimerId, ScheduledTimer>,
+    next_timer_id: &'a mut TimerId,
+    freezes: &'a mut BTreeMap<EntityId, FrozenEntity>,
+    retimers: &'a mut HashMap<TypeId, ActionRetimer>,
+    session_id_manager: &'a mut IdManager<ActionSessionId>,
+    replication: &'a mut Option<Vec<ReplicationRecord>>,
+    dirty_entities: &'a mut Option<DirtyLayers>,
+}
+
+impl<'a> ActionController<'a> {
+    #[inline]
+    pub fn enqueue<T>(&mut self, entity_id: EntityId, raw: Arc<T>, duration: TimeMilliseconds)
+    where
+        T: 'static,
+    {
+        self.enqueue_with_options(entity_id, raw, duration, EnqueueOptions::default());
+    }
+
+    #[inline]
+    pub fn enqueue_with_options<T>(
+        &mut self,
+        entity_id: EntityId,
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static,
+    {
+        self.enqueue_to_layer(entity_id, DEFAULT_ACTION_LAYER, raw, duration, options);
+    }
+
+    pub fn enqueue_to_layer<T>(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static,
+    {
+        let start_tick = self
+            .contexts
+            .get(&(entity_id, layer))
+            .unwrap_or_else(|| panic!("layer {} of entity {} is not attached", layer, entity_id))
+            .last_tick;
+        let end_tick = start_tick + duration / self.tick_duration;
+        self.schedule(entity_id, layer, raw, start_tick, end_tick, options.each);
+    }
+
+    fn schedule<T>(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        raw: Arc<T>,
+        start_tick: Tick,
+        end_tick: Tick,
+        each: bool,
+    ) where
+        T: 'static,
+    {
+        let type_id = TypeId::of::<T>();
+        self.retimers
+            .entry(type_id)
+            .or_insert_with(ActionRetimer::of::<T>);
+        let current_tick = entity_tick(self.freezes, self.current_tick, entity_id);
+        let actions = bundles_of(self.actions, self.freezes, entity_id);
+        let context = self.contexts.get_mut(&(entity_id, layer)).unwrap();
+
+        debug_assert!(
+            current_tick <= context.running_end_tick,
+            "{} <= {}",
+            current_tick,
+            context.running_end_tick
+        );
+        debug_assert!(
+            context.running_end_tick <= context.last_tick,
+            "{} <= {}",
+            context.running_end_tick,
+            context.last_tick
+        );
+
+        self.vacated_entities.remove(&(entity_id, layer));
+        if let Some(dirty) = self.dirty_entities {
+            dirty.insert((entity_id, layer));
+        }
+
+        let start = start_tick * self.tick_duration;
+        let end = end_tick.wrapping_mul(self.tick_duration);
+        {
+            let item = actions.entry(start_tick).or_insert_with(Default::default);
+            item.map.push(
+                Action {
+                    raw: Arc::clone(&raw),
+                    entity_id,
+                    layer,
+                    ty: ActionType::Start {
+                        start: start_tick,
+                        end: end_tick,
+                        each,
+                    },
+                },
+                context.session_id,
+            );
+            item.events.push_back((
+                (entity_id, layer),
+                (
+                    context.session_id,
+                    Event::Started {
+                        type_id,
+                        type_name: std::any::type_name::<T>(),
+                        update_action: Arc::new(Box::new(Action {
+                            raw: Arc::clone(&raw),
+                            entity_id,
+                            layer,
+                            ty: ActionType::Update { start, end },
+                        })),
+                        each_action: if each {
+                            Some(Arc::new(Box::new(Action {
+                                raw: Arc::clone(&raw),
+                                entity_id,
+                                layer,
+                                ty: ActionType::EachTick {
+                                    start: start_tick,
+                                    end: end_tick,
+                                },
+                            })))
+                        } else {
+                            None
+                        },
+                        running_end_tick: end_tick,
+                    },
+                ),
+            ));
+        }
+        {
+            let item = actions.entry(end_tick).or_insert_with(Default::default);
+            item.map.push(
+                Action {
+                    raw: Arc::clone(&raw),
+                    entity_id,
+                    layer,
+                    ty: ActionType::End {
+                        start: start_tick,
+                        end: end_tick,
+                    },
+                },
+                context.session_id,
+            );
+            item.events
+                .push_back(((entity_id, layer), (context.session_id, Event::Ended)));
+        }
+        context.last_tick = end_tick;
+
+        if let Some(records) = self.replication {
+            let action: ArcAction<T> = Action {
+                raw,
+                entity_id,
+                layer,
+                ty: ActionType::Start {
+                    start: start_tick,
+                    end: end_tick,
+                    each,
+                },
+            };
+            records.push(ReplicationRecord::Schedule(
+                type_id,
+                Arc::new(Box::new(action)),
+            ));
+        }
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+
+    #[inline]
+    pub fn cancel(&mut self, entity_id: EntityId, immediate: bool) {
+        self.cancel_layer(entity_id, DEFAULT_ACTION_LAYER, immediate);
+    }
+
+    #[inline]
+    pub fn cancel_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        self.cancel_layer_with_reason(entity_id, DEFAULT_ACTION_LAYER, immediate, reason);
+    }
+
+    #[inline]
+    pub fn cancel_layer(&mut self, entity_id: EntityId, layer: ActionLayer, immediate: bool) {
+        self.cancel_layer_with_reason(entity_id, layer, immediate, CancelReason::Unspecified);
+    }
+
+    // The cancellation is propagated to the other participants of the joint actions with
+    // CancelReason::JointPartnerCanceled
+    pub fn cancel_layer_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        let session_id = self.contexts[&(entity_id, layer)].session_id;
+        let timing = if immediate {
+            CancelTiming::Immediate
+        } else {
+            CancelTiming::RunningEnd
+        };
+        self.cancel_sessions(vec![((entity_id, layer), session_id)], timing, reason);
+    }
+
+    // Cancels the actions of the layer after the tick, which is clamped between the current tick
+    // of the entity and the end of the running action
+    pub fn cancel_layer_at(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        tick: Tick,
+        reason: CancelReason,
+    ) {
+        let session_id = self.contexts[&(entity_id, layer)].session_id;
+        self.cancel_sessions(
+            vec![((entity_id, layer), session_id)],
+            CancelTiming::At(tick),
+            reason,
+        );
+    }
+
+    fn cancel_sessions(
+        &mut self,
+        queue: Vec<((EntityId, ActionLayer), ActionSessionId)>,
+        timing: CancelTiming,
+        reason: CancelReason,
+    ) {
+        let mut queue = queue
+            .into_iter()
+            .map(|(key, session_id)| (key, session_id, reason.clone()))
+            .collect::<Vec<_>>();
+        while let Some(((entity_id, layer), session_id, reason)) = queue.pop() {
+            let context = match self.contexts.get_mut(&(entity_id, layer)) {
+                Some(context) if context.session_id == session_id => context,
+                _ => continue,
+            };
+            context.session_id = self.session_id_manager.gen();
+            if let Some(dirty) = self.dirty_entities {
+                dirty.insert((entity_id, layer));
+            }
+            let current_tick = entity_tick(self.freezes, self.current_tick, entity_id);
+            let tick = match timing {
+                CancelTiming::Immediate => current_tick,
+                CancelTiming::RunningEnd => context.running_end_tick,
+                CancelTiming::At(tick) => tick.min(context.running_end_tick).max(current_tick),
+            };
+            context.last_tick = tick;
+            context.running_end_tick = tick;
+            context.session_expired_at = tick;
+
+            let item = bundles_of(self.actions, self.freezes, entity_id)
+                .entry(tick)
+                .or_insert_with(Default::default);
+            item.cancels.insert((entity_id, layer), reason.clone());
+            item.events
+                .push_back(((entity_id, layer), (context.session_id, Event::Canceled)));
+            if let Some(records) = self.replication {
+                records.push(ReplicationRecord::Cancel(entity_id, layer, tick, reason));
+            }
+
+            queue.extend(
+                self.find_joint_participants((entity_id, layer), session_id, tick)
+                    .into_iter()
+                    .map(|(key, session_id)| (key, session_id, CancelReason::JointPartnerCanceled)),
+            );
+        }
+    }
+}
+
+// The clock of the frozen entity stops at the frozen tick
+fn entity_tick(
+    freezes: &BTreeMap<EntityId, FrozenEntity>,
+    current_tick: Tick,
+    entity_id: EntityId,
+) -> Tick {
+    freezes
+        .get(&entity_id)
+        .map(|frozen| frozen.tick)
+        .unwrap_or(current_tick)
+}
+
+fn bundles_of<'a>(
+    actions: &'a mut BTreeMap<Tick, BundleForeachTick>,
+    freezes: &'a mut BTreeMap<EntityId, FrozenEntity>,
+    entity_id: EntityId,
+) -> &'a mut BTreeMap<Tick, BundleForeachTick> {
+    match freezes.get_mut(&entity_id) {
+        Some(frozen) => &mut frozen.actions,
+        None => actions,
+    }
+}
+
+fn retime_action<T>(
+    action: &Arc<Box<dyn Any>>,
+    retime: &dyn Fn(&ActionType) -> ActionType,
+) -> Arc<Box<dyn Any>>
+where
+    T: 'static,
+{
+    let action = action.downcast_ref::<ArcAction<T>>().unwrap();
+    Arc::new(Box::new(Action {
+        raw: Arc::clone(&action.raw),
+        entity_id: action.entity_id,
+        layer: action.layer,
+        ty: retime(&action.ty),
+    }))
+}
+
+// The validator passes the actions of the superseded sessions until the latest expiry, so only the
+// actions which start and end in the saved data are retained besides the running ones
+fn retain_scheduled_actions<T>(
+    actions: &mut Vec<Action<T>>,
+    update_actions: &[Action<T>],
+    starts: &BTreeSet<(EntityId, ActionLayer, Tick, Tick)>,
+    tick_duration: TimeMilliseconds,
+) {
+    actions.retain(|action| match action.ty {
+        ActionType::Start { start, end, .. } => {
+            starts.contains(&(action.entity_id, action.layer, start, end))
+        }
+        _ => true,
+    });
+    let mut ranges = actions
+        .iter()
+        .filter_map(|action| match action.ty {
+            ActionType::Start { start, end, .. } => Some((
+                action.entity_id,
+                action.layer,
+                start.wrapping_mul(tick_duration),
+                end.wrapping_mul(tick_duration),
+            )),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    ranges.extend(update_actions.iter().filter_map(|action| match action.ty {
+        // The start of the running action wraps below zero when the data is shifted back by
+        // ActionManager::load_data, so the ticks are compared in the wrapped times
+        ActionType::Update { start, end } => Some((action.entity_id, action.layer, start, end)),
+        _ => None,
+    }));
+    actions.retain(|action| match action.ty {
+        ActionType::End { start, end } => ranges.contains(&(
+            action.entity_id,
+            action.layer,
+            start.wrapping_mul(tick_duration),
+            end.wrapping_mul(tick_duration),
+        )),
+        _ => true,
+    });
+
+    let ends = actions
+        .iter()
+        .filter_map(|action| match action.ty {
+            ActionType::End { start, end } => Some((action.entity_id, action.layer, start, end)),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    actions.retain(|action| match action.ty {
+        ActionType::Start { start, end, .. } | ActionType::EachTick { start, end } => {
+            ends.contains(&(action.entity_id, action.layer, start, end))
+        }
+        _ => true,
+    });
+}
+
+// The End actions precede the Start actions in the same tick, and the zero-length actions are
+// loaded before the action starting after them
+fn sort_actions<T>(actions: &mut [Action<T>]) {
+    let key = |action: &Action<T>| {
+        let (order, start, end) = match action.ty {
+            ActionType::End { start, end } => (0, start, end),
+            ActionType::EachTick { start, end } => (1, start, end),
+            ActionType::Start { start, end, .. } => (2, start, end),
+            _ => unreachable!(),
+        };
+        (
+            action.tick().unwrap(),
+            action.entity_id,
+            action.layer,
+            order,
+            start,
+            end,
+        )
+    };
+    actions.sort_by_key(key);
+}
+
+// The update actions come first, and the others are sorted by the tick
+fn validate_sorted_actions<T>(
+    actions: &[Action<T>],
+    current_tick: Tick,
+) -> Result<(), ActionManagerError> {
+    let mut tick_validation: Tick = current_tick;
+    for action in actions.iter() {
+        match action.ty {
+            ActionType::Start { .. } | ActionType::End { .. } | ActionType::EachTick { .. } => {
+                let tick = action.tick().unwrap();
+                if tick < tick_validation {
+                    return Err(ActionManagerError::InvalidDataCauseBySortedActions);
+                }
+                tick_validation = tick;
+            }
+            ActionType::Update { .. } => {
+                if tick_validation != current_tick {
+                    return Err(ActionManagerError::InvalidDataCauseBySortedActions);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// The tick durations must be positive, and the update actions must fit in the rescaled time
+fn validate_rescaling<T>(
+    data: &ActionManagerData<T>,
+    tick_duration: TimeMilliseconds,
+) -> Result<(), ActionManagerError> {
+    if data.tick_duration == 0 || tick_duration == 0 {
+        return Err(ActionManagerError::InvalidDataZeroTickDuration);
+    }
+    let actions = data
+        .actions
+        .iter()
+        .chain(data.freezes.iter().flat_map(|freeze| freeze.actions.iter()));
+    for action in actions {
+        if action
+            .ty
+            .rescale(data.tick_duration, tick_duration)
+            .is_none()
+        {
+            return Err(ActionManagerError::InvalidDataTickOverflow);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ActionManagerError {
+    InvalidDataCauseBySortedActions,
+    InvalidDataNoEndAction,
+    InvalidDataNoJointParticipant,
+    InvalidDataZeroTickDuration,
+    InvalidDataTickOverflow,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActionManagerData<T> {
+    actions: Vec<Action<T>>,
+    tick_duration: TimeMilliseconds,
+    current_tick: Tick,
+    next_time: TimeMilliseconds,
+    #[serde(default)]
+    joints: Vec<JointActionData>,
+    #[serde(default = "Vec::new")] // Avoids the Default bound of T
+    timers: Vec<TimerData<T>>,
+    #[serde(default = "default_time_scale")]
+    time_scale: TimeScale,
+    #[serde(default)]
+    time_scale_remainder: u64,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default = "Vec::new")]
+    freezes: Vec<FreezeData<T>>,
+}
+
+fn default_time_scale() -> TimeScale {
+    DEFAULT_TIME_SCALE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FreezeData<T> {
+    entity_id: EntityId,
+    tick: Tick,
+    actions: Vec<Action<T>>,
+    #[serde(default)]
+    cancels: Vec<CancelData>,
+}
+
+// The cancellation pending in the stopped clock of the frozen entity
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CancelData {
+    layer: ActionLayer,
+    tick: Tick,
+    reason: CancelReason,
+}
+
+// The actions of an entity taken out of the data by ActionManagerData::split_entities
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntityActionData<T> {
+    actions: Vec<Action<T>>,
+    freeze: Option<FreezeData<T>>,
+}
+
+impl<T> Default for EntityActionData<T> {
+    fn default() -> Self {
+        EntityActionData {
+            actions: Vec::new(),
+            freeze: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimerData<T> {
+    id: TimerId,
+    raw: T,
+    tick: Tick,
+    interval: Option<Tick>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JointActionData {
+    participants: Vec<(EntityId, ActionLayer)>,
+    start_tick: Tick,
+    end_tick: Tick,
+}
+
+impl<T> ActionManagerData<T> {
+    // Creates the data from the actions without any joint actions, timers and frozen entities.
+    // The update actions are for the running actions, and each of the actions must be paired
+    // with the End action.
+    pub fn new(
+        actions: Vec<Action<T>>,
+        tick_duration: TimeMilliseconds,
+        current_tick: Tick,
+    ) -> Self {
+        let (mut update_actions, mut actions): (Vec<_>, Vec<_>) = actions
+            .into_iter()
+            .partition(|action| matches!(action.ty, ActionType::Update { .. }));
+        update_actions.sort_by_key(|action| (action.entity_id, action.layer));
+        sort_actions(&mut actions);
+        update_actions.append(&mut actions);
+        ActionManagerData {
+            actions: update_actions,
+            tick_duration,
+            current_tick,
+            next_time: current_tick * tick_duration,
+            joints: Vec::new(),
+            timers: Vec::new(),
+            time_scale: DEFAULT_TIME_SCALE,
+            time_scale_remainder: 0,
+            paused: false,
+            freezes: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn tick_duration(&self) -> TimeMilliseconds {
+        self.tick_duration
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+
+    // Takes out the actions and the frozen states by the entity. The joints and the timers are
+    // left in the data.
+    pub fn split_entities(mut self) -> (Self, BTreeMap<EntityId, EntityActionData<T>>) {
+        let mut entities: BTreeMap<EntityId, EntityActionData<T>> = BTreeMap::new();
+        for action in std::mem::take(&mut self.actions) {
+            entities
+                .entry(action.entity_id)
+                .or_default()
+                .actions
+                .push(action);
+        }
+        for freeze in std::mem::take(&mut self.freezes) {
+            let entity_id = freeze.entity_id;
+            entities.entry(entity_id).or_default().freeze = Some(freeze);
+        }
+        (self, entities)
+    }
+
+    // Keeps the actions, the frozen states and the joints of the entities. The joints with any
+    // other participants and the timers are dropped.
+    pub fn retain_entities(&mut self, entity_ids: &BTreeSet<EntityId>) {
+        self.actions
+            .retain(|action| entity_ids.contains(&action.entity_id));
+        self.freezes
+            .retain(|freeze| entity_ids.contains(&freeze.entity_id));
+        self.joints.retain(|joint| {
+            joint
+                .participants
+                .iter()
+                .all(|(entity_id, _)| entity_ids.contains(entity_id))
+        });
+        self.timers.clear();
+    }
+
+    // Puts back the entities in the same order as ActionManager::to_data
+    pub fn merge_entities(mut self, entities: BTreeMap<EntityId, EntityActionData<T>>) -> Self {
+        let mut actions = std::mem::take(&mut self.actions);
+        for (_entity_id, mut entity) in entities {
+            actions.append(&mut entity.actions);
+            if let Some(freeze) = entity.freeze {
+                self.freezes.push(freeze);
+            }
+        }
+        let (mut update_actions, mut actions): (Vec<_>, Vec<_>) = actions
+            .into_iter()
+            .partition(|action| matches!(action.ty, ActionType::Update { .. }));
+        update_actions.sort_by_key(|action| (action.entity_id, action.layer));
+        sort_actions(&mut actions);
+        update_actions.append(&mut actions);
+        self.actions = update_actions;
+        self.freezes.sort_by_key(|freeze| freeze.entity_id);
+        self
+    }
+}
+
+#[cfg(test)]
+#[macro_use]
+mod test {
+    use crate::define_actions;
+    use crate::v2::action::collection::TypedAnyActionMap;
+    use crate::v2::action::manager::{
+        ActionManager, ActionManagerData, ActionManagerError, ActionSessionValidator,
+        CatchUpPolicy, EnqueueOptions, PullActionResult, RemapContext, Tick,
+    };
+
+    use crate::v2::action::{ActionType, ArcAction};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeSet;
+    use std::fmt::Debug;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+    pub struct MoveState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+    pub struct JumpState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+    pub struct TalkState;
+
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct SnapshotResult<'a> {
+        tag: &'static str,
+        current_tick: Tick,
+        move_actions: Option<Vec<&'a ArcAction<MoveState>>>,
+        jump_actions: Option<Vec<&'a ArcAction<JumpState>>>,
+        talk_actions: Option<Vec<&'a ArcAction<TalkState>>>,
+    }
+
+    impl<'a> SnapshotResult<'a> {
+        pub fn from_result(
+            tag: &'static str,
+            current_tick: Tick,
+            result: &'a PullActionResult,
+            validator: &ActionSessionValidator,
+        ) -> Self {
+            SnapshotResult {
+                tag,
+                current_tick,
+                move_actions: result.map.get(validator),
+                jump_actions: result.map.get(validator),
+                talk_actions: result.map.get(validator),
+            }
+        }
+    }
+
+    define_actions!(
+        TestAction,
+        (Move, MoveState),
+        (Jump, JumpState),
+        (Talk, TalkState)
+    );
+
+    #[allow(dead_code)]
+    fn debug_pull_action_result(
+        tag: &str,
+        result: &Option<PullActionResult>,
+        validator: &ActionSessionValidator,
+    ) {
+        if let Some(result) = result {
+            println!(
+                "[{}] move_states: {:?}",
+                tag,
+                result.map.get::<MoveState>(validator)
+            );
+            println!(
+                "[{}] jump_states: {:?}",
+                tag,
+                result.map.get::<JumpState>(validator)
+            );
+            println!(
+                "[{}] talk_states: {:?}",
+                tag,
+                result.map.get::<TalkState>(validator)
+            );
+        }
+    }
+
+    fn assert_snapshot(manager: &mut ActionManager) {
+        while let Some(actions) = manager.pull_actions() {
+            insta::assert_debug_snapshot!(SnapshotResult {
+                current_tick: manager.current_tick(),
+                tag: "changes",
+                move_actions: actions.map.get(&manager.validator()),
+                jump_actions: actions.map.get(&manager.validator()),
+                talk_actions: actions.map.get(&manager.validator())
+            });
+        }
+        assert_snapshot_update(manager);
+    }
+
+    fn assert_snapshot_update(manager: &mut ActionManager) {
+        let actions = manager.pull_updates();
+
+        insta::assert_debug_snapshot!(SnapshotResult {
+            current_tick: manager.current_tick(),
+            tag: "updates",
+            move_actions: actions.get(),
+            jump_actions: actions.get(),
+            talk_actions: actions.get()
+        });
+    }
+
+    fn assert_actions<T>(
+        actions0: Option<Vec<&ArcAction<T>>>,
+        actions1: Option<Vec<&ArcAction<T>>>,
+        current_tick0: Tick,
+        current_tick1: Tick,
+    ) where
+        T: 'static + Eq + Debug,
+    {
+        if actions0.is_none() != actions1.is_none() {
+            println!(
+                "current_tick0: {:?}, current_tick1: {:?}",
+                current_tick0, current_tick1
+            );
+            println!("{:?} = {:?}", actions0, actions1);
+            assert_eq!(actions0.is_none(), actions1.is_none());
+        }
+        if let Some(actions0) = actions0 {
+            let actions1 = actions1.unwrap();
+            assert_eq!(actions0.len(), actions1.len());
+            for (action0, action1) in actions0.iter().zip(actions1.iter()) {
+                assert_eq!(action0.entity_id, action1.entity_id);
+                assert_eq!(action0.ty, action1.ty);
+                assert_eq!(action0.raw, action1.raw);
+            }
+        }
+    }
+
+    fn assert_action_map<T>(
+        map0: &TypedAnyActionMap,
+        map1: &TypedAnyActionMap,
+        manager0: &ActionManager,
+        manager1: &ActionManager,
+        current_tick0: Tick,
+        current_tick1: Tick,
+    ) where
+        T: 'static + Eq + Debug,
+    {
+        let actions0 = map0.get::<T>(&manager0.validator());
+        let actions1 = map1.get::<T>(&manager1.validator());
+        assert_actions(actions0, actions1, current_tick0, current_tick1);
+    }
+
+    pub fn assert_managers(
+        manager0: &mut ActionManager,
+        manager1: &mut ActionManager,
+    ) -> (usize, Option<BTreeSet<EntityId>>) {
+        let mut tick_count = 0;
+        loop {
+            let result0 = manager0.pull_actions();
+            let result1 = manager1.pull_actions();
+            let current_tick0 = manager0.current_tick();
+            let current_tick1 = manager0.current_tick();
+            if result0.is_none() != result1.is_none() {
+                debug_pull_action_result("result0", &result0, &manager0.validator());
+                debug_pull_action_result("result1", &result1, &manager0.validator());
+                assert_eq!(result0.is_none(), result1.is_none());
+            }
+
+            if let Some(result0) = result0 {
+                let result1 = result1.unwrap();
+                assert_eq!(result0.canceled_layers, result1.canceled_layers);
+                assert_action_map::<MoveState>(
+                    &result0.map,
+                    &result1.map,
+                    manager0,
+                    manager1,
+                    current_tick0,
+                    current_tick1,
+                );
+                assert_action_map::<JumpState>(
+                    &result0.map,
+                    &result1.map,
+                    manager0,
+                    manager1,
+                    current_tick0,
+                    current_tick1,
+                );
+                assert_action_map::<TalkState>(
+                    &result0.map,
+                    &result1.map,
+                    manager0,
+                    manager1,
+                    current_tick0,
+                    current_tick1,
+                );
+            } else {
+                break;
+            }
+            tick_count += 1;
+
+            let vacated_entities0 = manager0.pull_vacated_entities();
+            let vacated_entities1 = manager1.pull_vacated_entities();
+
+            assert_eq!(vacated_entities0, vacated_entities1);
+
+            if !vacated_entities0.is_empty() {
+                return (tick_count, Some(vacated_entities0));
+            }
+        }
+
+        let map0 = manager0.pull_updates();
+        let map1 = manager1.pull_updates();
+        let current_tick0 = manager0.current_tick();
+        let current_tick1 = manager0.current_tick();
+
+        assert_actions::<MoveState>(map0.get(), map1.get(), current_tick0, current_tick1);
+        assert_actions::<JumpState>(map0.get(), map1.get(), current_tick0, current_tick1);
+        assert_actions::<TalkState>(map0.get(), map1.get(), current_tick0, current_tick1);
+
+        (tick_count, None)
+    }
+
+    #[test]
+    fn test() {
+        // --- Millis: 0, Tick: 0 ---- //
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(1, Arc::new(JumpState), 2000); // tick: 30(45)
+        manager.enqueue(1, Arc::new(TalkState), 1500); // tick: 22(67)
+
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+        manager.enqueue(2, Arc::new(JumpState), 1500); // tick: 22(29)
+        manager.enqueue(2, Arc::new(TalkState), 2000); // tick: 30(59)
+
+        assert_snapshot(&mut manager);
+
+        // --- Tick: 3 ---- //
+        manager.update(200);
+
+        assert_snapshot(&mut manager);
+
+        // --- Tick: 7 ---- //
+        manager.update(300);
+
+        assert_snapshot(&mut manager);
+
+        // --- Tick: 60
+        manager.update(3328);
+
+        assert_snapshot(&mut manager);
+    }
+
+    #[test]
+    fn test_vacated_entities() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+
+        assert!(manager.pull_vacated_entities().is_empty());
+        assert!(manager.has_some_actions(1));
+        assert!(manager.has_some_actions(2));
+
+        // Just ended time
+        manager.update(500);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert!(manager.pull_actions().is_none());
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert!(!manager.has_some_actions(2));
+
+        manager.enqueue(2, Arc::new(JumpState), 2000);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert!(manager.pull_actions().is_none());
+        assert!(manager.pull_vacated_entities().is_empty());
+
+        manager.update(10000);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert!(!manager.has_some_actions(1));
+        assert!(manager.has_some_actions(2));
+
+        manager.enqueue(1, Arc::new(TalkState), 3000);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert!(manager.pull_vacated_entities().is_empty());
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert!(manager.has_some_actions(1));
+        assert!(!manager.has_some_actions(2));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(1, Arc::new(JumpState), 2000); // tick: 30(45)
+        manager.enqueue(1, Arc::new(TalkState), 1500); // tick: 22(67)
+
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+        manager.enqueue(2, Arc::new(JumpState), 1500); // tick: 22(29)
+        manager.enqueue(2, Arc::new(TalkState), 2000); // tick: 30(59)
+        manager.enqueue(2, Arc::new(MoveState), 3000); // tick: 45(104)
+
+        manager.update(2112); // tick: 32
+
+        let result = manager.pull_actions(); // tick: 0 - start1 start2
+        assert_eq!(manager.current_tick(), 0);
+        assert_eq!(result.unwrap().cancels.len(), 0);
+        let result = manager.pull_actions(); // tick: 7 - end2 start2
+        assert_eq!(result.unwrap().cancels.len(), 0);
+        let result = manager.pull_actions(); // tick: 15 - end1 end2
+        assert_eq!(result.unwrap().cancels.len(), 0);
+        let result = manager.pull_actions(); // tick: 29 - end2 start2
+        assert_eq!(result.unwrap().cancels.len(), 0);
+
+        assert_eq!(manager.current_tick(), 29);
+
+        manager.cancel(1, true);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+
+        manager.enqueue(1, Arc::new(TalkState), 2000); // tick: 30(59)
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+        assert_eq!(result.cancels.len(), 0);
+
+        assert!(manager.pull_actions().is_none());
+
+        manager.update(396); // tick: 38
+
+        assert!(manager.pull_actions().is_none());
+        assert_eq!(manager.current_tick(), 38);
+
+        manager.cancel(2, false);
+
+        assert!(manager.pull_actions().is_none());
+
+        manager.update(132); // tick: 40
+
+        assert!(manager.pull_actions().is_none());
+        assert_eq!(manager.current_tick(), 40);
+
+        manager.update(1254); // tick: 59
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+        assert_eq!(result.cancels.len(), 0);
+        assert_eq!(manager.pull_vacated_entities().len(), 0);
+
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 59);
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_cancel_of_detached_or_superseded_session() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 0-5
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 0-5
+        manager.cancel(1, false); // tick: 5
+        manager.cancel(2, false); // tick: 5
+        manager.cancel(2, true); // tick: 0
+        manager.detach(1);
+
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        manager.update(500);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 5);
+        assert!(result.cancels.is_empty());
+        assert!(result.canceled_layers.is_empty());
+        assert!(manager.pull_vacated_entities().is_empty());
+    }
+
+    #[test]
+    fn test_zero_time_action() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+
+        manager.update(462);
+
+        manager.pull_actions().unwrap();
+        manager.pull_actions().unwrap();
+
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        manager.enqueue(2, Arc::new(JumpState), 0);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 7);
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        manager.enqueue(2, Arc::new(TalkState), 1000);
+
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 7);
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        manager.update(0);
+
+        manager.cancel(2, true);
+
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 7);
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert_eq!(result.cancels.into_iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_detach() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue(2, Arc::new(MoveState), 500); // tick: 7
+        manager.enqueue_with_options(2, Arc::new(MoveState), 500, EnqueueOptions { each: true });
+
+        manager.update(396);
+
+        manager.pull_actions().unwrap();
+
+        manager.detach(1);
+        manager.detach(2);
+
+        assert!(manager.contexts.get(&(1, 0)).is_none());
+        assert!(manager.contexts.get(&(2, 0)).is_none());
+        assert!(manager.each_tick_actions.is_empty());
+        assert_eq!(manager.pull_vacated_entities().len(), 0);
+        assert!(manager.pull_updates().get::<MoveState>().is_none());
+
+        assert!(manager.pull_actions().is_none());
+        assert_eq!(manager.pull_vacated_entities().len(), 0);
+
+        manager.update(1000);
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        let result = manager.pull_actions().unwrap();
+        insta::assert_debug_snapshot!(SnapshotResult::from_result(
+            "changes",
+            manager.current_tick(),
+            &result,
+            &manager.validator()
+        ));
+
+        assert!(manager.pull_actions().is_none());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 3000);
+        manager.enqueue(1, Arc::new(JumpState), 500);
+        manager.enqueue_with_options(1, Arc::new(TalkState), 1500, EnqueueOptions { each: true });
+        manager.enqueue(1, Arc::new(JumpState), 2000);
+        manager.enqueue(1, Arc::new(MoveState), 3000);
+        manager.enqueue(1, Arc::new(TalkState), 1000);
+
+        manager.enqueue(2, Arc::new(MoveState), 500);
+        manager.enqueue(2, Arc::new(TalkState), 1000);
+        manager.enqueue(2, Arc::new(JumpState), 2000);
+        manager.enqueue(2, Arc::new(TalkState), 2500);
+        manager.enqueue_with_options(2, Arc::new(MoveState), 4000, EnqueueOptions { each: true });
+        manager.enqueue(2, Arc::new(JumpState), 2000);
+
+        manager.update(500); // tick: 7
+
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        insta::assert_debug_snapshot!(data);
+
+        let mut new_manager = ActionManager::from_data(data, convert_from_actions).unwrap();
+
+        manager.update(10000);
+        new_manager.update(10000);
+
+        let (tick_count, _) = assert_managers(&mut manager, &mut new_manager);
+        println!("Tick count = {}", tick_count);
+    }
+
+    #[test]
+    fn test_serialization_with_superseded_session() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 0-5
+        manager.enqueue(1, Arc::new(JumpState), 500); // tick: 5-10
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        manager.cancel(1, false); // tick: 5
+
+        // The jump of the canceled session starts at the expiry of the session but never ends
+        let data = manager.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        assert_eq!(
+            data.actions
+                .iter()
+                .map(|action| action.ty)
+                .collect::<Vec<_>>(),
+            vec![
+                ActionType::Update { start: 0, end: 500 },
+                ActionType::End { start: 0, end: 5 },
+            ]
+        );
+
+        let mut manager = ActionManager::from_data(data, convert_from_actions).unwrap();
+        manager.update(400);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.current_tick(), 5);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert!(manager.pull_updates().get::<JumpState>().is_none());
+    }
+
+    #[test]
+    fn test_tick_duration() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 10
+        manager.enqueue(1, Arc::new(JumpState), 500); // tick: 5(15)
+
+        manager.update(999); // tick: 9
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.current_tick(), 9);
+
+        manager.update(1); // tick: 10
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 10);
+        assert!(result.map.get::<MoveState>(&manager.validator()).is_some());
+        assert!(result.map.get::<JumpState>(&manager.validator()).is_some());
+    }
+
+    #[test]
+    fn test_update_until() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 300); // tick: 0-3
+
+        manager.update_until(550, 2);
+        assert_eq!(manager.next_time(), 250);
+        assert_eq!(manager.backlog(), 300);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.current_tick(), 2);
+
+        // The carried over time is processed once the limit is raised
+        manager.update_until(0, 10);
+        assert_eq!(manager.next_time(), 550);
+        assert_eq!(manager.backlog(), 0);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 3);
+        assert!(result.map.get::<MoveState>(&manager.validator()).is_some());
+    }
+
+    #[test]
+    fn test_clock_without_actions() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+
+        manager.update(500); // tick: 5
+        assert!(manager.pull_actions().is_none());
+        assert_eq!(manager.current_tick(), 5);
+
+        // The layer attached while nothing is queued starts at the current tick
+        manager.attach(2);
+        manager.pull_vacated_entities();
+        manager.enqueue(2, Arc::new(MoveState), 300); // tick: 5-8
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(manager.current_tick(), 5);
+        assert_eq!(
+            result
+                .map
+                .get::<MoveState>(&manager.validator())
+                .unwrap()
+                .iter()
+                .map(|action| *action.ty())
+                .collect::<Vec<_>>(),
+            vec![ActionType::Start {
+                start: 5,
+                end: 8,
+                each: false
+            }]
+        );
+    }
+
+    #[test]
+    fn test_serialization_with_zero_tick_duration() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 10
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        assert!(matches!(
+            ActionManager::from_data_with_tick_duration(data, 0, convert_from_actions),
+            Err(ActionManagerError::InvalidDataZeroTickDuration)
+        ));
+
+        let mut data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        data.tick_duration = 0;
+        let mut new_manager = ActionManager::with_tick_duration(100);
+        assert!(matches!(
+            new_manager.load_data(data, convert_from_actions, &mut RemapContext::default()),
+            Err(ActionManagerError::InvalidDataZeroTickDuration)
+        ));
+    }
+
+    #[test]
+    fn test_serialization_with_rescaling() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 10
+        manager.enqueue_with_options(1, Arc::new(JumpState), 500, EnqueueOptions { each: true }); // tick: 5(15)
+
+        manager.update(1200); // tick: 12
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+
+        let mut new_manager =
+            ActionManager::from_data_with_tick_duration(data, 50, convert_from_actions).unwrap();
+        assert_eq!(new_manager.tick_duration(), 50);
+        assert_eq!(new_manager.current_tick(), 24);
+
+        let updates = new_manager.pull_updates().get::<JumpState>().unwrap();
+        assert_eq!(
+            updates[0].ty,
+            ActionType::Update {
+                start: 1000,
+                end: 1500
+            }
+        );
+
+        new_manager.update(300); // tick: 30
+        let mut end_tick = None;
+        while let Some(result) = new_manager.pull_actions() {
+            if result
+                .map
+                .get::<JumpState>(&new_manager.validator())
+                .is_some()
+            {
+                end_tick = Some(new_manager.current_tick());
+            }
+        }
+        assert_eq!(end_tick, Some(30));
+        assert_eq!(
+            new_manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.enqueue(1, Arc::new(MoveState), 1000); // tick: 15
+        manager.enqueue_with_options(2, Arc::new(MoveState), 500, EnqueueOptions { each: true }); // tick: 7
+        manager.enqueue(2, Arc::new(JumpState), 500); // tick: 7(14)
+        manager.pull_vacated_entities();
+
+        manager.update(1000);
+        manager.pull_actions().unwrap(); // tick: 0
+        manager.pull_actions().unwrap(); // tick: 1
+
+        // Mid-frame snapshot which still has unprocessed actions
+        let snapshot = manager.snapshot();
+        assert_eq!(snapshot.current_tick(), 1);
+
+        let mut ticks = Vec::new();
+        while manager.pull_actions().is_some() {
+            ticks.push(manager.current_tick());
+        }
+        manager.cancel(1, true);
+        let vacated_entities = manager.pull_vacated_entities();
+        let checksum = manager.checksum();
+
+        manager.restore(&snapshot);
+        assert_eq!(manager.current_tick(), 1);
+        assert!(manager
+            .pull_updates()
+            .get::<MoveState>()
+            .map(|actions| actions.len() == 2)
+            .unwrap());
+
+        let mut new_ticks = Vec::new();
+        while manager.pull_actions().is_some() {
+            new_ticks.push(manager.current_tick());
+        }
+        manager.cancel(1, true);
+        assert_eq!(ticks, new_ticks);
+        assert_eq!(manager.pull_vacated_entities(), vacated_entities);
+        assert_eq!(manager.checksum(), checksum);
+    }
+
+    #[test]
+    fn test_time_scale() {
+        let mut manager = ActionManager::default();
+        manager.set_time_scale(2000);
+        manager.update(500);
+        assert_eq!(manager.next_time(), 1000);
+
+        // The scaled time less than 1ms is carried over
+        manager.set_time_scale(500);
+        manager.update(3);
+        manager.update(1);
+        assert_eq!(manager.next_time(), 1002);
+
+        manager.pause();
+        manager.update(1000);
+        assert!(manager.is_paused());
+        assert_eq!(manager.next_time(), 1002);
+        assert!(manager.pull_actions().is_none());
+
+        manager.resume();
+        manager.update(1000);
+        assert_eq!(manager.next_time(), 1502);
+    }
+
+    #[test]
+    fn test_catch_up_limit() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.enable_catch_up_limit(5, CatchUpPolicy::Drop);
+        manager.update(250);
+        assert_eq!(manager.next_time(), 250);
+        assert_eq!(manager.interpolation_alpha(), 0.5);
+
+        // The fraction of the tick is kept
+        manager.update(1020);
+        assert_eq!(manager.next_time(), 770);
+        assert_eq!(manager.backlog(), 0);
+        assert_eq!(manager.interpolation_alpha(), 0.7);
+
+        manager.enable_catch_up_limit(5, CatchUpPolicy::CarryOver);
+        manager.update(1000);
+        assert_eq!(manager.next_time(), 1270);
+        assert_eq!(manager.backlog(), 500);
+        manager.update(0);
+        assert_eq!(manager.next_time(), 1770);
+        assert_eq!(manager.backlog(), 0);
+
+        manager.update(900);
+        manager.disable_catch_up_limit();
+        assert_eq!(manager.backlog(), 400);
+        manager.update(0);
+        assert_eq!(manager.next_time(), 2670);
+    }
+
+    #[test]
+    fn test_upcoming_actions() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 300); // tick: 0-3
+        manager.enqueue(1, Arc::new(JumpState), 200); // tick: 3-5
+        manager.enqueue(1, Arc::new(MoveState), 400); // tick: 5-9
+        manager.enqueue(2, Arc::new(MoveState), 100); // tick: 0-1
+
+        let upcoming = |manager: &ActionManager| {
+            manager
+                .upcoming_actions::<MoveState>(1)
+                .map(|action| *action.ty())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            upcoming(&manager),
+            vec![
+                ActionType::Start {
+                    start: 0,
+                    end: 3,
+                    each: false
+                },
+                ActionType::Start {
+                    start: 5,
+                    end: 9,
+                    each: false
+                }
+            ]
+        );
+        assert_eq!(manager.upcoming_actions::<JumpState>(1).count(), 1);
+        assert_eq!(manager.upcoming_actions::<JumpState>(2).count(), 0);
+
+        manager.update(350);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.upcoming_actions::<JumpState>(1).count(), 0);
+        assert_eq!(upcoming(&manager).len(), 1);
+
+        // The canceled actions are invalidated
+        manager.cancel(1, false);
+        assert!(upcoming(&manager).is_empty());
+    }
+
+    #[test]
+    fn test_each_tick() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.enqueue(1, Arc::new(MoveState), 264); // tick: 4
+        manager.enqueue_with_options(1, Arc::new(JumpState), 198, EnqueueOptions { each: true }); // tick: 3(7)
+        manager.enqueue(1, Arc::new(TalkState), 198); // tick: 3(10)
+
+        manager.enqueue_with_options(2, Arc::new(MoveState), 132, EnqueueOptions { each: true }); // tick: 2
+        manager.enqueue(2, Arc::new(JumpState), 66); // tick: 1(3)
+        manager.enqueue(2, Arc::new(TalkState), 264); // tick: 4(7)
+
+        assert_snapshot(&mut manager);
+
+        manager.update(462);
+
+        assert_snapshot(&mut manager);
+    }
+}
@@ -0,0 +1,478 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{
+    bundles_of, validate_rescaling, validate_sorted_actions, ActionManager, ActionManagerConverter,
+    ActionManagerData, ActionManagerError, ActionRetimer, Event, Tick,
+};
+use crate::v2::action::{rescale_tick, Action, ActionType, ArcAction};
+use crate::v2::Lazy;
+use std::any::TypeId;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tearchan_ecs::component::EntityId;
+#[allow(deprecated)]
+use tearchan_ecs::entity::manager::{EntityRemapContext, EntityRemapperToken};
+
+// The remapping of the loaded data into the running world. It's passed to the loading functions
+// explicitly, so the worlds can be loaded concurrently.
+#[derive(Default, Debug, Clone)]
+pub struct RemapContext {
+    entities: EntityRemapContext,
+    ticks: Option<TickRemapping>, // Set by ActionManager::load_data
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TickRemapping {
+    tick: Tick,
+    positive: bool,
+    source_tick_duration: TimeMilliseconds, // The tick duration of the loaded data
+    tick_duration: TimeMilliseconds,
+}
+
+impl RemapContext {
+    pub fn new(entities: EntityRemapContext) -> Self {
+        RemapContext {
+            entities,
+            ticks: None,
+        }
+    }
+
+    #[inline]
+    pub fn entities(&self) -> &EntityRemapContext {
+        &self.entities
+    }
+
+    // Passed to EntityManager::load_data
+    #[inline]
+    pub fn entities_mut(&mut self) -> &mut EntityRemapContext {
+        &mut self.entities
+    }
+
+    #[inline]
+    pub fn remap_entity(&self, entity_id: EntityId) -> EntityId {
+        self.entities.remap(entity_id)
+    }
+
+    // Rescales the tick of the loaded data to the tick duration of the manager, and shifts it
+    // like the loaded actions
+    pub fn remap_tick(&self, tick: Tick) -> Tick {
+        let ticks = match self.ticks {
+            Some(ticks) => ticks,
+            None => return tick,
+        };
+        let tick = rescale_tick(tick, ticks.source_tick_duration, ticks.tick_duration);
+        if ticks.positive {
+            tick.wrapping_add(ticks.tick)
+        } else {
+            tick.wrapping_sub(ticks.tick)
+        }
+    }
+
+    // Sets the remapping to ENTITY_REMAPPER and ACTION_REMAPPER while the token is alive, for the
+    // Deserialize impls which still depend on them
+    #[deprecated(note = "remap with the RemapContext passed to the loading functions instead")]
+    #[allow(deprecated)]
+    pub fn install(&self) -> RemapContextToken {
+        RemapContextToken {
+            _entities: self.entities.install(),
+            _ticks: ActionRemapperToken::new(self.clone()),
+        }
+    }
+}
+
+#[allow(deprecated)]
+pub struct RemapContextToken {
+    _entities: EntityRemapperToken<'static>,
+    _ticks: ActionRemapperToken<'static>,
+}
+
+// The compatibility shim of RemapContext, which is shared by the whole process. It remaps by the
+// installed context, or returns the ticks as they are.
+#[derive(Default)]
+pub struct ActionRemapper {
+    context: Mutex<Option<RemapContext>>,
+}
+
+impl ActionRemapper {
+    pub fn remap(&self, tick: Tick) -> Tick {
+        match self.context.lock().unwrap().as_ref() {
+            Some(context) => context.remap_tick(tick),
+            None => tick,
+        }
+    }
+
+    // Blocks the installations while the token is alive
+    pub fn lock(&self) -> ActionRemapperToken<'_> {
+        ActionRemapperToken {
+            _guard: ACTION_REMAPPER_WRITE_LOCK.lock().unwrap(),
+        }
+    }
+}
+
+#[deprecated(note = "remap with the RemapContext passed to the loading functions instead")]
+pub static ACTION_REMAPPER: Lazy<ActionRemapper> = Lazy::new(ActionRemapper::default);
+static ACTION_REMAPPER_WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub struct ActionRemapperToken<'a> {
+    _guard: MutexGuard<'a, ()>,
+}
+
+#[allow(deprecated)]
+impl<'a> ActionRemapperToken<'a> {
+    fn new(context: RemapContext) -> Self {
+        let guard = ACTION_REMAPPER_WRITE_LOCK.lock().unwrap();
+        *ACTION_REMAPPER.context.lock().unwrap() = Some(context);
+        ActionRemapperToken { _guard: guard }
+    }
+}
+
+#[allow(deprecated)]
+impl<'a> Drop for ActionRemapperToken<'a> {
+    fn drop(&mut self) {
+        *ACTION_REMAPPER.context.lock().unwrap() = None;
+    }
+}
+
+impl ActionManager {
+    // Loads the data into the running manager with shifting its ticks, and records the shift to
+    // the context for the other data depending on the ticks
+    pub fn load_data<T>(
+        &mut self,
+        data: ActionManagerData<T>,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+        context: &mut RemapContext,
+    ) -> Result<(), ActionManagerError> {
+        validate_rescaling(&data, self.tick_duration)?;
+        validate_sorted_actions(&data.actions, data.current_tick)?;
+        for freeze in data.freezes.iter() {
+            validate_sorted_actions(&freeze.actions, freeze.tick)?;
+        }
+
+        let data_current_tick =
+            rescale_tick(data.current_tick, data.tick_duration, self.tick_duration);
+        let remapping_tick = (self.current_tick as i128 - data_current_tick as i128).abs() as Tick;
+        let remapping_tick_positive = self.current_tick > data_current_tick;
+
+        // The stopped clock of the entity frozen before the shifted start of the data is moved to
+        // the saved tick rather than wrapped below zero. Only the ticks after the frozen tick
+        // matter on unfreezing.
+        let mut data = data;
+        for freeze in data.freezes.iter_mut() {
+            let freeze_tick = rescale_tick(freeze.tick, data.tick_duration, self.tick_duration);
+            if remapping_tick_positive || remapping_tick <= freeze_tick {
+                continue;
+            }
+            let shift = data.current_tick - freeze.tick;
+            freeze.tick = data.current_tick;
+            for action in freeze.actions.iter_mut() {
+                action.ty = action.ty.remap(shift, true, data.tick_duration);
+            }
+            for cancel in freeze.cancels.iter_mut() {
+                cancel.tick += shift;
+            }
+            for action in data.actions.iter_mut() {
+                if action.entity_id == freeze.entity_id {
+                    action.ty = action.ty.remap(shift, true, data.tick_duration);
+                }
+            }
+        }
+
+        context.ticks = Some(TickRemapping {
+            tick: remapping_tick,
+            positive: remapping_tick_positive,
+            source_tick_duration: data.tick_duration,
+            tick_duration: self.tick_duration,
+        });
+        self.load_freezes(
+            &data.freezes,
+            |entity_id| context.remap_entity(entity_id),
+            |tick| context.remap_tick(tick),
+        );
+        for action in data.actions.iter() {
+            let entity_id = context.remap_entity(action.entity_id);
+            self.create_context(entity_id, action.layer, self.current_tick);
+        }
+        let mut c = ActionManagerConverter {
+            entities: &context.entities,
+            remapping_tick,
+            remapping_tick_positive,
+            source_tick_duration: data.tick_duration,
+            tick_duration: self.tick_duration,
+            freezes: &mut self.freezes,
+            retimers: &mut self.retimers,
+            actions: &mut self.actions,
+            contexts: &mut self.contexts,
+            update_actions: &mut self.update_actions,
+            each_tick_actions: &mut self.each_tick_actions,
+            joints: &mut self.joints,
+            timers: &mut self.timers,
+            next_timer_id: &mut self.next_timer_id,
+            timer: None,
+        };
+        for action in data.actions.into_iter() {
+            converter(action, &mut c);
+        }
+        for freeze in data.freezes.into_iter() {
+            for action in freeze.actions.into_iter() {
+                converter(action, &mut c);
+            }
+        }
+        c.load_joints(data.joints)?;
+        c.load_timers(data.timers, converter);
+
+        self.complete_loaded_contexts()?;
+        self.mark_all_dirty();
+        Ok(())
+    }
+}
+
+impl<'a> ActionManagerConverter<'a> {
+    pub(super) fn remap_tick(&self, tick: Tick) -> Tick {
+        let tick = rescale_tick(tick, self.source_tick_duration, self.tick_duration);
+        if self.remapping_tick_positive {
+            tick.wrapping_add(self.remapping_tick)
+        } else {
+            tick.wrapping_sub(self.remapping_tick)
+        }
+    }
+
+    pub fn load<T>(&mut self, action: ArcAction<T>)
+    where
+        T: 'static,
+    {
+        if let Some(timer) = self.timer.clone() {
+            self.load_timer(timer, Arc::clone(action.raw()));
+            return;
+        }
+
+        let type_id = TypeId::of::<T>();
+        self.retimers
+            .entry(type_id)
+            .or_insert_with(ActionRetimer::of::<T>);
+        let entity_id = self.entities.remap(action.entity_id);
+        let layer = action.layer;
+        let context = self
+            .contexts
+            .get_mut(&(entity_id, layer))
+            .unwrap_or_else(|| panic!("layer {} of entity {} is not attached", layer, entity_id));
+
+        let remapped_action_type = action
+            .ty
+            .rescale(self.source_tick_duration, self.tick_duration)
+            .expect("the rescaling is validated before loading")
+            .remap(
+                self.remapping_tick,
+                self.remapping_tick_positive,
+                self.tick_duration,
+            );
+        match remapped_action_type {
+            ActionType::Start { start, end, each } => {
+                let tick = remapped_action_type.tick().unwrap();
+                let item = bundles_of(self.actions, self.freezes, entity_id)
+                    .entry(tick)
+                    .or_insert_with(Default::default);
+                let start_time = start.wrapping_mul(self.tick_duration);
+                let end_time = end.wrapping_mul(self.tick_duration);
+                item.events.push_back((
+                    (entity_id, layer),
+                    (
+                        context.session_id,
+                        Event::Started {
+                            type_id,
+                            type_name: std::any::type_name::<T>(),
+                            update_action: Arc::new(Box::new(Action {
+                                raw: Arc::clone(&action.raw),
+                                entity_id,
+                                layer,
+                                ty: ActionType::Update {
+                                    start: start_time,
+                                    end: end_time,
+                                },
+                            })),
+                            each_action: if each {
+                                Some(Arc::new(Box::new(Action {
+                                    raw: Arc::clone(&action.raw),
+                                    entity_id,
+                                    layer,
+                                    ty: ActionType::EachTick { start, end },
+                                })))
+                            } else {
+                                None
+                            },
+                            running_end_tick: end,
+                        },
+                    ),
+                ));
+                item.map.push(
+                    Action {
+                        raw: Arc::clone(action.raw()),
+                        entity_id,
+                        layer,
+                        ty: remapped_action_type,
+                    },
+                    context.session_id,
+                );
+            }
+            ActionType::End { .. } => {
+                let tick = remapped_action_type.tick().unwrap();
+                let item = bundles_of(self.actions, self.freezes, entity_id)
+                    .entry(tick)
+                    .or_insert_with(Default::default);
+                item.events
+                    .push_back(((entity_id, layer), (context.session_id, Event::Ended)));
+                item.map.push(
+                    Action {
+                        raw: Arc::clone(action.raw()),
+                        entity_id,
+                        layer,
+                        ty: remapped_action_type,
+                    },
+                    context.session_id,
+                );
+                context.last_tick = context.last_tick.max(tick);
+                context.running_end_tick = context.running_end_tick.min(tick);
+            }
+            ActionType::Update { .. } => {
+                self.update_actions.insert(
+                    entity_id,
+                    Action {
+                        raw: Arc::clone(action.raw()),
+                        entity_id,
+                        layer,
+                        ty: remapped_action_type,
+                    },
+                );
+            }
+            ActionType::EachTick { .. } => {
+                let tick = remapped_action_type.tick().unwrap();
+                let each_tick_actions = match self.freezes.get_mut(&entity_id) {
+                    Some(frozen) => &mut frozen.each_tick_actions,
+                    None => &mut *self.each_tick_actions,
+                };
+                each_tick_actions.insert(
+                    Action {
+                        raw: Arc::clone(action.raw()),
+                        entity_id,
+                        layer,
+                        ty: remapped_action_type,
+                    },
+                    context.session_id,
+                    tick,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{JumpState, MoveState, TalkState, TestAction};
+    #[allow(deprecated)]
+    use crate::v2::action::manager::{ActionManager, RemapContext, ACTION_REMAPPER};
+
+    use crate::v2::action::ActionType;
+    use crate::v2::HordeActions;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_load_data_with_rescaling() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 5
+        manager.update(500); // tick: 5
+        while manager.pull_actions().is_some() {}
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(JumpState), 1000); // tick: 15
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+
+        let mut new_manager = ActionManager::with_tick_duration(25);
+        new_manager.attach(2);
+        new_manager.enqueue(2, Arc::new(TalkState), 10000);
+        new_manager.update(100); // tick: 4
+        while new_manager.pull_actions().is_some() {}
+        assert_eq!(new_manager.current_tick(), 4);
+        let mut context = RemapContext::default();
+        new_manager
+            .load_data(data, TestAction::convert_from_actions, &mut context)
+            .unwrap();
+        assert_eq!(context.remap_tick(5), 4);
+        assert_eq!(context.remap_tick(15), 44);
+        #[allow(deprecated)]
+        {
+            let _token = context.install();
+            assert_eq!(ACTION_REMAPPER.remap(15), 44);
+        }
+
+        // The saved ticks 5..15 are rescaled to 20..60 and then remapped to 4..44
+        let updates = new_manager.pull_updates().get::<JumpState>().unwrap();
+        assert_eq!(
+            updates[0].ty,
+            ActionType::Update {
+                start: 100,
+                end: 1100
+            }
+        );
+
+        new_manager.update(1000); // tick: 44
+        let mut end_tick = None;
+        while let Some(result) = new_manager.pull_actions() {
+            if result
+                .map
+                .get::<JumpState>(&new_manager.validator())
+                .is_some()
+            {
+                end_tick = Some(new_manager.current_tick());
+            }
+        }
+        assert_eq!(end_tick, Some(44));
+    }
+    #[test]
+    fn test_load_data_with_early_freeze() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 0-5
+        manager.update(100); // tick: 1
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        manager.update(300); // tick: 4
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+
+        // The frozen tick 1 is before the saved tick 4 remapped to 0
+        let mut new_manager = ActionManager::with_tick_duration(100);
+        let mut context = RemapContext::default();
+        new_manager
+            .load_data(data, TestAction::convert_from_actions, &mut context)
+            .unwrap();
+        assert_eq!(new_manager.entity_tick(1), 0);
+        assert_eq!(new_manager.verify(), Ok(()));
+
+        // The remaining 4 ticks run after unfreezing
+        new_manager.update(200); // tick: 2
+        while new_manager.pull_actions().is_some() {}
+        new_manager.unfreeze(1);
+        new_manager.update(1000);
+        let mut end_tick = None;
+        while let Some(result) = new_manager.pull_actions() {
+            if result
+                .map
+                .get::<MoveState>(&new_manager.validator())
+                .is_some()
+            {
+                end_tick = Some(new_manager.current_tick());
+            }
+        }
+        assert_eq!(end_tick, Some(6));
+    }
+}
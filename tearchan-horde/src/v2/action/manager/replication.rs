@@ -0,0 +1,196 @@
+use crate::v2::action::collection::TypedAnyActionMap;
+use crate::v2::action::manager::{
+    entity_tick, ActionManager, ActionManagerConverter, ActionSessionValidator, ReplicationRecord,
+};
+use crate::v2::action::replication::{ReplicatedCommand, ReplicationDelta};
+use crate::v2::action::{Action, ActionType, CancelReason, DEFAULT_ACTION_LAYER, VALID_SESSION_ID};
+use tearchan_ecs::entity::manager::EntityRemapContext;
+
+impl ActionManager {
+    // Records the commands for the replicated clients until disabled
+    pub fn enable_replication(&mut self) {
+        if self.replication.is_none() {
+            self.replication = Some(Vec::new());
+        }
+    }
+
+    pub fn disable_replication(&mut self) {
+        self.replication = None;
+    }
+
+    pub fn is_replicating(&self) -> bool {
+        self.replication.is_some()
+    }
+
+    // Commits the recorded commands as the delta which is sent to the clients
+    pub fn pull_replication<T>(
+        &mut self,
+        converter: fn(&TypedAnyActionMap, &ActionSessionValidator) -> Vec<Action<T>>,
+    ) -> ReplicationDelta<T> {
+        let records = self
+            .replication
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let commands = records
+            .into_iter()
+            .filter_map(|record| {
+                Some(match record {
+                    ReplicationRecord::AttachLayer(entity_id, layer) => {
+                        ReplicatedCommand::AttachLayer { entity_id, layer }
+                    }
+                    ReplicationRecord::DetachLayer(entity_id, layer) => {
+                        ReplicatedCommand::DetachLayer { entity_id, layer }
+                    }
+                    ReplicationRecord::Schedule(type_id, action) => {
+                        let mut map = TypedAnyActionMap::default();
+                        map.push_as_raw(
+                            type_id,
+                            action,
+                            0,
+                            DEFAULT_ACTION_LAYER,
+                            VALID_SESSION_ID,
+                            0,
+                        );
+                        ReplicatedCommand::Schedule {
+                            action: converter(&map, &ActionSessionValidator::default()).pop()?,
+                        }
+                    }
+                    ReplicationRecord::Cancel(entity_id, layer, tick, reason) => {
+                        ReplicatedCommand::Cancel {
+                            entity_id,
+                            layer,
+                            tick,
+                            reason,
+                        }
+                    }
+                    ReplicationRecord::Reschedule(entity_id, layer, end_tick) => {
+                        ReplicatedCommand::Reschedule {
+                            entity_id,
+                            layer,
+                            end_tick,
+                        }
+                    }
+                    ReplicationRecord::Freeze(entity_id) => ReplicatedCommand::Freeze { entity_id },
+                    ReplicationRecord::Unfreeze(entity_id) => {
+                        ReplicatedCommand::Unfreeze { entity_id }
+                    }
+                })
+            })
+            .collect();
+        ReplicationDelta {
+            tick: self.current_tick,
+            commands,
+        }
+    }
+
+    // Applies the delta committed by the server. The actions arriving after their start are
+    // shortened to catch up with the server, and the actions overlapping the pending actions
+    // cancel them from the start like v1's push_actions.
+    pub fn apply_replication<T>(
+        &mut self,
+        delta: ReplicationDelta<T>,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+    ) where
+        T: Clone,
+    {
+        for command in delta.commands {
+            match command {
+                ReplicatedCommand::AttachLayer { entity_id, layer } => {
+                    if !self.contexts.contains_key(&(entity_id, layer)) {
+                        self.attach_layer(entity_id, layer);
+                    }
+                }
+                ReplicatedCommand::DetachLayer { entity_id, layer } => {
+                    self.detach_layer(entity_id, layer);
+                }
+                ReplicatedCommand::Schedule { action } => {
+                    self.load_replicated_action(action, converter);
+                }
+                ReplicatedCommand::Cancel {
+                    entity_id,
+                    layer,
+                    tick,
+                    reason,
+                } => {
+                    if self.contexts.contains_key(&(entity_id, layer)) {
+                        self.controller()
+                            .cancel_layer_at(entity_id, layer, tick, reason);
+                    }
+                }
+                ReplicatedCommand::Reschedule {
+                    entity_id,
+                    layer,
+                    end_tick,
+                } => {
+                    if self.contexts.contains_key(&(entity_id, layer)) {
+                        self.controller()
+                            .reschedule_layer(entity_id, layer, end_tick);
+                    }
+                }
+                ReplicatedCommand::Freeze { entity_id } => self.freeze(entity_id),
+                ReplicatedCommand::Unfreeze { entity_id } => self.unfreeze(entity_id),
+            }
+        }
+    }
+
+    fn load_replicated_action<T>(
+        &mut self,
+        action: Action<T>,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+    ) where
+        T: Clone,
+    {
+        let (start, end, each) = match action.ty {
+            ActionType::Start { start, end, each } => (start, end, each),
+            _ => return,
+        };
+        let entity_id = action.entity_id;
+        let layer = action.layer;
+        let last_tick = match self.contexts.get(&(entity_id, layer)) {
+            None => return, // Already detached on the client
+            Some(context) => context.last_tick,
+        };
+        if start < last_tick {
+            self.controller()
+                .cancel_layer_at(entity_id, layer, start, CancelReason::Unspecified);
+        }
+        let current_tick = entity_tick(&self.freezes, self.current_tick, entity_id);
+        let start = start.max(current_tick);
+        let end = end.max(start);
+
+        let end_action = Action::with_layer(
+            action.raw.clone(),
+            entity_id,
+            layer,
+            ActionType::End { start, end },
+        );
+        let start_action = Action::with_layer(
+            action.raw,
+            entity_id,
+            layer,
+            ActionType::Start { start, end, each },
+        );
+        let entities = EntityRemapContext::default();
+        let mut c = ActionManagerConverter {
+            entities: &entities,
+            remapping_tick: 0,
+            remapping_tick_positive: true,
+            source_tick_duration: self.tick_duration,
+            tick_duration: self.tick_duration,
+            freezes: &mut self.freezes,
+            retimers: &mut self.retimers,
+            actions: &mut self.actions,
+            contexts: &mut self.contexts,
+            update_actions: &mut self.update_actions,
+            each_tick_actions: &mut self.each_tick_actions,
+            joints: &mut self.joints,
+            timers: &mut self.timers,
+            next_timer_id: &mut self.next_timer_id,
+            timer: None,
+        };
+        converter(start_action, &mut c);
+        converter(end_action, &mut c);
+        self.vacated_entities.remove(&(entity_id, layer));
+    }
+}
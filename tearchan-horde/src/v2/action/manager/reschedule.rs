@@ -0,0 +1,351 @@
+use crate::v2::action::manager::{
+    bundles_of, entity_tick, ActionController, ActionManager, BundleForeachTick, Event,
+    ReplicationRecord, Tick,
+};
+use crate::v2::action::{ActionLayer, ActionType, DEFAULT_ACTION_LAYER};
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tearchan_ecs::component::EntityId;
+
+impl ActionManager {
+    pub fn reschedule(&mut self, entity_id: EntityId, end_tick: Tick) -> bool {
+        self.controller().reschedule(entity_id, end_tick)
+    }
+
+    pub fn reschedule_layer(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        end_tick: Tick,
+    ) -> bool {
+        self.controller()
+            .reschedule_layer(entity_id, layer, end_tick)
+    }
+}
+
+impl<'a> ActionController<'a> {
+    #[inline]
+    pub fn reschedule(&mut self, entity_id: EntityId, end_tick: Tick) -> bool {
+        self.reschedule_layer(entity_id, DEFAULT_ACTION_LAYER, end_tick)
+    }
+
+    // Moves the end of the running action to the tick, which is clamped to the current tick of the
+    // entity, and shifts the queued actions after it by the same ticks without renewing the
+    // session. Returns false if no action is running on the layer, the layer has the joint
+    // actions which cannot be shifted without the other participants, or the tick overflows.
+    pub fn reschedule_layer(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        end_tick: Tick,
+    ) -> bool {
+        let current_tick = entity_tick(self.freezes, self.current_tick, entity_id);
+        let (running_end_tick, session_id) = match self.contexts.get(&(entity_id, layer)) {
+            Some(context) if current_tick < context.running_end_tick => {
+                (context.running_end_tick, context.session_id)
+            }
+            _ => return false,
+        };
+        let has_joint = self.joints.iter().any(|joint| {
+            current_tick < joint.end_tick
+                && joint
+                    .participants
+                    .contains(&((entity_id, layer), session_id))
+        });
+        if has_joint {
+            return false;
+        }
+        let end_tick = end_tick.max(current_tick);
+        let end_time = match end_tick.checked_mul(self.tick_duration) {
+            Some(end_time) => end_time,
+            None => return false,
+        };
+        if end_tick == running_end_tick {
+            return true;
+        }
+        let positive = running_end_tick < end_tick;
+        let shift = if positive {
+            end_tick - running_end_tick
+        } else {
+            running_end_tick - end_tick
+        };
+        let shift_tick = |tick: Tick| {
+            if positive {
+                tick + shift
+            } else {
+                tick - shift
+            }
+        };
+        let tick_duration = self.tick_duration;
+        let retimers = &*self.retimers;
+        let retime = |type_id: &TypeId,
+                      action: &Arc<Box<dyn Any>>,
+                      retime: &dyn Fn(&ActionType) -> ActionType| {
+            let retimer = retimers
+                .get(type_id)
+                .expect("The retimer is registered on scheduling");
+            (retimer.retime)(action, retime)
+        };
+        let shift_type = |ty: &ActionType| match ty {
+            // The end of the running action, whose start may wrap below zero on loading
+            ActionType::End { start, end } if *end == running_end_tick && *start != *end => {
+                ActionType::End {
+                    start: *start,
+                    end: end_tick,
+                }
+            }
+            ty => ty.remap(shift, positive, tick_duration),
+        };
+
+        let actions = bundles_of(self.actions, self.freezes, entity_id);
+        let mut moved = Vec::new();
+        for (tick, item) in actions.range_mut(running_end_tick..) {
+            let (events, others) = std::mem::take(&mut item.events)
+                .into_iter()
+                .partition(|(key, _)| *key == (entity_id, layer));
+            item.events = others;
+            let bundle = BundleForeachTick {
+                map: item.map.take_layer(entity_id, layer),
+                events,
+                cancels: item
+                    .cancels
+                    .remove(&(entity_id, layer))
+                    .map(|reason| BTreeMap::from([((entity_id, layer), reason)]))
+                    .unwrap_or_default(),
+                timers: Vec::new(),
+            };
+            if !bundle.is_empty() {
+                moved.push((*tick, bundle));
+            }
+        }
+        actions.retain(|_tick, item| !item.is_empty());
+        for (tick, bundle) in moved {
+            let item = actions.entry(shift_tick(tick)).or_default();
+            for (type_id, vec) in bundle.map.iter() {
+                for (meta, action) in vec.iter() {
+                    item.map.push_as_raw(
+                        *type_id,
+                        retime(type_id, action, &shift_type),
+                        meta.entity_id,
+                        meta.layer,
+                        meta.session_id,
+                        shift_tick(meta.tick),
+                    );
+                }
+            }
+            for (key, (session_id, event)) in bundle.events {
+                let event = match event {
+                    Event::Started {
+                        type_id,
+                        type_name,
+                        update_action,
+                        each_action,
+                        running_end_tick,
+                    } => Event::Started {
+                        type_id,
+                        type_name,
+                        update_action: retime(&type_id, &update_action, &shift_type),
+                        each_action: each_action
+                            .map(|action| retime(&type_id, &action, &shift_type)),
+                        running_end_tick: shift_tick(running_end_tick),
+                    },
+                    event => event,
+                };
+                item.events.push_back((key, (session_id, event)));
+            }
+            item.cancels.extend(bundle.cancels);
+        }
+
+        // The running action keeps the start
+        let stretch_type = |ty: &ActionType| match ty {
+            ActionType::Update { start, .. } => ActionType::Update {
+                start: *start,
+                end: end_time,
+            },
+            ActionType::EachTick { start, .. } => ActionType::EachTick {
+                start: *start,
+                end: end_tick,
+            },
+            ty => *ty,
+        };
+        for (type_id, action_layer, action) in self.update_actions.take(entity_id) {
+            let action = if action_layer == layer {
+                retime(&type_id, &action, &stretch_type)
+            } else {
+                action
+            };
+            self.update_actions
+                .insert_as_raw(action, type_id, entity_id, action_layer);
+        }
+        let each_tick_actions = match self.freezes.get_mut(&entity_id) {
+            Some(frozen) => &mut frozen.each_tick_actions,
+            None => &mut *self.each_tick_actions,
+        };
+        for (type_id, meta, action) in each_tick_actions.take(entity_id) {
+            let (action, tick) = if meta.layer == layer {
+                (retime(&type_id, &action, &stretch_type), end_tick)
+            } else {
+                (action, meta.tick)
+            };
+            each_tick_actions.insert_as_raw(
+                action,
+                type_id,
+                meta.entity_id,
+                meta.layer,
+                meta.session_id,
+                tick,
+            );
+        }
+
+        let context = self.contexts.get_mut(&(entity_id, layer)).unwrap();
+        context.running_end_tick = end_tick;
+        context.last_tick = shift_tick(context.last_tick);
+        if context.session_expired_at >= running_end_tick {
+            context.session_expired_at = shift_tick(context.session_expired_at);
+        }
+        self.mark_dirty((entity_id, layer));
+        if let Some(records) = self.replication {
+            records.push(ReplicationRecord::Reschedule(entity_id, layer, end_tick));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{JumpState, MoveState, TestAction};
+    use crate::v2::action::manager::{ActionManager, ActionManagerData, Tick};
+
+    use crate::v2::action::{ActionType, CancelReason};
+    use crate::v2::HordeActions;
+
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    fn pull_ended_actions(manager: &mut ActionManager) -> Vec<(Tick, &'static str)> {
+        let mut ended = Vec::new();
+        while let Some(result) = manager.pull_actions() {
+            let validator = manager.validator();
+            for action in result.map.get::<MoveState>(&validator).unwrap_or_default() {
+                if let ActionType::End { .. } = action.ty() {
+                    ended.push((manager.current_tick(), "move"));
+                }
+            }
+            for action in result.map.get::<JumpState>(&validator).unwrap_or_default() {
+                if let ActionType::End { .. } = action.ty() {
+                    ended.push((manager.current_tick(), "jump"));
+                }
+            }
+        }
+        ended
+    }
+
+    #[test]
+    fn test_reschedule() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        assert!(!manager.reschedule(1, 10));
+
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 0-5
+        manager.enqueue(1, Arc::new(JumpState), 300); // tick: 5-8
+        manager.update(200);
+        assert!(pull_ended_actions(&mut manager).is_empty());
+
+        // Slowed down
+        assert!(manager.reschedule(1, 8));
+        let updates = manager.pull_updates().get::<MoveState>().unwrap();
+        assert_eq!(updates[0].ty(), &ActionType::Update { start: 0, end: 800 });
+        assert_eq!(
+            manager
+                .upcoming_actions::<JumpState>(1)
+                .map(|action| *action.ty())
+                .collect::<Vec<_>>(),
+            vec![ActionType::Start {
+                start: 8,
+                end: 11,
+                each: false
+            }]
+        );
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        let mut new_manager =
+            ActionManager::from_data(data, TestAction::convert_from_actions).unwrap();
+
+        // Sped up, which is clamped to the current tick
+        manager.update(200);
+        new_manager.update(200);
+        assert!(pull_ended_actions(&mut manager).is_empty());
+        assert!(pull_ended_actions(&mut new_manager).is_empty());
+        assert!(manager.reschedule(1, 3));
+        assert!(new_manager.reschedule(1, 3));
+
+        manager.update(1000);
+        new_manager.update(1000);
+        let ended = pull_ended_actions(&mut manager);
+        assert_eq!(ended, vec![(4, "move"), (7, "jump")]);
+        assert_eq!(pull_ended_actions(&mut new_manager), ended);
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+    #[test]
+    fn test_reschedule_with_joint() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 0-5
+        manager.enqueue_joint(&[1, 2], Arc::new(JumpState), 300); // tick: 5-8
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+
+        // The joint action cannot be shifted without the other participant
+        assert!(!manager.reschedule(1, 8));
+        assert_eq!(
+            manager
+                .upcoming_actions::<JumpState>(1)
+                .map(|action| *action.ty())
+                .collect::<Vec<_>>(),
+            vec![ActionType::Start {
+                start: 5,
+                end: 8,
+                each: false
+            }]
+        );
+
+        // The joint action is still linked
+        manager.cancel(2, true);
+        let result = manager.pull_actions().unwrap();
+        assert_eq!(
+            result.canceled_layers,
+            BTreeMap::from([
+                ((1, 0), CancelReason::JointPartnerCanceled),
+                ((2, 0), CancelReason::Unspecified),
+            ])
+        );
+        assert_eq!(
+            manager
+                .pull_vacated_entities()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        manager.enqueue(1, Arc::new(MoveState), 500); // tick: 1-6
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        assert!(manager.reschedule(1, 3));
+    }
+}
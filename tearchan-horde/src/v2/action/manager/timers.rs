@@ -0,0 +1,303 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{
+    ActionController, ActionManager, ActionManagerConverter, BundleForeachTick, ScheduledTimer,
+    Tick, TimerData,
+};
+use crate::v2::action::timer::{Timer, TimerId, TimerOptions};
+use crate::v2::action::{rescale_tick, Action, ActionType, DEFAULT_ACTION_LAYER};
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+impl ActionManager {
+    pub fn schedule_timer<T>(&mut self, raw: Arc<T>, delay: TimeMilliseconds) -> TimerId
+    where
+        T: 'static,
+    {
+        self.controller().schedule_timer(raw, delay)
+    }
+
+    pub fn schedule_timer_with_options<T>(
+        &mut self,
+        raw: Arc<T>,
+        delay: TimeMilliseconds,
+        options: TimerOptions,
+    ) -> TimerId
+    where
+        T: 'static,
+    {
+        self.controller()
+            .schedule_timer_with_options(raw, delay, options)
+    }
+
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.controller().cancel_timer(timer_id)
+    }
+}
+
+impl<'a> ActionController<'a> {
+    #[inline]
+    pub fn schedule_timer<T>(&mut self, raw: Arc<T>, delay: TimeMilliseconds) -> TimerId
+    where
+        T: 'static,
+    {
+        self.schedule_timer_with_options(raw, delay, TimerOptions::default())
+    }
+
+    // Schedules the event which isn't bound to any entity, so it doesn't make any vacated entities
+    pub fn schedule_timer_with_options<T>(
+        &mut self,
+        raw: Arc<T>,
+        delay: TimeMilliseconds,
+        options: TimerOptions,
+    ) -> TimerId
+    where
+        T: 'static,
+    {
+        let timer_id = *self.next_timer_id;
+        *self.next_timer_id = timer_id.next();
+
+        let tick = self.current_tick + delay / self.tick_duration;
+        let interval = options
+            .interval
+            .map(|interval| (interval / self.tick_duration).max(1));
+        register_timer(self.actions, self.timers, timer_id, raw, tick, interval);
+        timer_id
+    }
+
+    // Returns false if the timer has been already fired or canceled
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.timers.remove(&timer_id).is_some()
+    }
+}
+
+impl<'a> ActionManagerConverter<'a> {
+    // Each timer is converted by the converter which is created for it, so the converted action
+    // is loaded as the timer
+    pub(super) fn load_timers<T>(
+        &mut self,
+        timers: Vec<TimerData<T>>,
+        converter: fn(action: Action<T>, manager: &mut ActionManagerConverter),
+    ) {
+        for timer in timers {
+            let action = Action::new(
+                timer.raw,
+                0,
+                ActionType::Start {
+                    start: timer.tick,
+                    end: timer.tick,
+                    each: false,
+                },
+            );
+            let timer = TimerData {
+                id: timer.id,
+                raw: (),
+                tick: timer.tick,
+                interval: timer.interval,
+            };
+            converter(action, &mut self.with_timer(timer));
+        }
+    }
+
+    fn with_timer<'b>(&'b mut self, timer: TimerData<()>) -> ActionManagerConverter<'b> {
+        ActionManagerConverter {
+            entities: self.entities,
+            remapping_tick: self.remapping_tick,
+            remapping_tick_positive: self.remapping_tick_positive,
+            source_tick_duration: self.source_tick_duration,
+            tick_duration: self.tick_duration,
+            freezes: self.freezes,
+            retimers: self.retimers,
+            actions: self.actions,
+            contexts: self.contexts,
+            update_actions: self.update_actions,
+            each_tick_actions: self.each_tick_actions,
+            joints: self.joints,
+            timers: self.timers,
+            next_timer_id: self.next_timer_id,
+            timer: Some(timer),
+        }
+    }
+
+    pub(super) fn load_timer<T>(&mut self, timer: TimerData<()>, raw: Arc<T>)
+    where
+        T: 'static,
+    {
+        // Keeps the saved id unless it's already used by the running timers
+        let timer_id = if self.timers.contains_key(&timer.id) {
+            *self.next_timer_id
+        } else {
+            timer.id
+        };
+        *self.next_timer_id = (*self.next_timer_id).max(timer_id.next());
+        let interval = timer.interval.map(|interval| {
+            rescale_tick(interval, self.source_tick_duration, self.tick_duration).max(1)
+        });
+        register_timer(
+            self.actions,
+            self.timers,
+            timer_id,
+            raw,
+            self.remap_tick(timer.tick),
+            interval,
+        );
+    }
+}
+
+fn register_timer<T>(
+    actions: &mut BTreeMap<Tick, BundleForeachTick>,
+    timers: &mut BTreeMap<TimerId, ScheduledTimer>,
+    timer_id: TimerId,
+    raw: Arc<T>,
+    tick: Tick,
+    interval: Option<Tick>,
+) where
+    T: 'static,
+{
+    actions.entry(tick).or_default().timers.push(timer_id);
+    timers.insert(
+        timer_id,
+        ScheduledTimer {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            tick,
+            interval,
+            timer: Arc::new(Box::new(Timer::new(timer_id, Arc::clone(&raw), interval))),
+            action: Arc::new(Box::new(Action {
+                raw,
+                entity_id: 0,
+                layer: DEFAULT_ACTION_LAYER,
+                ty: ActionType::Start {
+                    start: tick,
+                    end: tick,
+                    each: false,
+                },
+            })),
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{JumpState, MoveState, TalkState, TestAction};
+    use crate::v2::action::manager::{ActionManager, ActionManagerData, Tick};
+    use crate::v2::action::timer::{TimerId, TimerOptions};
+    use crate::v2::HordeActions;
+    use std::sync::Arc;
+
+    fn pull_fired_timers(manager: &mut ActionManager) -> Vec<(Tick, TimerId)> {
+        let mut fired = Vec::new();
+        while let Some(result) = manager.pull_actions() {
+            for timer in result.timers.get::<TalkState>().unwrap_or_default() {
+                fired.push((manager.current_tick(), timer.id()));
+            }
+        }
+        fired
+    }
+
+    #[test]
+    fn test_timer() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 2000); // tick: 30
+        let timer0 = manager.schedule_timer(Arc::new(TalkState), 500); // tick: 7
+        let timer1 = manager.schedule_timer_with_options(
+            Arc::new(TalkState),
+            200,
+            TimerOptions {
+                interval: Some(660),
+            },
+        ); // tick: 3, 13, 23, ...
+        let timer2 = manager.schedule_timer(Arc::new(TalkState), 1000); // tick: 15
+        assert!(manager.cancel_timer(timer2));
+        assert!(!manager.cancel_timer(timer2));
+
+        manager.update(1000); // tick: 15
+        assert_eq!(
+            pull_fired_timers(&mut manager),
+            vec![(3, timer1), (7, timer0), (13, timer1)]
+        );
+        // The timers don't make any vacated entities
+        assert!(manager.pull_vacated_entities().is_empty());
+        assert!(!manager.cancel_timer(timer0));
+
+        manager.update(1000); // tick: 30
+        assert_eq!(pull_fired_timers(&mut manager), vec![(23, timer1)]);
+        manager.pull_vacated_entities();
+
+        assert!(manager.cancel_timer(timer1));
+        manager.update(1000); // tick: 45
+        assert!(pull_fired_timers(&mut manager).is_empty());
+    }
+
+    #[test]
+    fn test_serialization_with_timer() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 2000); // tick: 30
+        manager.schedule_timer(Arc::new(TalkState), 1000); // tick: 15
+        manager.schedule_timer_with_options(
+            Arc::new(TalkState),
+            200,
+            TimerOptions {
+                interval: Some(660),
+            },
+        ); // tick: 3, 13, 23, ...
+
+        manager.update(500); // tick: 7
+        assert_eq!(pull_fired_timers(&mut manager).len(), 1);
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        let str = serde_json::to_string(&data).unwrap();
+        let data: ActionManagerData<TestAction> = serde_json::from_str(&str).unwrap();
+        let mut new_manager =
+            ActionManager::from_data(data, TestAction::convert_from_actions).unwrap();
+
+        manager.update(1500); // tick: 30
+        new_manager.update(1500);
+        let fired = pull_fired_timers(&mut manager);
+        assert_eq!(fired.len(), 3);
+        assert_eq!(pull_fired_timers(&mut new_manager), fired);
+
+        // The timer ids are not reused
+        let timer_id = new_manager.schedule_timer(Arc::new(TalkState), 0);
+        assert!(fired.iter().all(|(_, id)| *id != timer_id));
+    }
+
+    #[test]
+    fn test_serialization_with_skipped_timer() {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.pull_vacated_entities();
+
+        manager.enqueue(1, Arc::new(MoveState), 2000); // tick: 30
+        manager.schedule_timer(Arc::new(JumpState), 200); // tick: 3
+        let timer = manager.schedule_timer(Arc::new(TalkState), 500); // tick: 7
+        while manager.pull_actions().is_some() {}
+
+        let data = manager.to_data(
+            TestAction::convert_actions_from_typed_action_any_map,
+            TestAction::convert_actions_from_typed_any_action_map,
+            TestAction::convert_actions_from_any_action_vec,
+        );
+        // The converter drops the jump timer, which must not leak into the next timer
+        let mut new_manager = ActionManager::from_data(data, |action, converter| {
+            if !matches!(action.raw(), TestAction::Jump(_)) {
+                TestAction::convert_from_actions(action, converter);
+            }
+        })
+        .unwrap();
+
+        new_manager.update(1000); // tick: 15
+        assert_eq!(pull_fired_timers(&mut new_manager), vec![(7, timer)]);
+        assert!(!new_manager.cancel_timer(timer));
+    }
+}
@@ -0,0 +1,297 @@
+use crate::v2::action::manager::{entity_tick, ActionManager, Event, Tick};
+use crate::v2::action::timer::TimerId;
+use crate::v2::action::{ActionLayer, ActionSessionId};
+use std::collections::BTreeSet;
+use tearchan_ecs::component::EntityId;
+
+// The broken invariant found by ActionManager::verify
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ActionManagerViolation {
+    BackwardClock {
+        current_tick: Tick,
+        next_tick: Tick,
+    },
+    // The bundle before the clock, which is never pulled. entity_id is for the frozen entity.
+    StaleBundle {
+        entity_id: Option<EntityId>,
+        tick: Tick,
+        current_tick: Tick,
+    },
+    MisplacedAction {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        tick: Tick,
+        bundle_tick: Tick,
+    },
+    // The session which is duplicated or not allocated yet
+    InvalidSession {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        session_id: ActionSessionId,
+    },
+    InvalidContextTicks {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        running_end_tick: Tick,
+        last_tick: Tick,
+    },
+    NoEndAction {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        running_end_tick: Tick,
+    },
+    NotAttached {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    UnscheduledTimer {
+        timer_id: TimerId,
+        tick: Tick,
+    },
+    InvalidJointTicks {
+        start_tick: Tick,
+        end_tick: Tick,
+    },
+    // The pending cancellation of the frozen entity without the reason, which isn't saved
+    UnsavedCancel {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        tick: Tick,
+    },
+}
+
+impl ActionManager {
+    // Checks the internal invariants, and returns all violations found. It's too slow to call
+    // on every tick of the production, and intended for the tests and the debug builds.
+    pub fn verify(&self) -> Result<(), Vec<ActionManagerViolation>> {
+        let mut violations = Vec::new();
+        if self.next_tick < self.current_tick {
+            violations.push(ActionManagerViolation::BackwardClock {
+                current_tick: self.current_tick,
+                next_tick: self.next_tick,
+            });
+        }
+
+        let next_session_id = *self.session_id_manager.current();
+        let mut session_ids = BTreeSet::new();
+        for ((entity_id, layer), context) in self.contexts.iter() {
+            if next_session_id <= context.session_id || !session_ids.insert(context.session_id) {
+                violations.push(ActionManagerViolation::InvalidSession {
+                    entity_id: *entity_id,
+                    layer: *layer,
+                    session_id: context.session_id,
+                });
+            }
+            if context.last_tick < context.running_end_tick {
+                violations.push(ActionManagerViolation::InvalidContextTicks {
+                    entity_id: *entity_id,
+                    layer: *layer,
+                    running_end_tick: context.running_end_tick,
+                    last_tick: context.last_tick,
+                });
+            }
+
+            // The running action or the cancellation must finish the running session
+            let current_tick = entity_tick(&self.freezes, self.current_tick, *entity_id);
+            if context.running_end_tick <= current_tick {
+                continue;
+            }
+            let actions = match self.freezes.get(entity_id) {
+                Some(frozen) => &frozen.actions,
+                None => &self.actions,
+            };
+            let found = actions
+                .get(&context.running_end_tick)
+                .map(|item| {
+                    item.events.iter().any(|(key, (session_id, event))| {
+                        *key == (*entity_id, *layer)
+                            && *session_id == context.session_id
+                            && matches!(event, Event::Ended | Event::Canceled)
+                    })
+                })
+                .unwrap_or(false);
+            if !found {
+                violations.push(ActionManagerViolation::NoEndAction {
+                    entity_id: *entity_id,
+                    layer: *layer,
+                    running_end_tick: context.running_end_tick,
+                });
+            }
+        }
+
+        let bundles = self
+            .freezes
+            .iter()
+            .map(|(entity_id, frozen)| (Some(*entity_id), frozen.tick, &frozen.actions))
+            .chain(std::iter::once((None, self.current_tick, &self.actions)));
+        for (frozen_entity_id, current_tick, actions) in bundles {
+            for (tick, item) in actions.iter() {
+                if *tick < current_tick {
+                    violations.push(ActionManagerViolation::StaleBundle {
+                        entity_id: frozen_entity_id,
+                        tick: *tick,
+                        current_tick,
+                    });
+                }
+                for meta in item
+                    .map
+                    .iter()
+                    .flat_map(|(_, vec)| vec.iter().map(|(meta, _)| meta))
+                {
+                    if meta.tick != *tick
+                        || frozen_entity_id.is_some_and(|entity_id| entity_id != meta.entity_id)
+                    {
+                        violations.push(ActionManagerViolation::MisplacedAction {
+                            entity_id: meta.entity_id,
+                            layer: meta.layer,
+                            tick: meta.tick,
+                            bundle_tick: *tick,
+                        });
+                    }
+                }
+                for ((entity_id, layer), (session_id, event)) in item.events.iter() {
+                    let context = self.contexts.get(&(*entity_id, *layer));
+                    let is_future_session = match context {
+                        Some(context) => context.session_id < *session_id,
+                        None => next_session_id <= *session_id,
+                    };
+                    if is_future_session {
+                        violations.push(ActionManagerViolation::InvalidSession {
+                            entity_id: *entity_id,
+                            layer: *layer,
+                            session_id: *session_id,
+                        });
+                    }
+                    // to_data saves the frozen cancellation with the reason
+                    let is_pending_cancel = frozen_entity_id.is_some()
+                        && matches!(event, Event::Canceled)
+                        && context.is_some_and(|context| context.session_id == *session_id);
+                    if is_pending_cancel && !item.cancels.contains_key(&(*entity_id, *layer)) {
+                        violations.push(ActionManagerViolation::UnsavedCancel {
+                            entity_id: *entity_id,
+                            layer: *layer,
+                            tick: *tick,
+                        });
+                    }
+                }
+            }
+        }
+
+        for (entity_id, layer) in self.vacated_entities.iter() {
+            if !self.contexts.contains_key(&(*entity_id, *layer)) {
+                violations.push(ActionManagerViolation::NotAttached {
+                    entity_id: *entity_id,
+                    layer: *layer,
+                });
+            }
+        }
+        for (entity_id, frozen) in self.freezes.iter() {
+            if self.current_tick < frozen.tick {
+                violations.push(ActionManagerViolation::StaleBundle {
+                    entity_id: Some(*entity_id),
+                    tick: frozen.tick,
+                    current_tick: self.current_tick,
+                });
+            }
+        }
+        for (timer_id, timer) in self.timers.iter() {
+            let scheduled = self
+                .actions
+                .get(&timer.tick)
+                .map(|item| item.timers.contains(timer_id))
+                .unwrap_or(false);
+            if !scheduled {
+                violations.push(ActionManagerViolation::UnscheduledTimer {
+                    timer_id: *timer_id,
+                    tick: timer.tick,
+                });
+            }
+        }
+        for joint in self.joints.iter() {
+            if joint.end_tick < joint.start_tick {
+                violations.push(ActionManagerViolation::InvalidJointTicks {
+                    start_tick: joint.start_tick,
+                    end_tick: joint.end_tick,
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::test::{JumpState, MoveState};
+    use crate::v2::action::manager::{ActionManager, ActionManagerViolation};
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_verify() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 300); // tick: 0-3
+        manager.enqueue(1, Arc::new(JumpState), 200); // tick: 3-5
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        assert_eq!(manager.verify(), Ok(()));
+
+        // Breaks the running action
+        manager.actions.remove(&3);
+        manager.contexts.get_mut(&(1, 0)).unwrap().last_tick = 2;
+        assert_eq!(
+            manager.verify(),
+            Err(vec![
+                ActionManagerViolation::InvalidContextTicks {
+                    entity_id: 1,
+                    layer: 0,
+                    running_end_tick: 3,
+                    last_tick: 2,
+                },
+                ActionManagerViolation::NoEndAction {
+                    entity_id: 1,
+                    layer: 0,
+                    running_end_tick: 3,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_verify_frozen_cancel() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.pull_vacated_entities();
+        manager.enqueue(1, Arc::new(MoveState), 300); // tick: 0-3
+        manager.update(100);
+        while manager.pull_actions().is_some() {}
+        manager.freeze(1);
+        manager.cancel(1, false);
+        assert_eq!(manager.verify(), Ok(()));
+
+        // Loses the reason of the pending cancellation
+        manager
+            .freezes
+            .get_mut(&1)
+            .unwrap()
+            .actions
+            .get_mut(&3)
+            .unwrap()
+            .cancels
+            .clear();
+        assert_eq!(
+            manager.verify(),
+            Err(vec![ActionManagerViolation::UnsavedCancel {
+                entity_id: 1,
+                layer: 0,
+                tick: 3,
+            }])
+        );
+    }
+}
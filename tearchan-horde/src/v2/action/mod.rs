@@ -1,13 +1,21 @@
 use crate::action::manager::TimeMilliseconds;
 use crate::v2::Tick;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 use tearchan_ecs::component::EntityId;
 
 pub mod collection;
 pub mod manager;
+pub mod replication;
+pub mod timer;
+pub mod tween;
 
 pub const VALID_SESSION_ID: ActionSessionId = ActionSessionId(0);
+pub const DEFAULT_ACTION_LAYER: ActionLayer = 0;
+
+// Actions in different layers of the same entity run concurrently with their own queues
+pub type ActionLayer = u32;
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct ActionSessionId(u128);
@@ -24,6 +32,36 @@ impl ActionSessionId {
     }
 }
 
+impl Display for ActionSessionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Why the actions are canceled
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum CancelReason {
+    #[default]
+    Unspecified,
+    // The other participant of the joint action is canceled or detached
+    JointPartnerCanceled,
+    // Defined by the users, e.g. their reason encoded with any serde format
+    User(Arc<[u8]>),
+}
+
+impl CancelReason {
+    pub fn user<T: Into<Arc<[u8]>>>(payload: T) -> Self {
+        CancelReason::User(payload.into())
+    }
+
+    pub fn payload(&self) -> Option<&[u8]> {
+        match self {
+            CancelReason::User(payload) => Some(payload),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum ActionType {
     Start {
@@ -115,18 +153,65 @@ impl ActionType {
             }
         }
     }
+
+    // Returns None if the time of the update action overflows
+    pub fn rescale(&self, from: TimeMilliseconds, to: TimeMilliseconds) -> Option<ActionType> {
+        if from == to {
+            return Some(*self);
+        }
+        let ty = match self {
+            ActionType::Start { start, end, each } => ActionType::Start {
+                start: rescale_tick(*start, from, to),
+                end: rescale_tick(*end, from, to),
+                each: *each,
+            },
+            ActionType::Update { start, end } => ActionType::Update {
+                start: rescale_tick(*start / from, from, to).checked_mul(to)?,
+                end: rescale_tick(*end / from, from, to).checked_mul(to)?,
+            },
+            ActionType::End { start, end } => ActionType::End {
+                start: rescale_tick(*start, from, to),
+                end: rescale_tick(*end, from, to),
+            },
+            ActionType::EachTick { start, end } => ActionType::EachTick {
+                start: rescale_tick(*start, from, to),
+                end: rescale_tick(*end, from, to),
+            },
+        };
+        Some(ty)
+    }
+}
+
+// Converts the tick measured by the tick duration of `from` into the tick measured by `to`
+pub fn rescale_tick(tick: Tick, from: TimeMilliseconds, to: TimeMilliseconds) -> Tick {
+    if from == to {
+        return tick;
+    }
+    let tick = tick as u128 * from as u128 / to as u128;
+    tick.min(Tick::MAX as u128) as Tick
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Action<T> {
     raw: T,
     entity_id: EntityId,
+    #[serde(default)]
+    layer: ActionLayer,
     ty: ActionType,
 }
 
 impl<T> Action<T> {
     pub fn new(raw: T, entity_id: EntityId, ty: ActionType) -> Self {
-        Self { raw, entity_id, ty }
+        Self::with_layer(raw, entity_id, DEFAULT_ACTION_LAYER, ty)
+    }
+
+    pub fn with_layer(raw: T, entity_id: EntityId, layer: ActionLayer, ty: ActionType) -> Self {
+        Self {
+            raw,
+            entity_id,
+            layer,
+            ty,
+        }
     }
 
     pub fn tick(&self) -> Option<Tick> {
@@ -145,6 +230,7 @@ impl<T> Action<T> {
         Action {
             raw,
             entity_id: self.entity_id,
+            layer: self.layer,
             ty: self.ty,
         }
     }
@@ -153,6 +239,10 @@ impl<T> Action<T> {
         self.entity_id
     }
 
+    pub fn layer(&self) -> ActionLayer {
+        self.layer
+    }
+
     pub fn ty(&self) -> &ActionType {
         &self.ty
     }
@@ -0,0 +1,52 @@
+use crate::v2::action::{Action, ActionLayer, CancelReason};
+use crate::v2::Tick;
+use serde::{Deserialize, Serialize};
+use tearchan_ecs::component::EntityId;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ReplicatedCommand<T> {
+    AttachLayer {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    DetachLayer {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    Schedule {
+        action: Action<T>, // The action type is always Start
+    },
+    Cancel {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        tick: Tick,
+        #[serde(default)]
+        reason: CancelReason,
+    },
+    Reschedule {
+        entity_id: EntityId,
+        layer: ActionLayer,
+        end_tick: Tick,
+    },
+    Freeze {
+        entity_id: EntityId,
+    },
+    Unfreeze {
+        entity_id: EntityId,
+    },
+}
+
+// The commands committed by the server since the last delta, in the order of the execution.
+// The server and the clients must share the tick duration.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplicationDelta<T> {
+    pub tick: Tick, // The current tick of the server on committing
+    pub commands: Vec<ReplicatedCommand<T>>,
+}
+
+impl<T> ReplicationDelta<T> {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
@@ -0,0 +1,52 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::Tick;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct TimerId(u64);
+
+impl Default for TimerId {
+    fn default() -> Self {
+        TimerId(1)
+    }
+}
+
+impl TimerId {
+    pub fn next(&self) -> Self {
+        TimerId(self.0 + 1)
+    }
+}
+
+#[derive(Default)]
+pub struct TimerOptions {
+    pub interval: Option<TimeMilliseconds>, // Repeats the timer with the interval until canceled
+}
+
+// Scheduled event which doesn't belong to any entity
+#[derive(Debug, Clone)]
+pub struct Timer<T> {
+    id: TimerId,
+    raw: T,
+    interval: Option<Tick>,
+}
+
+impl<T> Timer<T> {
+    pub fn new(id: TimerId, raw: T, interval: Option<Tick>) -> Self {
+        Timer { id, raw, interval }
+    }
+
+    #[inline]
+    pub fn id(&self) -> TimerId {
+        self.id
+    }
+
+    #[inline]
+    pub fn raw(&self) -> &T {
+        &self.raw
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Option<Tick> {
+        self.interval
+    }
+}
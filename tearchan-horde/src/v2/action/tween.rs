@@ -0,0 +1,147 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::ActionType;
+use crate::v2::calc_ratio_f32_from_ms;
+use crate::v2::easing::Easing;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+pub trait Tweenable: Clone {
+    fn lerp(&self, to: &Self, ratio: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    #[inline]
+    fn lerp(&self, to: &Self, ratio: f32) -> Self {
+        self + (to - self) * ratio
+    }
+}
+
+impl Tweenable for Vec2 {
+    #[inline]
+    fn lerp(&self, to: &Self, ratio: f32) -> Self {
+        nalgebra_glm::lerp(self, to, ratio)
+    }
+}
+
+impl Tweenable for Vec3 {
+    #[inline]
+    fn lerp(&self, to: &Self, ratio: f32) -> Self {
+        nalgebra_glm::lerp(self, to, ratio)
+    }
+}
+
+impl Tweenable for Vec4 {
+    #[inline]
+    fn lerp(&self, to: &Self, ratio: f32) -> Self {
+        nalgebra_glm::lerp(self, to, ratio)
+    }
+}
+
+// The action state interpolating the value from the start to the end of the action, e.g. for
+// the movement, the colour and the scale
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tween<T> {
+    pub from: T,
+    pub to: T,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+pub type TweenF32 = Tween<f32>;
+pub type TweenVec2 = Tween<Vec2>;
+pub type TweenVec3 = Tween<Vec3>;
+pub type TweenVec4 = Tween<Vec4>;
+
+impl<T> Tween<T>
+where
+    T: Tweenable,
+{
+    pub fn new(from: T, to: T, easing: Easing) -> Self {
+        Tween { from, to, easing }
+    }
+
+    #[inline]
+    pub fn value(&self, ratio: f32) -> T {
+        self.from.lerp(&self.to, self.easing.apply(ratio))
+    }
+
+    // The value of the action at the time. The End action is always at the end.
+    pub fn evaluate(
+        &self,
+        ty: &ActionType,
+        time: TimeMilliseconds,
+        tick_duration: TimeMilliseconds,
+    ) -> T {
+        let ratio = match ty {
+            ActionType::Start { start, end, .. } | ActionType::EachTick { start, end } => {
+                calc_ratio_f32_from_ms(start * tick_duration, end * tick_duration, time)
+            }
+            ActionType::Update { start, end } => calc_ratio_f32_from_ms(*start, *end, time),
+            ActionType::End { .. } => 1.0f32,
+        };
+        self.value(ratio)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::action::manager::ActionManager;
+    use crate::v2::action::tween::{Tween, TweenF32, TweenVec2};
+    use crate::v2::action::ActionType;
+    use crate::v2::easing::{Easing, EasingCurve};
+    use nalgebra_glm::vec2;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_evaluate() {
+        let mut manager = ActionManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.enqueue(
+            1,
+            Arc::new(TweenVec2::new(
+                vec2(0.0f32, 0.0f32),
+                vec2(10.0f32, 20.0f32),
+                Easing::Linear,
+            )),
+            1000,
+        );
+        manager.enqueue(
+            2,
+            Arc::new(TweenF32::new(1.0f32, 2.0f32, Easing::In(EasingCurve::Quad))),
+            1000,
+        );
+        manager.update(500);
+        while manager.pull_actions().is_some() {}
+
+        let positions = manager.pull_updates().get::<TweenVec2>().unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(manager.evaluate_tween(positions[0]), vec2(5.0f32, 10.0f32));
+        let scales = manager.pull_updates().get::<TweenF32>().unwrap();
+        assert_eq!(manager.evaluate_tween(scales[0]), 1.25f32);
+
+        manager.update(500);
+        let result = manager.pull_actions().unwrap();
+        let ends = result
+            .map
+            .get::<TweenF32>(&manager.validator())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|action| matches!(action.ty(), ActionType::End { .. }))
+            .collect::<Vec<_>>();
+        assert_eq!(manager.evaluate_tween(ends[0]), 2.0f32);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let tween = Tween::new(
+            vec2(1.0f32, 2.0f32),
+            vec2(3.0f32, 4.0f32),
+            Easing::Out(EasingCurve::Bounce),
+        );
+        let str = serde_json::to_string(&tween).unwrap();
+        assert_eq!(serde_json::from_str::<TweenVec2>(&str).unwrap(), tween);
+        let tween: TweenF32 = serde_json::from_str(r#"{"from":0.0,"to":1.0}"#).unwrap();
+        assert_eq!(tween.easing, Easing::Linear);
+    }
+}
@@ -0,0 +1,460 @@
+use crate::v2::Tick;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use tearchan_ecs::component::EntityId;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// FNV-1a hasher that produces the same value on every platform.
+// Action types are folded into the hash by their type names, so checksums are comparable between
+// the builds of the same source with the same compiler.
+#[derive(Clone, Debug)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for StateHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_i16(&mut self, i: i16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_i32(&mut self, i: i32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_i64(&mut self, i: i64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_i128(&mut self, i: i128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+pub fn hash_slice<T>(values: &[T], hasher: &mut StateHasher)
+where
+    T: Hash,
+{
+    values.hash(hasher);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct StateChecksum {
+    tick: Tick,
+    hash: u64,
+    #[serde(default)]
+    timers: u64, // The hash of the pending timers, which don't belong to any entity
+    entities: BTreeMap<EntityId, u64>,
+}
+
+impl StateChecksum {
+    pub fn new(tick: Tick, timers: u64, entities: BTreeMap<EntityId, u64>) -> Self {
+        let mut hasher = StateHasher::default();
+        tick.hash(&mut hasher);
+        timers.hash(&mut hasher);
+        for (entity_id, hash) in entities.iter() {
+            entity_id.hash(&mut hasher);
+            hash.hash(&mut hasher);
+        }
+        StateChecksum {
+            tick,
+            hash: hasher.finish(),
+            timers,
+            entities,
+        }
+    }
+
+    #[inline]
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    #[inline]
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    #[inline]
+    pub fn timers(&self) -> u64 {
+        self.timers
+    }
+
+    #[inline]
+    pub fn entities(&self) -> &BTreeMap<EntityId, u64> {
+        &self.entities
+    }
+
+    pub fn find_divergent_entity(&self, other: &StateChecksum) -> Option<EntityId> {
+        if self.hash == other.hash {
+            return None;
+        }
+        let mut entity_ids = self
+            .entities
+            .keys()
+            .chain(other.entities.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        entity_ids.sort_unstable();
+        entity_ids
+            .into_iter()
+            .find(|entity_id| self.entities.get(entity_id) != other.entities.get(entity_id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StateDivergence {
+    pub tick: Tick,
+    pub entity_id: Option<EntityId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StateChecksumHistory {
+    capacity: usize,
+    checksums: VecDeque<StateChecksum>,
+}
+
+impl StateChecksumHistory {
+    pub fn new(capacity: usize) -> Self {
+        StateChecksumHistory {
+            capacity,
+            checksums: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // The checksum of the same tick overwrites the previous one
+    pub fn push(&mut self, checksum: StateChecksum) {
+        if let Some(last) = self.checksums.back_mut() {
+            if last.tick == checksum.tick {
+                *last = checksum;
+                return;
+            }
+        }
+        self.checksums.push_back(checksum);
+        while self.checksums.len() > self.capacity {
+            self.checksums.pop_front();
+        }
+    }
+
+    pub fn get(&self, tick: Tick) -> Option<&StateChecksum> {
+        self.checksums.iter().find(|checksum| checksum.tick == tick)
+    }
+
+    #[inline]
+    pub fn latest(&self) -> Option<&StateChecksum> {
+        self.checksums.back()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StateChecksum> {
+        self.checksums.iter()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.checksums.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.checksums.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.checksums.clear();
+    }
+
+    pub fn truncate_after(&mut self, tick: Tick) {
+        while let Some(last) = self.checksums.back() {
+            if last.tick <= tick {
+                break;
+            }
+            self.checksums.pop_back();
+        }
+    }
+
+    // Compares the checksums of the ticks recorded in both histories
+    pub fn find_divergence(&self, other: &StateChecksumHistory) -> Option<StateDivergence> {
+        self.checksums.iter().find_map(|checksum| {
+            let other = other.get(checksum.tick)?;
+            if checksum.hash == other.hash {
+                return None;
+            }
+            Some(StateDivergence {
+                tick: checksum.tick,
+                entity_id: checksum.find_divergent_entity(other),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::{ActionController, ActionManager, EnqueueOptions};
+    use crate::v2::action::CancelReason;
+    use crate::v2::checksum::{StateChecksumHistory, StateDivergence, StateHasher};
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JumpState;
+
+    define_actions!(TestAction, (Move, MoveState), (Jump, JumpState));
+
+    #[derive(Default)]
+    struct TestProvider {
+        positions: HashMap<EntityId, i32>,
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = u32;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<u32>) {
+            for action in map
+                .get::<MoveState>(&controller.validator())
+                .unwrap_or_default()
+            {
+                *self.positions.entry(action.entity_id()).or_default() += 1;
+            }
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, _entity_id: EntityId, _jobs: Vec<u32>, _reason: CancelReason) {}
+
+        fn on_first(&self, _entity_id: EntityId, priority: u32) -> u32 {
+            priority
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            _job: u32,
+            controller: &mut ActionController,
+        ) -> Option<u32> {
+            controller.enqueue(entity_id, Arc::new(MoveState), 200 * entity_id);
+            None
+        }
+
+        fn on_checksum(&self, entity_id: EntityId, hasher: &mut StateHasher) {
+            self.positions.get(&entity_id).hash(hasher);
+        }
+    }
+
+    fn create_manager() -> ActionManager {
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.enqueue(1, Arc::new(MoveState), 1000);
+        manager.enqueue_with_options(1, Arc::new(JumpState), 500, EnqueueOptions { each: true });
+        manager.enqueue(2, Arc::new(JumpState), 700);
+        manager.pull_vacated_entities();
+        manager
+    }
+
+    #[test]
+    fn test_stable_hasher() {
+        let mut hasher = StateHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+
+        let mut hasher0 = StateHasher::default();
+        let mut hasher1 = StateHasher::default();
+        1u64.hash(&mut hasher0);
+        hasher1.write(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(hasher0.finish(), hasher1.finish());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut manager0 = create_manager();
+        let mut manager1 = create_manager();
+        manager0.update(600);
+        manager1.update(600);
+        while manager0.pull_actions().is_some() {}
+        while manager1.pull_actions().is_some() {}
+        assert_eq!(manager0.checksum(), manager1.checksum());
+
+        let data = manager0.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        let manager2 = ActionManager::from_data(data, convert_from_actions).unwrap();
+        assert_eq!(manager0.checksum(), manager2.checksum());
+
+        let checksum0 = manager0.checksum_with(|entity_id, hasher| entity_id.hash(hasher));
+        let checksum1 = manager1.checksum_with(|entity_id, hasher| {
+            if entity_id == 2 {
+                0u8.hash(hasher);
+            }
+            entity_id.hash(hasher)
+        });
+        assert_eq!(checksum0.find_divergent_entity(&checksum1), Some(2));
+    }
+
+    #[test]
+    fn test_checksum_with_timers() {
+        let mut manager0 = create_manager();
+        let mut manager1 = create_manager();
+        let timer_id = manager0.schedule_timer(Arc::new(JumpState), 1000);
+        manager1.schedule_timer(Arc::new(JumpState), 1000);
+        manager0.update(600);
+        manager1.update(600);
+        while manager0.pull_actions().is_some() {}
+        while manager1.pull_actions().is_some() {}
+        assert_eq!(manager0.checksum(), manager1.checksum());
+
+        let data = manager0.to_data(
+            convert_actions_from_typed_action_any_map,
+            convert_actions_from_typed_any_action_map,
+            convert_actions_from_any_action_vec,
+        );
+        let manager2 = ActionManager::from_data(data, convert_from_actions).unwrap();
+        assert_eq!(manager0.checksum(), manager2.checksum());
+
+        manager1.cancel_timer(timer_id);
+        let checksum0 = manager0.checksum();
+        let checksum1 = manager1.checksum();
+        assert_ne!(checksum0.hash(), checksum1.hash());
+        assert_ne!(checksum0.timers(), checksum1.timers());
+        assert_eq!(checksum0.entities(), checksum1.entities());
+        assert_eq!(checksum0.find_divergent_entity(&checksum1), None);
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let mut manager0 = create_manager();
+        let mut manager1 = create_manager();
+        let mut history0 = StateChecksumHistory::new(4);
+        let mut history1 = StateChecksumHistory::new(4);
+
+        for tick in 0..10 {
+            if tick == 6 {
+                manager1.cancel(1, true);
+            }
+            manager0.update(66);
+            manager1.update(66);
+            while manager0.pull_actions().is_some() {}
+            while manager1.pull_actions().is_some() {}
+            manager0.pull_vacated_entities();
+            manager1.pull_vacated_entities();
+            history0.push(manager0.checksum());
+            history1.push(manager1.checksum());
+        }
+
+        assert_eq!(history0.len(), 4);
+        assert_eq!(history0.iter().next().unwrap().tick(), 7);
+        assert_eq!(
+            history0.find_divergence(&history1),
+            Some(StateDivergence {
+                tick: 7,
+                entity_id: Some(1)
+            })
+        );
+        assert_eq!(history0.find_divergence(&history0), None);
+    }
+
+    #[test]
+    fn test_job_manager_checksum_history() {
+        let mut providers = [TestProvider::default(), TestProvider::default()];
+        let mut managers: [JobManager<TestProvider>; 2] = Default::default();
+        for manager in managers.iter_mut() {
+            manager.enable_checksum_history(100);
+            manager.attach(1);
+            manager.attach(2);
+        }
+
+        for frame in 0..30 {
+            for (index, (manager, provider)) in
+                managers.iter_mut().zip(providers.iter_mut()).enumerate()
+            {
+                if index == 1 && frame == 20 {
+                    manager.interrupt(2, Arc::new(JumpState), 100);
+                }
+                manager.run(provider, 100);
+            }
+        }
+
+        let history0 = managers[0].checksum_history().unwrap();
+        let history1 = managers[1].checksum_history().unwrap();
+        assert_eq!(
+            history0.latest(),
+            Some(&managers[0].checksum(&providers[0]))
+        );
+        let divergence = history0.find_divergence(history1).unwrap();
+        assert_eq!(divergence.entity_id, Some(2));
+        assert!(history0
+            .iter()
+            .take_while(|checksum| checksum.tick() < divergence.tick)
+            .all(|checksum| history1.get(checksum.tick()) == Some(checksum)));
+    }
+}
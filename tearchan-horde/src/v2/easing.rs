@@ -0,0 +1,267 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+// The curves by Robert Penner. The out and the in-out variants are derived from the in variant.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum EasingCurve {
+    Sine,
+    Quad,
+    Cubic,
+    Quart,
+    Quint,
+    Expo,
+    Circ,
+    Back,
+    Elastic,
+    Bounce,
+}
+
+impl EasingCurve {
+    pub fn ease_in(&self, t: f32) -> f32 {
+        match self {
+            EasingCurve::Sine => 1.0f32 - (t * PI / 2.0f32).cos(),
+            EasingCurve::Quad => t * t,
+            EasingCurve::Cubic => t * t * t,
+            EasingCurve::Quart => t * t * t * t,
+            EasingCurve::Quint => t * t * t * t * t,
+            EasingCurve::Expo => {
+                if t <= 0.0f32 {
+                    0.0f32
+                } else {
+                    2.0f32.powf(10.0f32 * t - 10.0f32)
+                }
+            }
+            EasingCurve::Circ => 1.0f32 - (1.0f32 - t * t).max(0.0f32).sqrt(),
+            EasingCurve::Back => {
+                let c1 = 1.70158f32;
+                (c1 + 1.0f32) * t * t * t - c1 * t * t
+            }
+            EasingCurve::Elastic => {
+                if t <= 0.0f32 || t >= 1.0f32 {
+                    return t.clamp(0.0f32, 1.0f32);
+                }
+                let c4 = 2.0f32 * PI / 3.0f32;
+                -(2.0f32.powf(10.0f32 * t - 10.0f32)) * ((t * 10.0f32 - 10.75f32) * c4).sin()
+            }
+            EasingCurve::Bounce => 1.0f32 - bounce_out(1.0f32 - t),
+        }
+    }
+
+    #[inline]
+    pub fn ease_out(&self, t: f32) -> f32 {
+        1.0f32 - self.ease_in(1.0f32 - t)
+    }
+
+    pub fn ease_in_out(&self, t: f32) -> f32 {
+        if t < 0.5f32 {
+            self.ease_in(t * 2.0f32) / 2.0f32
+        } else {
+            1.0f32 - self.ease_in(2.0f32 - t * 2.0f32) / 2.0f32
+        }
+    }
+}
+
+fn bounce_out(t: f32) -> f32 {
+    let n1 = 7.5625f32;
+    let d1 = 2.75f32;
+    if t < 1.0f32 / d1 {
+        n1 * t * t
+    } else if t < 2.0f32 / d1 {
+        let t = t - 1.5f32 / d1;
+        n1 * t * t + 0.75f32
+    } else if t < 2.5f32 / d1 {
+        let t = t - 2.25f32 / d1;
+        n1 * t * t + 0.9375f32
+    } else {
+        let t = t - 2.625f32 / d1;
+        n1 * t * t + 0.984375f32
+    }
+}
+
+// Where the steps jump, the same as jump-start and jump-end of CSS
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum StepPosition {
+    Start,
+    End,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    In(EasingCurve),
+    Out(EasingCurve),
+    InOut(EasingCurve),
+    // The control points of the curve from (0, 0) to (1, 1), the same as cubic-bezier of CSS.
+    // x1 and x2 must be in 0..=1.
+    CubicBezier {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+    },
+    Steps {
+        count: u32,
+        position: StepPosition,
+    },
+}
+
+impl Easing {
+    // Maps the ratio clamped to 0..=1. Some curves, e.g. Back and Elastic, overshoot the range.
+    pub fn apply(&self, ratio: f32) -> f32 {
+        let t = ratio.clamp(0.0f32, 1.0f32);
+        match self {
+            Easing::Linear => t,
+            Easing::In(curve) => curve.ease_in(t),
+            Easing::Out(curve) => curve.ease_out(t),
+            Easing::InOut(curve) => curve.ease_in_out(t),
+            Easing::CubicBezier { x1, y1, x2, y2 } => cubic_bezier(*x1, *y1, *x2, *y2, t),
+            Easing::Steps { count, position } => {
+                let count = (*count).max(1) as f32;
+                let step = match position {
+                    StepPosition::Start => (t * count).ceil(),
+                    StepPosition::End => (t * count).floor(),
+                };
+                (step / count).min(1.0f32)
+            }
+        }
+    }
+}
+
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, x: f32) -> f32 {
+    let sample = |a1: f32, a2: f32, s: f32| {
+        let u = 1.0f32 - s;
+        3.0f32 * u * u * s * a1 + 3.0f32 * u * s * s * a2 + s * s * s
+    };
+    let slope = |a1: f32, a2: f32, s: f32| {
+        let u = 1.0f32 - s;
+        3.0f32 * u * u * a1 + 6.0f32 * u * s * (a2 - a1) + 3.0f32 * s * s * (1.0f32 - a2)
+    };
+
+    // Finds the parameter of x by Newton's method, and falls back to the bisection
+    let mut s = x;
+    for _ in 0..8 {
+        let error = sample(x1, x2, s) - x;
+        if error.abs() < 1e-6f32 {
+            return sample(y1, y2, s);
+        }
+        let d = slope(x1, x2, s);
+        if d.abs() < 1e-6f32 {
+            break;
+        }
+        s -= error / d;
+    }
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    s = x;
+    for _ in 0..32 {
+        let value = sample(x1, x2, s);
+        if (value - x).abs() < 1e-6f32 {
+            break;
+        }
+        if value < x {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0f32;
+    }
+    sample(y1, y2, s)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::v2::easing::{Easing, EasingCurve, StepPosition};
+
+    const CURVES: [EasingCurve; 10] = [
+        EasingCurve::Sine,
+        EasingCurve::Quad,
+        EasingCurve::Cubic,
+        EasingCurve::Quart,
+        EasingCurve::Quint,
+        EasingCurve::Expo,
+        EasingCurve::Circ,
+        EasingCurve::Back,
+        EasingCurve::Elastic,
+        EasingCurve::Bounce,
+    ];
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3f32,
+            "actual={}, expected={}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_curves() {
+        for curve in CURVES.iter() {
+            for easing in [
+                Easing::In(*curve),
+                Easing::Out(*curve),
+                Easing::InOut(*curve),
+            ] {
+                assert_near(easing.apply(0.0f32), 0.0f32);
+                assert_near(easing.apply(1.0f32), 1.0f32);
+                assert_near(easing.apply(-1.0f32), 0.0f32);
+                assert_near(easing.apply(2.0f32), 1.0f32);
+            }
+            assert_near(Easing::InOut(*curve).apply(0.5f32), 0.5f32);
+        }
+
+        assert_near(Easing::In(EasingCurve::Quad).apply(0.5f32), 0.25f32);
+        assert_near(Easing::Out(EasingCurve::Quad).apply(0.5f32), 0.75f32);
+        assert_near(Easing::In(EasingCurve::Cubic).apply(0.5f32), 0.125f32);
+        assert_near(Easing::In(EasingCurve::Sine).apply(0.5f32), 0.292_893f32);
+        assert_near(Easing::Out(EasingCurve::Bounce).apply(0.5f32), 0.765_625f32);
+        assert!(Easing::In(EasingCurve::Back).apply(0.2f32) < 0.0f32);
+        assert!(Easing::Out(EasingCurve::Elastic).apply(0.2f32) > 1.0f32);
+    }
+
+    #[test]
+    fn test_cubic_bezier() {
+        let linear = Easing::CubicBezier {
+            x1: 0.0f32,
+            y1: 0.0f32,
+            x2: 1.0f32,
+            y2: 1.0f32,
+        };
+        for i in 0..=10 {
+            let t = i as f32 / 10.0f32;
+            assert_near(linear.apply(t), t);
+        }
+
+        // ease of CSS
+        let ease = Easing::CubicBezier {
+            x1: 0.25f32,
+            y1: 0.1f32,
+            x2: 0.25f32,
+            y2: 1.0f32,
+        };
+        assert_near(ease.apply(0.0f32), 0.0f32);
+        assert_near(ease.apply(0.5f32), 0.802_4f32);
+        assert_near(ease.apply(1.0f32), 1.0f32);
+    }
+
+    #[test]
+    fn test_steps() {
+        let end = Easing::Steps {
+            count: 4,
+            position: StepPosition::End,
+        };
+        assert_near(end.apply(0.0f32), 0.0f32);
+        assert_near(end.apply(0.3f32), 0.25f32);
+        assert_near(end.apply(0.99f32), 0.75f32);
+        assert_near(end.apply(1.0f32), 1.0f32);
+
+        let start = Easing::Steps {
+            count: 4,
+            position: StepPosition::Start,
+        };
+        assert_near(start.apply(0.0f32), 0.0f32);
+        assert_near(start.apply(0.01f32), 0.25f32);
+        assert_near(start.apply(0.3f32), 0.5f32);
+        assert_near(start.apply(1.0f32), 1.0f32);
+    }
+}
@@ -0,0 +1,534 @@
+use crate::v2::action::manager::ActionController;
+use crate::v2::action::ActionLayer;
+use serde::{Deserialize, Serialize};
+use tearchan_ecs::component::EntityId;
+
+pub type BehaviorTreeId = u32;
+type NodeId = usize;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BehaviorStatus {
+    Success,
+    Failure,
+    // The action node has enqueued some actions, and the rest of the tree is evaluated after them
+    Running,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParallelPolicy {
+    RequireAll,
+    RequireOne,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Decorator {
+    Inverter,
+    AlwaysSucceed,
+    AlwaysFail,
+    Repeat(u32), // Runs the child n times, and fails as soon as it fails. Repeat(0) never runs it.
+}
+
+pub enum Behavior<A, C> {
+    Sequence(Vec<Behavior<A, C>>),
+    Selector(Vec<Behavior<A, C>>),
+    // Evaluates all children regardless of their results.
+    // The actions enqueued to the different layers run concurrently.
+    Parallel(ParallelPolicy, Vec<Behavior<A, C>>),
+    Decorator(Decorator, Box<Behavior<A, C>>),
+    Condition(C),
+    Action(A),
+}
+
+pub trait BehaviorInterface {
+    type Action;
+    type Condition;
+
+    fn on_condition(
+        &self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        condition: &Self::Condition,
+    ) -> bool;
+
+    fn on_action(
+        &self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        action: &Self::Action,
+        controller: &mut ActionController,
+    ) -> BehaviorStatus;
+}
+
+enum Node<A, C> {
+    Sequence(Vec<NodeId>),
+    Selector(Vec<NodeId>),
+    Parallel(ParallelPolicy, Vec<NodeId>),
+    Decorator(Decorator, NodeId),
+    Condition(C),
+    Action(A),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+struct Frame {
+    node: NodeId,
+    child: usize, // The index of the next child
+    count: u32,   // The succeeded children of parallel or the iterations of repeat
+}
+
+// The evaluation state of the tree, which is used as the job of HordeInterface
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct BehaviorJob {
+    tree: BehaviorTreeId,
+    stack: Vec<Frame>,
+    status: Option<BehaviorStatus>, // The result of the last evaluated node
+}
+
+impl BehaviorJob {
+    #[inline]
+    pub fn tree(&self) -> BehaviorTreeId {
+        self.tree
+    }
+}
+
+pub struct BehaviorTree<A, C> {
+    nodes: Vec<Node<A, C>>,
+    root: NodeId,
+}
+
+impl<A, C> BehaviorTree<A, C> {
+    pub fn new(root: Behavior<A, C>) -> Self {
+        let mut nodes = Vec::new();
+        let root = flatten(root, &mut nodes);
+        BehaviorTree { nodes, root }
+    }
+}
+
+fn flatten<A, C>(behavior: Behavior<A, C>, nodes: &mut Vec<Node<A, C>>) -> NodeId {
+    let node = match behavior {
+        Behavior::Sequence(children) => Node::Sequence(flatten_children(children, nodes)),
+        Behavior::Selector(children) => Node::Selector(flatten_children(children, nodes)),
+        Behavior::Parallel(policy, children) => {
+            Node::Parallel(policy, flatten_children(children, nodes))
+        }
+        Behavior::Decorator(decorator, child) => Node::Decorator(decorator, flatten(*child, nodes)),
+        Behavior::Condition(condition) => Node::Condition(condition),
+        Behavior::Action(action) => Node::Action(action),
+    };
+    nodes.push(node);
+    nodes.len() - 1
+}
+
+fn flatten_children<A, C>(
+    children: Vec<Behavior<A, C>>,
+    nodes: &mut Vec<Node<A, C>>,
+) -> Vec<NodeId> {
+    children
+        .into_iter()
+        .map(|child| flatten(child, nodes))
+        .collect()
+}
+
+// The trees are selected by the priority of HordeInterface::on_first
+pub struct BehaviorTrees<A, C> {
+    trees: Vec<BehaviorTree<A, C>>,
+}
+
+impl<A, C> Default for BehaviorTrees<A, C> {
+    fn default() -> Self {
+        BehaviorTrees { trees: Vec::new() }
+    }
+}
+
+impl<A, C> BehaviorTrees<A, C> {
+    pub fn register(&mut self, tree: BehaviorTree<A, C>) -> BehaviorTreeId {
+        self.trees.push(tree);
+        (self.trees.len() - 1) as BehaviorTreeId
+    }
+
+    // Used for HordeInterface::on_first
+    pub fn first(&self, tree_id: BehaviorTreeId) -> BehaviorJob {
+        let tree = &self.trees[tree_id as usize];
+        BehaviorJob {
+            tree: tree_id,
+            stack: vec![Frame {
+                node: tree.root,
+                child: 0,
+                count: 0,
+            }],
+            status: None,
+        }
+    }
+
+    // Used for HordeInterface::on_next. Evaluates the tree until an action node is running,
+    // and returns None when the tree is completed.
+    // If the tree is completed without any actions, JobManager retries with the next priority.
+    pub fn next<T>(
+        &self,
+        provider: &T,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        mut job: BehaviorJob,
+        controller: &mut ActionController,
+    ) -> Option<BehaviorJob>
+    where
+        T: BehaviorInterface<Action = A, Condition = C>,
+    {
+        let tree = &self.trees[job.tree as usize];
+        while let Some(frame) = job.stack.last_mut() {
+            let status = job.status.take();
+            let (status, child) = match &tree.nodes[frame.node] {
+                Node::Condition(condition) => {
+                    if provider.on_condition(entity_id, layer, condition) {
+                        (Some(BehaviorStatus::Success), None)
+                    } else {
+                        (Some(BehaviorStatus::Failure), None)
+                    }
+                }
+                Node::Action(action) => {
+                    match provider.on_action(entity_id, layer, action, controller) {
+                        BehaviorStatus::Running => {
+                            job.stack.pop();
+                            job.status = Some(BehaviorStatus::Success);
+                            return Some(job);
+                        }
+                        status => (Some(status), None),
+                    }
+                }
+                Node::Sequence(children) => match status {
+                    Some(BehaviorStatus::Failure) => (Some(BehaviorStatus::Failure), None),
+                    _ => match children.get(frame.child) {
+                        Some(child) => (None, Some(*child)),
+                        None => (Some(BehaviorStatus::Success), None),
+                    },
+                },
+                Node::Selector(children) => match status {
+                    Some(BehaviorStatus::Success) => (Some(BehaviorStatus::Success), None),
+                    _ => match children.get(frame.child) {
+                        Some(child) => (None, Some(*child)),
+                        None => (Some(BehaviorStatus::Failure), None),
+                    },
+                },
+                Node::Parallel(policy, children) => {
+                    if let Some(BehaviorStatus::Success) = status {
+                        frame.count += 1;
+                    }
+                    match children.get(frame.child) {
+                        Some(child) => (None, Some(*child)),
+                        None => {
+                            let succeeded = match policy {
+                                ParallelPolicy::RequireAll => {
+                                    frame.count as usize == children.len()
+                                }
+                                ParallelPolicy::RequireOne => frame.count > 0,
+                            };
+                            (Some(to_status(succeeded)), None)
+                        }
+                    }
+                }
+                Node::Decorator(decorator, child) => match status {
+                    None if *decorator == Decorator::Repeat(0) => {
+                        (Some(BehaviorStatus::Success), None)
+                    }
+                    None => (None, Some(*child)),
+                    Some(status) => match decorator {
+                        Decorator::Inverter => {
+                            (Some(to_status(status == BehaviorStatus::Failure)), None)
+                        }
+                        Decorator::AlwaysSucceed => (Some(BehaviorStatus::Success), None),
+                        Decorator::AlwaysFail => (Some(BehaviorStatus::Failure), None),
+                        Decorator::Repeat(count) => {
+                            frame.count += 1;
+                            if status == BehaviorStatus::Failure {
+                                (Some(BehaviorStatus::Failure), None)
+                            } else if frame.count < *count {
+                                (None, Some(*child))
+                            } else {
+                                (Some(BehaviorStatus::Success), None)
+                            }
+                        }
+                    },
+                },
+            };
+
+            match child {
+                Some(child) => {
+                    frame.child += 1;
+                    job.stack.push(Frame {
+                        node: child,
+                        child: 0,
+                        count: 0,
+                    });
+                }
+                None => {
+                    job.stack.pop();
+                    job.status = status;
+                }
+            }
+        }
+        None
+    }
+}
+
+fn to_status(succeeded: bool) -> BehaviorStatus {
+    if succeeded {
+        BehaviorStatus::Success
+    } else {
+        BehaviorStatus::Failure
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::{ActionController, ActionManager};
+    use crate::v2::action::{ActionLayer, CancelReason, DEFAULT_ACTION_LAYER};
+    use crate::v2::job::behavior::{
+        Behavior, BehaviorInterface, BehaviorJob, BehaviorStatus, BehaviorTree, BehaviorTrees,
+        Decorator, ParallelPolicy,
+    };
+    use crate::v2::job::manager::{JobController, JobManager, JobManagerData};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState;
+
+    define_actions!(TestAction, (Move, MoveState));
+
+    // The action fails if it has no duration
+    type TestBehaviorAction = (&'static str, Option<TimeMilliseconds>);
+
+    enum TestCondition {
+        Hungry,
+        Const(bool),
+    }
+
+    #[derive(Default)]
+    struct TestProvider {
+        trees: BehaviorTrees<TestBehaviorAction, TestCondition>,
+        hungry: bool,
+        logs: RefCell<Vec<String>>,
+    }
+
+    impl BehaviorInterface for TestProvider {
+        type Action = TestBehaviorAction;
+        type Condition = TestCondition;
+
+        fn on_condition(
+            &self,
+            _entity_id: EntityId,
+            _layer: ActionLayer,
+            condition: &TestCondition,
+        ) -> bool {
+            match condition {
+                TestCondition::Hungry => self.hungry,
+                TestCondition::Const(value) => *value,
+            }
+        }
+
+        fn on_action(
+            &self,
+            entity_id: EntityId,
+            _layer: ActionLayer,
+            (name, duration): &TestBehaviorAction,
+            controller: &mut ActionController,
+        ) -> BehaviorStatus {
+            self.logs.borrow_mut().push(name.to_string());
+            match duration {
+                None => BehaviorStatus::Failure,
+                Some(duration) => {
+                    controller.enqueue(entity_id, Arc::new(MoveState), *duration);
+                    BehaviorStatus::Running
+                }
+            }
+        }
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = BehaviorJob;
+
+        fn on_change_tick(
+            &mut self,
+            _map: &TypedAnyActionMap,
+            _controller: JobController<BehaviorJob>,
+        ) {
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(
+            &mut self,
+            _entity_id: EntityId,
+            jobs: Vec<BehaviorJob>,
+            _reason: CancelReason,
+        ) {
+            self.logs
+                .borrow_mut()
+                .push(format!("cancel {}", jobs.len()));
+        }
+
+        fn on_first(&self, _entity_id: EntityId, priority: u32) -> BehaviorJob {
+            self.trees.first(priority.min(1))
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: BehaviorJob,
+            controller: &mut ActionController,
+        ) -> Option<BehaviorJob> {
+            self.on_next_with_layer(entity_id, DEFAULT_ACTION_LAYER, job, controller)
+        }
+
+        fn on_next_with_layer(
+            &self,
+            entity_id: EntityId,
+            layer: ActionLayer,
+            job: BehaviorJob,
+            controller: &mut ActionController,
+        ) -> Option<BehaviorJob> {
+            self.trees.next(self, entity_id, layer, job, controller)
+        }
+    }
+
+    fn create_provider() -> TestProvider {
+        let mut provider = TestProvider::default();
+        provider
+            .trees
+            .register(BehaviorTree::new(Behavior::Sequence(vec![
+                Behavior::Condition(TestCondition::Hungry),
+                Behavior::Action(("eat", Some(500))),
+                Behavior::Action(("rest", Some(1000))),
+            ])));
+        provider
+            .trees
+            .register(BehaviorTree::new(Behavior::Selector(vec![
+                Behavior::Action(("fail", None)),
+                Behavior::Action(("wander", Some(2000))),
+            ])));
+        provider
+    }
+
+    #[test]
+    fn test_nodes() {
+        let mut provider = TestProvider::default();
+        let tree_id = provider
+            .trees
+            .register(BehaviorTree::new(Behavior::Sequence(vec![
+                Behavior::Selector(vec![
+                    Behavior::Condition(TestCondition::Const(false)),
+                    Behavior::Action(("a", Some(100))),
+                ]),
+                Behavior::Parallel(
+                    ParallelPolicy::RequireOne,
+                    vec![
+                        Behavior::Action(("b", None)),
+                        Behavior::Action(("c", Some(100))),
+                    ],
+                ),
+                Behavior::Decorator(
+                    Decorator::Inverter,
+                    Box::new(Behavior::Condition(TestCondition::Const(false))),
+                ),
+                Behavior::Decorator(
+                    Decorator::Repeat(2),
+                    Box::new(Behavior::Action(("d", Some(100)))),
+                ),
+                Behavior::Parallel(
+                    ParallelPolicy::RequireAll,
+                    vec![
+                        Behavior::Action(("e", Some(100))),
+                        Behavior::Action(("f", None)),
+                    ],
+                ),
+                Behavior::Action(("g", Some(100))),
+            ])));
+
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        let mut job = Some(provider.trees.first(tree_id));
+        let mut steps = 0;
+        while let Some(current) = job {
+            job = provider
+                .trees
+                .next(&provider, 1, 0, current, &mut manager.controller());
+            steps += 1;
+        }
+        assert_eq!(
+            provider.logs.into_inner(),
+            vec!["a", "b", "c", "d", "d", "e", "f"]
+        );
+        assert_eq!(steps, 6);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut provider = TestProvider::default();
+        let tree_id = provider
+            .trees
+            .register(BehaviorTree::new(Behavior::Sequence(vec![
+                Behavior::Decorator(
+                    Decorator::Repeat(0),
+                    Box::new(Behavior::Action(("a", Some(100)))),
+                ),
+                Behavior::Decorator(
+                    Decorator::Repeat(3),
+                    Box::new(Behavior::Action(("b", Some(100)))),
+                ),
+                Behavior::Decorator(
+                    Decorator::Repeat(3),
+                    Box::new(Behavior::Action(("c", None))),
+                ),
+                Behavior::Action(("d", Some(100))),
+            ])));
+
+        let mut manager = ActionManager::default();
+        manager.attach(1);
+        let mut job = Some(provider.trees.first(tree_id));
+        while let Some(current) = job {
+            job = provider
+                .trees
+                .next(&provider, 1, 0, current, &mut manager.controller());
+        }
+        assert_eq!(provider.logs.into_inner(), vec!["b", "b", "b", "c"]);
+    }
+
+    #[test]
+    fn test_job_manager() {
+        let mut provider = create_provider();
+        provider.hungry = true;
+        let mut manager: JobManager<TestProvider> = JobManager::default();
+        manager.attach(1);
+        manager.run(&mut provider, 0);
+        assert_eq!(provider.logs.borrow().clone(), vec!["eat", "rest"]);
+
+        let data = manager.to_data::<TestAction>();
+        let str = serde_json::to_string(&data).unwrap();
+        let data: JobManagerData<TestAction, BehaviorJob> = serde_json::from_str(&str).unwrap();
+        let new_manager: JobManager<TestProvider> = JobManager::from_data(data).unwrap();
+        let new_data = new_manager.to_data::<TestAction>();
+        assert_eq!(serde_json::to_string(&new_data).unwrap(), str);
+
+        // The tree which doesn't produce any actions is escalated to the next priority
+        provider.hungry = false;
+        manager.run(&mut provider, 1600); // tick: 24
+        assert_eq!(
+            provider.logs.borrow().clone(),
+            vec!["eat", "rest", "fail", "wander"]
+        );
+
+        manager.cancel(1, true);
+        manager.run(&mut provider, 0);
+        assert_eq!(provider.logs.borrow()[4], "cancel 2");
+    }
+}
@@ -0,0 +1,206 @@
+use crate::v2::job::manager::{EntityData, JobManagerData, JobManagerError};
+use crate::v2::Tick;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tearchan_ecs::component::EntityId;
+
+// The changes of JobManagerData between two ticks. The entities changed by the manager are
+// contained, and the others such as the clock, the joints and the timers are always contained since
+// they are small enough.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobManagerDelta<T, U> {
+    base_tick: Tick,
+    data: JobManagerData<T, U>, // The data without any entities
+    #[serde(default = "BTreeMap::new")] // Avoids the Default bound of T and U
+    entities: BTreeMap<EntityId, EntityData<T, U>>, // The added or changed entities
+    #[serde(default)]
+    removed_entities: Vec<EntityId>,
+}
+
+impl<T, U> JobManagerDelta<T, U> {
+    // Takes the changed entities out of the data, and the changed entities missing in the data are
+    // removed
+    pub(crate) fn new(
+        base_tick: Tick,
+        next: JobManagerData<T, U>,
+        changed_entities: &BTreeSet<EntityId>,
+    ) -> Self {
+        let (data, mut entities) = next.split_entities();
+        let removed_entities = changed_entities
+            .iter()
+            .filter(|entity_id| !entities.contains_key(entity_id))
+            .copied()
+            .collect();
+        entities.retain(|entity_id, _entity| changed_entities.contains(entity_id));
+        JobManagerDelta {
+            base_tick,
+            data,
+            entities,
+            removed_entities,
+        }
+    }
+
+    // Reconstructs the data at the tick of the delta, which must be diffed from the base
+    pub fn apply(&self, base: JobManagerData<T, U>) -> Result<JobManagerData<T, U>, JobManagerError>
+    where
+        T: Clone,
+        U: Clone,
+    {
+        if base.current_tick() != self.base_tick {
+            return Err(JobManagerError::InvalidDeltaTick {
+                expected: self.base_tick,
+                actual: base.current_tick(),
+            });
+        }
+        let (_, mut entities) = base.split_entities();
+        for entity_id in self.removed_entities.iter() {
+            entities.remove(entity_id);
+        }
+        for (entity_id, entity) in self.entities.iter() {
+            entities.insert(*entity_id, entity.clone());
+        }
+        Ok(self.data.clone().merge_entities(entities))
+    }
+
+    #[inline]
+    pub fn base_tick(&self) -> Tick {
+        self.base_tick
+    }
+
+    #[inline]
+    pub fn tick(&self) -> Tick {
+        self.data.current_tick()
+    }
+
+    #[inline]
+    pub fn changed_entities(&self) -> impl Iterator<Item = &EntityId> {
+        self.entities.keys()
+    }
+
+    #[inline]
+    pub fn removed_entities(&self) -> &[EntityId] {
+        &self.removed_entities
+    }
+}
+
+// Applies the chain of the deltas in order to the base snapshot
+pub fn apply_deltas<'a, T, U, I>(
+    base: JobManagerData<T, U>,
+    deltas: I,
+) -> Result<JobManagerData<T, U>, JobManagerError>
+where
+    T: Clone + 'a,
+    U: Clone + 'a,
+    I: IntoIterator<Item = &'a JobManagerDelta<T, U>>,
+{
+    deltas
+        .into_iter()
+        .try_fold(base, |data, delta| delta.apply(data))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::CancelReason;
+    use crate::v2::job::delta::{apply_deltas, JobManagerDelta};
+    use crate::v2::job::manager::{JobController, JobManager, JobManagerData};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState(u32);
+
+    define_actions!(TestAction, (Move, MoveState));
+
+    struct TestProvider;
+
+    impl HordeInterface for TestProvider {
+        type Job = u32;
+
+        fn on_change_tick(&mut self, _map: &TypedAnyActionMap, _controller: JobController<u32>) {}
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, _entity_id: EntityId, _jobs: Vec<u32>, _reason: CancelReason) {}
+
+        fn on_first(&self, _entity_id: EntityId, _priority: u32) -> u32 {
+            0
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: u32,
+            controller: &mut ActionController,
+        ) -> Option<u32> {
+            // The entity 1 moves slowly, so it's unchanged in most of the deltas
+            let duration = if entity_id == 1 { 5000 } else { 300 };
+            controller.enqueue(entity_id, Arc::new(MoveState(job)), duration);
+            None
+        }
+    }
+
+    fn to_data(manager: &JobManager<TestProvider>) -> JobManagerData<TestAction, u32> {
+        manager.to_data::<TestAction>()
+    }
+
+    #[test]
+    fn test_apply_deltas() {
+        let mut provider = TestProvider;
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.attach(2);
+        manager.run(&mut provider, 0);
+
+        assert!(manager.pull_delta::<TestAction>().is_none());
+        manager.enable_delta();
+        let base = to_data(&manager);
+        let mut deltas = Vec::new();
+        for step in 0..6 {
+            match step {
+                1 => manager.attach(3),
+                3 => manager.detach(2),
+                4 => manager.freeze(3),
+                _ => {}
+            }
+            manager.run(&mut provider, 400);
+            deltas.push(manager.pull_delta::<TestAction>().unwrap());
+        }
+        let last = to_data(&manager);
+
+        assert_eq!(
+            deltas[0].changed_entities().copied().collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(
+            deltas[1].changed_entities().copied().collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(deltas[3].removed_entities(), &[2]);
+        assert_eq!(deltas[5].changed_entities().count(), 0);
+
+        let deltas: Vec<JobManagerDelta<TestAction, u32>> =
+            serde_json::from_str(&serde_json::to_string(&deltas).unwrap()).unwrap();
+        let data = apply_deltas(base.clone(), deltas.iter()).unwrap();
+        assert_eq!(data.current_tick(), manager.current_tick());
+        assert_eq!(
+            serde_json::to_value(&data).unwrap(),
+            serde_json::to_value(&last).unwrap()
+        );
+
+        let loaded: JobManager<TestProvider> = JobManager::from_data(data).unwrap();
+        assert_eq!(loaded.current_tick(), manager.current_tick());
+
+        assert!(deltas[1].apply(base).is_err());
+    }
+}
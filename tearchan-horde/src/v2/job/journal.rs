@@ -0,0 +1,395 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{ActionController, TimeScale};
+use crate::v2::action::timer::TimerId;
+use crate::v2::action::{ActionLayer, CancelReason, DEFAULT_ACTION_LAYER};
+use crate::v2::job::manager::{JobManager, JobManagerError};
+use crate::v2::job::HordeInterface;
+use crate::v2::{HordeActions, Tick};
+use serde::{Deserialize, Serialize};
+use tearchan_ecs::component::EntityId;
+
+pub type EnqueueConverter<T> =
+    fn(EntityId, ActionLayer, T, TimeMilliseconds, &mut ActionController);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum JobCommand<T> {
+    Attach {
+        entity_id: EntityId,
+    },
+    Detach {
+        entity_id: EntityId,
+    },
+    AttachLayer {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    DetachLayer {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    Interrupt {
+        entity_id: EntityId,
+        #[serde(default = "default_layer")]
+        layer: ActionLayer,
+        action: T,
+        duration: TimeMilliseconds,
+        #[serde(default)]
+        reason: CancelReason,
+    },
+    EnqueueJoint {
+        participants: Vec<(EntityId, ActionLayer)>,
+        action: T,
+        duration: TimeMilliseconds,
+        #[serde(default)]
+        each: bool,
+    },
+    Cancel {
+        entity_id: EntityId,
+        #[serde(default = "default_layer")]
+        layer: ActionLayer,
+        immediate: bool,
+        #[serde(default)]
+        reason: CancelReason,
+    },
+    Reschedule {
+        entity_id: EntityId,
+        #[serde(default = "default_layer")]
+        layer: ActionLayer,
+        end_tick: Tick,
+    },
+    // The replay schedules the timer with the same id as long as the journal starts from the same
+    // state, so CancelTimer can refer to it
+    ScheduleTimer {
+        action: T,
+        delay: TimeMilliseconds,
+        #[serde(default)]
+        interval: Option<TimeMilliseconds>,
+    },
+    CancelTimer {
+        timer_id: TimerId,
+    },
+    SetTimeScale {
+        time_scale: TimeScale,
+    },
+    Pause,
+    Resume,
+    Freeze {
+        entity_id: EntityId,
+    },
+    Unfreeze {
+        entity_id: EntityId,
+    },
+    Run {
+        delta: TimeMilliseconds,
+    },
+}
+
+fn default_layer() -> ActionLayer {
+    DEFAULT_ACTION_LAYER
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct JobCommandRecord<T> {
+    pub tick: Tick, // The tick that the command takes effect
+    pub command: JobCommand<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobJournal<T> {
+    records: Vec<JobCommandRecord<T>>,
+}
+
+impl<T> Default for JobJournal<T> {
+    fn default() -> Self {
+        JobJournal {
+            records: Vec::new(),
+        }
+    }
+}
+
+impl<T> JobJournal<T> {
+    pub fn record<U>(
+        &mut self,
+        manager: &mut JobManager<U>,
+        provider: &mut U,
+        command: JobCommand<T>,
+        converter: EnqueueConverter<T>,
+    ) where
+        T: HordeActions + Clone,
+        U: HordeInterface,
+    {
+        self.records.push(JobCommandRecord {
+            tick: manager.current_tick(),
+            command: command.clone(),
+        });
+        manager.execute(provider, command, converter);
+    }
+
+    pub fn replay<U>(
+        &self,
+        manager: &mut JobManager<U>,
+        provider: &mut U,
+        converter: EnqueueConverter<T>,
+    ) -> Result<(), JobManagerError>
+    where
+        T: HordeActions + Clone,
+        U: HordeInterface,
+    {
+        for record in self.records.iter() {
+            if record.tick != manager.current_tick() {
+                return Err(JobManagerError::InvalidJournalTick {
+                    expected: record.tick,
+                    actual: manager.current_tick(),
+                });
+            }
+            manager.execute(provider, record.command.clone(), converter);
+        }
+        Ok(())
+    }
+
+    // Returns the records that take effect at or after the tick, used for re-simulation after rewinding
+    pub fn since(&self, tick: Tick) -> JobJournal<T>
+    where
+        T: Clone,
+    {
+        let index = self.records.partition_point(|record| record.tick < tick);
+        JobJournal {
+            records: self.records[index..].to_vec(),
+        }
+    }
+
+    #[inline]
+    pub fn records(&self) -> &[JobCommandRecord<T>] {
+        &self.records
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{
+        TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId, TypedAnyTimerMap,
+    };
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::timer::TimerId;
+    use crate::v2::action::CancelReason;
+    use crate::v2::job::journal::{JobCommand, JobJournal};
+    use crate::v2::job::manager::{JobController, JobManager, JobManagerData};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState(i32);
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JumpState;
+
+    define_actions!(TestAction, (Move, MoveState), (Jump, JumpState));
+
+    #[derive(Default)]
+    struct TestProvider {
+        logs: Vec<String>,
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = i32;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<i32>) {
+            let validator = controller.validator();
+            let mut logs = Vec::new();
+            for action in map.get::<MoveState>(&validator).unwrap_or_default() {
+                logs.push(format!("{:?}", action));
+            }
+            for action in map.get::<JumpState>(&validator).unwrap_or_default() {
+                logs.push(format!("{:?}", action));
+            }
+            logs.sort();
+            for log in logs {
+                self.logs
+                    .push(format!("[{}] {}", controller.current_tick(), log));
+            }
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_timer(&mut self, timers: &TypedAnyTimerMap, controller: JobController<i32>) {
+            for timer in timers.get::<JumpState>().unwrap_or_default() {
+                self.logs.push(format!(
+                    "[{}] timer {:?}",
+                    controller.current_tick(),
+                    timer.id()
+                ));
+            }
+        }
+
+        fn on_cancel_job(&mut self, entity_id: EntityId, jobs: Vec<i32>, reason: CancelReason) {
+            self.logs
+                .push(format!("cancel {} {:?} {:?}", entity_id, jobs, reason));
+        }
+
+        fn on_first(&self, entity_id: EntityId, _priority: u32) -> i32 {
+            entity_id as i32
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: i32,
+            controller: &mut ActionController,
+        ) -> Option<i32> {
+            let duration = entity_id * 300 + controller.current_tick() * 7 % 500;
+            controller.enqueue(entity_id, Arc::new(MoveState(job)), duration);
+            None
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut provider = TestProvider::default();
+        let mut manager: JobManager<TestProvider> = JobManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        manager.run(&mut provider, 700);
+
+        let data = manager.to_data::<TestAction>();
+        let snapshot = serde_json::to_string(&data).unwrap();
+        provider.logs.clear();
+
+        let commands = vec![
+            JobCommand::Run { delta: 300 },
+            JobCommand::Attach { entity_id: 3 },
+            JobCommand::Run { delta: 1000 },
+            JobCommand::Interrupt {
+                entity_id: 2,
+                layer: 0,
+                action: TestAction::Jump(Arc::new(JumpState)),
+                duration: 200,
+                reason: CancelReason::user([5]),
+            },
+            JobCommand::ScheduleTimer {
+                action: TestAction::Jump(Arc::new(JumpState)),
+                delay: 100,
+                interval: Some(200),
+            },
+            JobCommand::Run { delta: 500 },
+            JobCommand::Cancel {
+                entity_id: 1,
+                layer: 0,
+                immediate: false,
+                reason: CancelReason::user([3]),
+            },
+            JobCommand::Reschedule {
+                entity_id: 3,
+                layer: 0,
+                end_tick: 2600,
+            },
+            JobCommand::CancelTimer {
+                timer_id: TimerId::default(),
+            },
+            JobCommand::Run { delta: 2000 },
+            JobCommand::EnqueueJoint {
+                participants: vec![(2, 0), (3, 0)],
+                action: TestAction::Jump(Arc::new(JumpState)),
+                duration: 300,
+                each: false,
+            },
+            JobCommand::Run { delta: 1500 },
+            JobCommand::Detach { entity_id: 3 },
+            JobCommand::Run { delta: 1500 },
+        ];
+        let mut journal = JobJournal::default();
+        for command in commands {
+            journal.record(
+                &mut manager,
+                &mut provider,
+                command,
+                convert_and_enqueue_action,
+            );
+        }
+        assert_eq!(journal.len(), 14);
+
+        let journal: JobJournal<TestAction> =
+            serde_json::from_str(&serde_json::to_string(&journal).unwrap()).unwrap();
+        let data: JobManagerData<TestAction, i32> = serde_json::from_str(&snapshot).unwrap();
+        let mut new_provider = TestProvider::default();
+        let mut new_manager: JobManager<TestProvider> = JobManager::from_data(data).unwrap();
+        journal
+            .replay(
+                &mut new_manager,
+                &mut new_provider,
+                convert_and_enqueue_action,
+            )
+            .unwrap();
+
+        assert!(!provider.logs.is_empty());
+        assert!(provider
+            .logs
+            .contains(&"cancel 1 [1] User([3])".to_string()));
+        assert!(provider
+            .logs
+            .iter()
+            .any(|log| log.starts_with("cancel 2") && log.ends_with("User([5])")));
+        assert_eq!(
+            provider
+                .logs
+                .iter()
+                .filter(|log| log.contains("timer"))
+                .count(),
+            3
+        );
+        assert_eq!(provider.logs, new_provider.logs);
+        assert_eq!(manager.current_tick(), new_manager.current_tick());
+    }
+
+    #[test]
+    fn test_replay_with_invalid_tick() {
+        let mut provider = TestProvider::default();
+        let mut manager: JobManager<TestProvider> = JobManager::default();
+        manager.attach(1);
+
+        let mut journal = JobJournal::default();
+        journal.record(
+            &mut manager,
+            &mut provider,
+            JobCommand::Run { delta: 1000 },
+            convert_and_enqueue_action,
+        );
+        journal.record(
+            &mut manager,
+            &mut provider,
+            JobCommand::Run { delta: 1000 },
+            convert_and_enqueue_action,
+        );
+
+        let mut new_manager: JobManager<TestProvider> = JobManager::default();
+        new_manager.attach(1);
+        let result = JobJournal {
+            records: journal.records()[1..].to_vec(),
+        }
+        .replay(&mut new_manager, &mut provider, convert_and_enqueue_action);
+        assert!(result.is_err());
+    }
+}
@@ -1,112 +1,1290 @@
 use crate::action::manager::TimeMilliseconds;
-use crate::v2::action::collection::{
-    AnyActionVec, TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId,
-};
+#[cfg(feature = "metrics")]
+use crate::v2::action::manager::ActionTraceKind;
 use crate::v2::action::manager::{
-    ActionManager, ActionManagerConverter, ActionManagerData, ActionManagerError,
-    ActionRemapperToken, ActionSessionValidator,
+    ActionManager, ActionManagerData, ActionManagerError, ActionManagerSnapshot,
+    ActionManagerViolation, ActionSessionValidator, ActionTrace, CatchUpPolicy, EnqueueOptions,
+    EntityActionData, RemapContext, TimeScale,
 };
-use crate::v2::action::Action;
-use crate::v2::job::HordeInterface;
-use crate::v2::Tick;
+use crate::v2::action::replication::ReplicationDelta;
+use crate::v2::action::timer::{TimerId, TimerOptions};
+use crate::v2::action::tween::{Tween, Tweenable};
+use crate::v2::action::{ActionLayer, ArcAction, CancelReason, DEFAULT_ACTION_LAYER};
+use crate::v2::checksum::{hash_slice, StateChecksum, StateChecksumHistory, StateHasher};
+use crate::v2::job::delta::JobManagerDelta;
+use crate::v2::job::journal::{EnqueueConverter, JobCommand};
+use crate::v2::job::metrics::{JobMetrics, ProviderCallback};
+use crate::v2::job::parallel::{evaluate_jobs, ActionBuffer};
+#[cfg(feature = "trace")]
+use crate::v2::job::trace::{JobTracer, TraceEvent, TraceEventKind};
+use crate::v2::job::{HordeInterface, ParallelHordeInterface};
+use crate::v2::rollback::RollbackBuffer;
+use crate::v2::{HordeActions, Tick};
 use serde::{Deserialize, Serialize};
-use std::any::TypeId;
-use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::{mpsc, Arc};
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+use std::time::Instant;
 use tearchan_ecs::component::EntityId;
-use tearchan_ecs::entity::manager::ENTITY_REMAPPER;
+use tearchan_util::thread::ThreadPool;
+
+struct ChecksumRecorder<T> {
+    history: StateChecksumHistory,
+    hash_jobs: fn(&[T], &mut StateHasher),
+}
+
+#[cfg(feature = "trace")]
+type FormatJob<T> = fn(&T) -> String;
+
+// Holds the tracer while tracing is enabled. Without the trace feature, it's a stub which never
+// records, and the tracing code is compiled out.
+#[cfg(feature = "trace")]
+struct TraceRecorder<T>(Option<(JobTracer, FormatJob<T>)>);
+
+#[cfg(feature = "trace")]
+impl<T> TraceRecorder<T> {
+    fn enable(&mut self, tick_duration: TimeMilliseconds)
+    where
+        T: Debug,
+    {
+        if self.0.is_none() {
+            self.0 = Some((JobTracer::new(tick_duration), |job| format!("{:?}", job)));
+        }
+    }
+
+    fn disable(&mut self) -> Option<JobTracer> {
+        self.0.take().map(|(tracer, _)| tracer)
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    fn tracer(&self) -> Option<&JobTracer> {
+        self.0.as_ref().map(|(tracer, _)| tracer)
+    }
+
+    fn tracer_mut(&mut self) -> Option<&mut JobTracer> {
+        self.0.as_mut().map(|(tracer, _)| tracer)
+    }
+
+    #[inline]
+    fn format_job(&self) -> Option<fn(&T) -> String> {
+        self.0.as_ref().map(|(_, format_job)| *format_job)
+    }
+
+    #[inline]
+    fn record(&mut self, event: TraceEvent) {
+        if let Some((tracer, _)) = &mut self.0 {
+            tracer.record(event);
+        }
+    }
+
+    fn record_actions(&mut self, traces: &[ActionTrace]) {
+        if let Some((tracer, _)) = &mut self.0 {
+            for trace in traces {
+                tracer.record_action(*trace);
+            }
+        }
+    }
+
+    fn record_first(
+        &mut self,
+        tick: Tick,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        priority: u32,
+        job: &T,
+    ) {
+        if let Some((tracer, format_job)) = &mut self.0 {
+            let job = format_job(job);
+            tracer.record(TraceEvent {
+                tick,
+                entity_id,
+                layer,
+                kind: TraceEventKind::First { priority, job },
+            });
+        }
+    }
+
+    // The job is formatted before it's consumed by the provider
+    fn record_next(
+        &mut self,
+        tick: Tick,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        job: String,
+        result: Option<&T>,
+    ) {
+        if let Some((tracer, format_job)) = &mut self.0 {
+            let result = result.map(*format_job);
+            tracer.record(TraceEvent {
+                tick,
+                entity_id,
+                layer,
+                kind: TraceEventKind::Next { job, result },
+            });
+        }
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+struct TraceRecorder<T>(std::marker::PhantomData<fn(&T)>);
+
+#[cfg(not(feature = "trace"))]
+impl<T> TraceRecorder<T> {
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn format_job(&self) -> Option<fn(&T) -> String> {
+        None
+    }
+
+    #[inline]
+    fn record_actions(&mut self, _traces: &[ActionTrace]) {}
+
+    #[inline]
+    fn record_first(
+        &mut self,
+        _tick: Tick,
+        _entity_id: EntityId,
+        _layer: ActionLayer,
+        _priority: u32,
+        _job: &T,
+    ) {
+    }
+
+    #[inline]
+    fn record_next(
+        &mut self,
+        _tick: Tick,
+        _entity_id: EntityId,
+        _layer: ActionLayer,
+        _job: String,
+        _result: Option<&T>,
+    ) {
+    }
+}
+
+impl<T> Default for TraceRecorder<T> {
+    fn default() -> Self {
+        TraceRecorder(Default::default())
+    }
+}
+
+#[cfg(feature = "metrics")]
+struct MetricsState {
+    metrics: JobMetrics,
+    running: BTreeMap<(EntityId, ActionLayer), &'static str>, // value = the type name
+}
+
+// Holds the metrics while they are enabled. Without the metrics feature, it's a stub which never
+// records, and neither the counters nor the clock are touched.
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+struct MetricsRecorder(Option<MetricsState>);
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder {
+    fn enable(&mut self) {
+        if self.0.is_none() {
+            self.0 = Some(MetricsState {
+                metrics: JobMetrics::default(),
+                running: BTreeMap::new(),
+            });
+        }
+    }
+
+    fn disable(&mut self) {
+        self.0 = None;
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    fn metrics(&self) -> Option<&JobMetrics> {
+        self.0.as_ref().map(|state| &state.metrics)
+    }
+
+    fn reset(&mut self) {
+        if let Some(state) = &mut self.0 {
+            state.metrics = JobMetrics::default();
+        }
+    }
+
+    fn record_actions(&mut self, traces: &[ActionTrace]) {
+        let state = match &mut self.0 {
+            None => return,
+            Some(state) => state,
+        };
+        for trace in traces {
+            let key = (trace.entity_id, trace.layer);
+            match trace.kind {
+                ActionTraceKind::Start { type_name } => {
+                    state.metrics.actions.entry(type_name).or_default().started += 1;
+                    state.running.insert(key, type_name);
+                }
+                // The actions started before enabling the metrics aren't counted
+                ActionTraceKind::End => {
+                    if let Some(type_name) = state.running.remove(&key) {
+                        state.metrics.actions.entry(type_name).or_default().ended += 1;
+                    }
+                }
+                ActionTraceKind::Cancel => {
+                    if let Some(type_name) = state.running.remove(&key) {
+                        state.metrics.actions.entry(type_name).or_default().canceled += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn record_run(&mut self, ticks: Tick, action_manager: &ActionManager) {
+        if let Some(state) = &mut self.0 {
+            state.metrics.runs += 1;
+            state.metrics.ticks_per_run.record(ticks);
+            // The running actions of the detached layers never end
+            state
+                .running
+                .retain(|(entity_id, layer), _| action_manager.is_attached(*entity_id, *layer));
+        }
+    }
+
+    #[inline]
+    fn record_escalations(&mut self, escalations: u64) {
+        if let Some(state) = &mut self.0 {
+            state.metrics.escalations += escalations;
+        }
+    }
+
+    // The clock is read only while the metrics are enabled
+    #[inline]
+    fn start_callback(&self) -> Option<Instant> {
+        self.0.as_ref().map(|_| Instant::now())
+    }
+
+    // The callbacks timed on the worker threads of run_parallel
+    fn record_callbacks(&mut self, callbacks: Vec<(ProviderCallback, Duration)>) {
+        if let Some(state) = &mut self.0 {
+            for (callback, elapsed) in callbacks {
+                state.metrics.record_callback(callback, elapsed);
+            }
+        }
+    }
+
+    #[inline]
+    fn end_callback(&mut self, callback: ProviderCallback, started: Option<Instant>) {
+        if let (Some(state), Some(started)) = (&mut self.0, started) {
+            state.metrics.record_callback(callback, started.elapsed());
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+#[derive(Default)]
+struct MetricsRecorder;
+
+#[cfg(not(feature = "metrics"))]
+impl MetricsRecorder {
+    #[inline]
+    fn enable(&mut self) {}
+
+    #[inline]
+    fn disable(&mut self) {}
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn metrics(&self) -> Option<&JobMetrics> {
+        None
+    }
+
+    #[inline]
+    fn reset(&mut self) {}
+
+    #[inline]
+    fn record_actions(&mut self, _traces: &[ActionTrace]) {}
+
+    #[inline]
+    fn record_run(&mut self, _ticks: Tick, _action_manager: &ActionManager) {}
+
+    #[inline]
+    fn record_escalations(&mut self, _escalations: u64) {}
+
+    #[inline]
+    fn start_callback(&self) -> Option<Instant> {
+        None
+    }
+
+    #[inline]
+    fn end_callback(&mut self, _callback: ProviderCallback, _started: Option<Instant>) {}
+}
+
+pub struct JobManager<T: HordeInterface> {
+    action_manager: ActionManager,
+    jobs: BTreeMap<(EntityId, ActionLayer), Vec<T::Job>>,
+    checksum_recorder: Option<ChecksumRecorder<T::Job>>,
+    rollback_buffer: Option<RollbackBuffer<JobManagerSnapshot<T::Job>>>,
+    trace_recorder: TraceRecorder<T::Job>,
+    metrics_recorder: MetricsRecorder,
+    delta_base_tick: Option<Tick>, // The tick of the last delta
+}
+
+impl<T> Default for JobManager<T>
+where
+    T: HordeInterface,
+{
+    fn default() -> Self {
+        JobManager {
+            action_manager: Default::default(),
+            jobs: Default::default(),
+            checksum_recorder: None,
+            rollback_buffer: None,
+            trace_recorder: Default::default(),
+            metrics_recorder: Default::default(),
+            delta_base_tick: None,
+        }
+    }
+}
+
+impl<T> JobManager<T>
+where
+    T: HordeInterface,
+{
+    pub fn with_tick_duration(tick_duration: TimeMilliseconds) -> Self {
+        JobManager {
+            action_manager: ActionManager::with_tick_duration(tick_duration),
+            jobs: Default::default(),
+            checksum_recorder: None,
+            rollback_buffer: None,
+            trace_recorder: Default::default(),
+            metrics_recorder: Default::default(),
+            delta_base_tick: None,
+        }
+    }
+
+    pub fn run(&mut self, provider: &mut T, delta: TimeMilliseconds) {
+        self.run_with(provider, delta, Self::evaluate_jobs);
+    }
+
+    fn run_with<F>(&mut self, provider: &mut T, delta: TimeMilliseconds, mut evaluate: F)
+    where
+        F: FnMut(&mut Self, &mut T, BTreeSet<(EntityId, ActionLayer)>),
+    {
+        let tick = self.action_manager.current_tick();
+        while !self.action_manager.get_vacated_entities().is_empty() {
+            self.run_actions(provider, &mut evaluate);
+        }
+        self.action_manager.update(delta);
+        self.run_actions(provider, &mut evaluate);
+        self.record_snapshot();
+
+        self.metrics_recorder.record_run(
+            self.action_manager.current_tick() - tick,
+            &self.action_manager,
+        );
+    }
+
+    #[inline]
+    pub fn attach(&mut self, entity_id: EntityId) {
+        self.controller().attach(entity_id);
+    }
+
+    #[inline]
+    pub fn detach(&mut self, entity_id: EntityId) {
+        self.controller().detach(entity_id);
+    }
+
+    #[inline]
+    pub fn attach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.controller().attach_layer(entity_id, layer);
+    }
+
+    #[inline]
+    pub fn detach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.controller().detach_layer(entity_id, layer);
+    }
+
+    #[inline]
+    pub fn interrupt<U>(&mut self, entity_id: EntityId, raw: Arc<U>, duration: TimeMilliseconds)
+    where
+        U: 'static,
+    {
+        self.action_manager.interrupt(entity_id, raw, duration);
+    }
+
+    #[inline]
+    pub fn cancel(&mut self, entity_id: EntityId, immediate: bool) {
+        self.action_manager.cancel(entity_id, immediate);
+    }
+
+    #[inline]
+    pub fn cancel_layer(&mut self, entity_id: EntityId, layer: ActionLayer, immediate: bool) {
+        self.action_manager
+            .cancel_layer(entity_id, layer, immediate);
+    }
+
+    #[inline]
+    pub fn cancel_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        self.cancel_layer_with_reason(entity_id, DEFAULT_ACTION_LAYER, immediate, reason);
+    }
+
+    #[inline]
+    pub fn cancel_layer_with_reason(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        immediate: bool,
+        reason: CancelReason,
+    ) {
+        self.action_manager
+            .cancel_layer_with_reason(entity_id, layer, immediate, reason);
+    }
+
+    #[inline]
+    pub fn reschedule(&mut self, entity_id: EntityId, end_tick: Tick) -> bool {
+        self.action_manager.reschedule(entity_id, end_tick)
+    }
+
+    #[inline]
+    pub fn reschedule_layer(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        end_tick: Tick,
+    ) -> bool {
+        self.action_manager
+            .reschedule_layer(entity_id, layer, end_tick)
+    }
+
+    #[inline]
+    pub fn schedule_timer<U>(&mut self, raw: Arc<U>, delay: TimeMilliseconds) -> TimerId
+    where
+        U: 'static,
+    {
+        self.action_manager.schedule_timer(raw, delay)
+    }
+
+    #[inline]
+    pub fn schedule_timer_with_options<U>(
+        &mut self,
+        raw: Arc<U>,
+        delay: TimeMilliseconds,
+        options: TimerOptions,
+    ) -> TimerId
+    where
+        U: 'static,
+    {
+        self.action_manager
+            .schedule_timer_with_options(raw, delay, options)
+    }
+
+    #[inline]
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.action_manager.cancel_timer(timer_id)
+    }
+
+    #[inline]
+    pub fn time_scale(&self) -> TimeScale {
+        self.action_manager.time_scale()
+    }
+
+    #[inline]
+    pub fn set_time_scale(&mut self, time_scale: TimeScale) {
+        self.action_manager.set_time_scale(time_scale);
+    }
+
+    #[inline]
+    pub fn pause(&mut self) {
+        self.action_manager.pause();
+    }
+
+    #[inline]
+    pub fn resume(&mut self) {
+        self.action_manager.resume();
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.action_manager.is_paused()
+    }
+
+    // Limits the ticks processed by a run to avoid the stall after a long frame
+    #[inline]
+    pub fn enable_catch_up_limit(&mut self, max_ticks: Tick, policy: CatchUpPolicy) {
+        self.action_manager.enable_catch_up_limit(max_ticks, policy);
+    }
+
+    #[inline]
+    pub fn disable_catch_up_limit(&mut self) {
+        self.action_manager.disable_catch_up_limit();
+    }
+
+    #[inline]
+    pub fn backlog(&self) -> TimeMilliseconds {
+        self.action_manager.backlog()
+    }
+
+    #[inline]
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.action_manager.interpolation_alpha()
+    }
+
+    #[inline]
+    pub fn evaluate_tween<U>(&self, action: &ArcAction<Tween<U>>) -> U
+    where
+        U: Tweenable,
+    {
+        self.action_manager.evaluate_tween(action)
+    }
+
+    #[inline]
+    pub fn upcoming_actions<U>(&self, entity_id: EntityId) -> impl Iterator<Item = &ArcAction<U>>
+    where
+        U: 'static,
+    {
+        self.action_manager.upcoming_actions(entity_id)
+    }
+
+    #[inline]
+    pub fn freeze(&mut self, entity_id: EntityId) {
+        self.action_manager.freeze(entity_id);
+    }
+
+    #[inline]
+    pub fn unfreeze(&mut self, entity_id: EntityId) {
+        self.action_manager.unfreeze(entity_id);
+    }
+
+    pub fn execute<U: HordeActions>(
+        &mut self,
+        provider: &mut T,
+        command: JobCommand<U>,
+        converter: EnqueueConverter<U>,
+    ) {
+        match command {
+            JobCommand::Attach { entity_id } => self.attach(entity_id),
+            JobCommand::Detach { entity_id } => self.detach(entity_id),
+            JobCommand::AttachLayer { entity_id, layer } => self.attach_layer(entity_id, layer),
+            JobCommand::DetachLayer { entity_id, layer } => self.detach_layer(entity_id, layer),
+            JobCommand::Interrupt {
+                entity_id,
+                layer,
+                action,
+                duration,
+                reason,
+            } => {
+                self.action_manager
+                    .cancel_layer_with_reason(entity_id, layer, true, reason);
+                converter(
+                    entity_id,
+                    layer,
+                    action,
+                    duration,
+                    &mut self.action_manager.controller(),
+                );
+            }
+            JobCommand::EnqueueJoint {
+                participants,
+                action,
+                duration,
+                each,
+            } => U::convert_and_enqueue_joint_action(
+                &participants,
+                action,
+                duration,
+                EnqueueOptions { each },
+                &mut self.action_manager.controller(),
+            ),
+            JobCommand::Cancel {
+                entity_id,
+                layer,
+                immediate,
+                reason,
+            } => self.cancel_layer_with_reason(entity_id, layer, immediate, reason),
+            JobCommand::Reschedule {
+                entity_id,
+                layer,
+                end_tick,
+            } => {
+                self.reschedule_layer(entity_id, layer, end_tick);
+            }
+            JobCommand::ScheduleTimer {
+                action,
+                delay,
+                interval,
+            } => {
+                U::convert_and_schedule_timer(
+                    action,
+                    delay,
+                    TimerOptions { interval },
+                    &mut self.action_manager.controller(),
+                );
+            }
+            JobCommand::CancelTimer { timer_id } => {
+                self.cancel_timer(timer_id);
+            }
+            JobCommand::SetTimeScale { time_scale } => self.set_time_scale(time_scale),
+            JobCommand::Pause => self.pause(),
+            JobCommand::Resume => self.resume(),
+            JobCommand::Freeze { entity_id } => self.freeze(entity_id),
+            JobCommand::Unfreeze { entity_id } => self.unfreeze(entity_id),
+            JobCommand::Run { delta } => self.run(provider, delta),
+        }
+    }
+
+    pub fn to_data<U: HordeActions>(&self) -> JobManagerData<U, T::Job> {
+        JobManagerData::new(
+            self.action_manager.to_data(
+                U::convert_actions_from_typed_action_any_map,
+                U::convert_actions_from_typed_any_action_map,
+                U::convert_actions_from_any_action_vec,
+            ),
+            self.jobs.clone(),
+        )
+    }
+
+    pub fn load_data<U: HordeActions>(
+        &mut self,
+        data: JobManagerData<U, T::Job>,
+        context: &mut RemapContext,
+    ) -> Result<(), JobManagerError> {
+        self.action_manager
+            .load_data(data.action_manager_data, U::convert_from_actions, context)
+            .map_err(JobManagerError::ActionManagerError)?;
+        for ((entity_id, layer), jobs) in flatten_jobs(data.jobs, data.layer_jobs) {
+            self.jobs
+                .insert((context.remap_entity(entity_id), layer), jobs);
+        }
+        Ok(())
+    }
+
+    pub fn from_data<U: HordeActions>(
+        data: JobManagerData<U, T::Job>,
+    ) -> Result<Self, JobManagerError> {
+        let tick_duration = data.action_manager_data.tick_duration();
+        JobManager::from_data_with_tick_duration(data, tick_duration)
+    }
+
+    pub fn from_data_with_tick_duration<U: HordeActions>(
+        data: JobManagerData<U, T::Job>,
+        tick_duration: TimeMilliseconds,
+    ) -> Result<Self, JobManagerError> {
+        let action_manager = ActionManager::from_data_with_tick_duration(
+            data.action_manager_data,
+            tick_duration,
+            U::convert_from_actions,
+        )
+        .map_err(JobManagerError::ActionManagerError)?;
+        let jobs = flatten_jobs(data.jobs, data.layer_jobs);
+        // The layers are attached by the loaded actions, including the End action pending at the
+        // frozen tick
+        let layers = action_manager.attached_layers().collect::<BTreeSet<_>>();
+        for (entity_id, layer) in jobs.keys() {
+            if !layers.contains(&(*entity_id, *layer)) {
+                return Err(JobManagerError::NotFoundEntity(*entity_id));
+            }
+        }
+        Ok(JobManager {
+            action_manager,
+            jobs,
+            checksum_recorder: None,
+            rollback_buffer: None,
+            trace_recorder: Default::default(),
+            metrics_recorder: Default::default(),
+            delta_base_tick: None,
+        })
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.action_manager.current_tick()
+    }
+
+    #[inline]
+    pub fn tick_duration(&self) -> TimeMilliseconds {
+        self.action_manager.tick_duration()
+    }
+
+    // Checks the invariants of the action manager and the job stacks
+    pub fn verify(&self) -> Result<(), Vec<JobManagerViolation>> {
+        let mut violations = match self.action_manager.verify() {
+            Ok(()) => Vec::new(),
+            Err(violations) => violations
+                .into_iter()
+                .map(JobManagerViolation::ActionManagerViolation)
+                .collect(),
+        };
+        let attached_layers = self
+            .action_manager
+            .attached_layers()
+            .collect::<BTreeSet<_>>();
+        for (entity_id, layer) in self.jobs.keys() {
+            if !attached_layers.contains(&(*entity_id, *layer)) {
+                violations.push(JobManagerViolation::NotAttached {
+                    entity_id: *entity_id,
+                    layer: *layer,
+                });
+            }
+        }
+        for (entity_id, layer) in attached_layers {
+            if !self.jobs.contains_key(&(entity_id, layer)) {
+                violations.push(JobManagerViolation::NoJobStack { entity_id, layer });
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    pub fn checksum(&self, provider: &T) -> StateChecksum
+    where
+        T::Job: Hash,
+    {
+        self.checksum_with(provider, hash_slice)
+    }
+
+    // Records the checksum of every processed tick up to the capacity
+    pub fn enable_checksum_history(&mut self, capacity: usize)
+    where
+        T::Job: Hash,
+    {
+        self.checksum_recorder = Some(ChecksumRecorder {
+            history: StateChecksumHistory::new(capacity),
+            hash_jobs: hash_slice,
+        });
+    }
+
+    pub fn disable_checksum_history(&mut self) {
+        self.checksum_recorder = None;
+    }
+
+    pub fn checksum_history(&self) -> Option<&StateChecksumHistory> {
+        self.checksum_recorder
+            .as_ref()
+            .map(|recorder| &recorder.history)
+    }
+
+    pub fn snapshot(&self) -> JobManagerSnapshot<T::Job> {
+        JobManagerSnapshot {
+            action_manager: self.action_manager.snapshot(),
+            jobs: self.jobs.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &JobManagerSnapshot<T::Job>) {
+        self.action_manager.restore(&snapshot.action_manager);
+        self.jobs = snapshot.jobs.clone();
+        if let Some(recorder) = &mut self.checksum_recorder {
+            recorder
+                .history
+                .truncate_after(self.action_manager.current_tick());
+        }
+    }
+
+    // Takes a snapshot at the end of every run, and also immediately for the current tick
+    pub fn enable_rollback(&mut self, capacity: usize) {
+        self.rollback_buffer = Some(RollbackBuffer::new(capacity));
+        self.record_snapshot();
+    }
+
+    pub fn disable_rollback(&mut self) {
+        self.rollback_buffer = None;
+    }
+
+    // Restores the latest snapshot at or before the tick and returns the tick of the snapshot.
+    // The inputs after the returned tick must be applied again to re-simulate.
+    pub fn rewind_to(&mut self, tick: Tick) -> Result<Tick, JobManagerError> {
+        let mut buffer = self
+            .rollback_buffer
+            .take()
+            .ok_or(JobManagerError::NotFoundSnapshot(tick))?;
+        let snapshot_tick = match buffer.find(tick) {
+            None => {
+                self.rollback_buffer = Some(buffer);
+                return Err(JobManagerError::NotFoundSnapshot(tick));
+            }
+            Some((snapshot_tick, snapshot)) => {
+                self.restore(snapshot);
+                snapshot_tick
+            }
+        };
+        buffer.truncate_after(snapshot_tick);
+        self.rollback_buffer = Some(buffer);
+        Ok(snapshot_tick)
+    }
+
+    // Records the jobs and actions of every entity until disabled
+    #[cfg(feature = "trace")]
+    pub fn enable_tracing(&mut self)
+    where
+        T::Job: Debug,
+    {
+        self.trace_recorder
+            .enable(self.action_manager.tick_duration());
+        if self.trace_recorder.is_enabled() {
+            self.action_manager.enable_tracing();
+        }
+    }
+
+    // Returns the tracer with the recorded events
+    #[cfg(feature = "trace")]
+    pub fn disable_tracing(&mut self) -> Option<JobTracer> {
+        if !self.metrics_recorder.is_enabled() {
+            self.action_manager.disable_tracing();
+        }
+        self.trace_recorder.disable()
+    }
+
+    #[cfg(feature = "trace")]
+    #[inline]
+    pub fn tracer(&self) -> Option<&JobTracer> {
+        self.trace_recorder.tracer()
+    }
+
+    #[cfg(feature = "trace")]
+    #[inline]
+    pub fn tracer_mut(&mut self) -> Option<&mut JobTracer> {
+        self.trace_recorder.tracer_mut()
+    }
+
+    // Records the counters of the actions, the jobs and the provider callbacks until disabled.
+    // It does nothing without the metrics feature.
+    pub fn enable_metrics(&mut self) {
+        self.metrics_recorder.enable();
+        if self.metrics_recorder.is_enabled() {
+            self.action_manager.enable_tracing();
+        }
+    }
+
+    // Returns the recorded metrics
+    pub fn disable_metrics(&mut self) -> Option<JobMetrics> {
+        let metrics = self.metrics();
+        if !self.trace_recorder.is_enabled() {
+            self.action_manager.disable_tracing();
+        }
+        self.metrics_recorder.disable();
+        metrics
+    }
+
+    // Returns the snapshot of the metrics with the current queue
+    pub fn metrics(&self) -> Option<JobMetrics> {
+        let mut metrics = self.metrics_recorder.metrics()?.clone();
+        metrics.record_pending_actions(
+            self.action_manager.current_tick(),
+            self.action_manager.pending_actions(),
+        );
+        Some(metrics)
+    }
+
+    // Clears the counters and keeps recording
+    #[inline]
+    pub fn reset_metrics(&mut self) {
+        self.metrics_recorder.reset();
+    }
+
+    // Records the actions committed by the jobs for the replicated clients until disabled
+    #[inline]
+    pub fn enable_replication(&mut self) {
+        self.action_manager.enable_replication();
+    }
+
+    #[inline]
+    pub fn disable_replication(&mut self) {
+        self.action_manager.disable_replication();
+    }
+
+    #[inline]
+    pub fn pull_replication<U: HordeActions>(&mut self) -> ReplicationDelta<U> {
+        self.action_manager
+            .pull_replication(U::convert_actions_from_typed_action_any_map)
+    }
+
+    // Records the changed entities for the deltas until disabled. The first delta is based on the
+    // data at the current tick.
+    pub fn enable_delta(&mut self) {
+        self.action_manager.enable_dirty_tracking();
+        if self.delta_base_tick.is_none() {
+            self.delta_base_tick = Some(self.action_manager.current_tick());
+        }
+    }
+
+    pub fn disable_delta(&mut self) {
+        self.action_manager.disable_dirty_tracking();
+        self.delta_base_tick = None;
+    }
+
+    // Takes the changes since the last delta, which is applied to the data at the last delta
+    pub fn pull_delta<U: HordeActions>(&mut self) -> Option<JobManagerDelta<U, T::Job>> {
+        let base_tick = self.delta_base_tick?;
+        let changed_entities = self.action_manager.pull_dirty_entities();
+        self.delta_base_tick = Some(self.action_manager.current_tick());
+        Some(JobManagerDelta::new(
+            base_tick,
+            self.to_data(),
+            &changed_entities,
+        ))
+    }
+
+    pub fn controller(&mut self) -> JobController<T::Job> {
+        JobController {
+            action_manager: &mut self.action_manager,
+            jobs: &mut self.jobs,
+        }
+    }
+
+    fn run_actions<F>(&mut self, provider: &mut T, evaluate: &mut F)
+    where
+        F: FnMut(&mut Self, &mut T, BTreeSet<(EntityId, ActionLayer)>),
+    {
+        // Loop for each tick
+        loop {
+            let result_or_none = self.action_manager.pull_actions();
+            let traces = self.action_manager.pull_traces();
+            self.metrics_recorder.record_actions(&traces);
+            self.trace_recorder.record_actions(&traces);
+            if let Some(result) = &result_or_none {
+                let started = self.start_callback();
+                provider.on_change_tick(
+                    &result.map,
+                    JobController {
+                        action_manager: &mut self.action_manager,
+                        jobs: &mut self.jobs,
+                    },
+                );
+                self.end_callback(ProviderCallback::ChangeTick, started);
+                if !result.timers.is_empty() {
+                    let started = self.start_callback();
+                    provider.on_timer(
+                        &result.timers,
+                        JobController {
+                            action_manager: &mut self.action_manager,
+                            jobs: &mut self.jobs,
+                        },
+                    );
+                    self.end_callback(ProviderCallback::Timer, started);
+                }
+
+                for ((entity_id, layer), reason) in result.canceled_layers.iter() {
+                    let started = self.start_callback();
+                    provider.on_cancel_job_with_layer(
+                        *entity_id,
+                        *layer,
+                        std::mem::take(self.jobs.get_mut(&(*entity_id, *layer)).unwrap()),
+                        reason.clone(),
+                    );
+                    self.end_callback(ProviderCallback::CancelJob, started);
+                }
+            }
+
+            let vacated_layers = self.action_manager.pull_vacated_layers();
+            if vacated_layers.is_empty() && result_or_none.is_none() {
+                break;
+            }
+            evaluate(self, provider, vacated_layers);
+
+            self.record_checksum(provider);
+        }
+
+        let started = self.start_callback();
+        provider.on_change_time(
+            self.action_manager.pull_updates(),
+            self.action_manager.next_time(),
+        );
+        self.end_callback(ProviderCallback::ChangeTime, started);
+    }
+
+    #[inline]
+    fn start_callback(&self) -> Option<Instant> {
+        self.metrics_recorder.start_callback()
+    }
+
+    #[inline]
+    fn end_callback(&mut self, callback: ProviderCallback, started: Option<Instant>) {
+        self.metrics_recorder.end_callback(callback, started);
+    }
+
+    fn checksum_with(
+        &self,
+        provider: &T,
+        hash_jobs: fn(&[T::Job], &mut StateHasher),
+    ) -> StateChecksum {
+        self.action_manager.checksum_with(|entity_id, hasher| {
+            for ((_, layer), jobs) in self
+                .jobs
+                .range((entity_id, ActionLayer::MIN)..=(entity_id, ActionLayer::MAX))
+            {
+                layer.hash(hasher);
+                hash_jobs(jobs, hasher);
+            }
+            provider.on_checksum(entity_id, hasher);
+        })
+    }
+
+    fn record_snapshot(&mut self) {
+        if self.rollback_buffer.is_none() {
+            return;
+        }
+        let snapshot = self.snapshot();
+        if let Some(buffer) = &mut self.rollback_buffer {
+            buffer.push(self.action_manager.current_tick(), snapshot);
+        }
+    }
+
+    fn evaluate_jobs(
+        &mut self,
+        provider: &mut T,
+        vacated_layers: BTreeSet<(EntityId, ActionLayer)>,
+    ) {
+        for (entity_id, layer) in vacated_layers {
+            let key = (entity_id, layer);
+            let mut priority = 0;
+            let mut job_queue: VecDeque<T::Job> = VecDeque::new();
+            self.jobs.get_mut(&key).unwrap().clear();
+            let started = self.start_callback();
+            job_queue.push_front(provider.on_first_with_layer(entity_id, layer, priority));
+            self.end_callback(ProviderCallback::First, started);
+            self.trace_first(entity_id, layer, priority, &job_queue[0]);
+
+            while let Some(job) = job_queue.pop_front() {
+                self.jobs.get_mut(&key).unwrap().push(job.clone());
+
+                let traced_job = self
+                    .trace_recorder
+                    .format_job()
+                    .map(|format_job| format_job(&job));
+                let started = self.start_callback();
+                let result = provider.on_next_with_layer(
+                    entity_id,
+                    layer,
+                    job,
+                    &mut self.action_manager.controller(),
+                );
+                self.end_callback(ProviderCallback::Next, started);
+                if let Some(job) = traced_job {
+                    self.trace_recorder.record_next(
+                        self.action_manager.current_tick(),
+                        entity_id,
+                        layer,
+                        job,
+                        result.as_ref(),
+                    );
+                }
+                if job_queue.is_empty()
+                    && !self
+                        .action_manager
+                        .has_some_actions_on_layer(entity_id, layer)
+                {
+                    // If the jobs and actions cannot be generated from the current job tree,
+                    // change the priority and recreate the first job
+                    priority += 1;
+                    self.metrics_recorder.record_escalations(1);
+                    self.jobs.get_mut(&key).unwrap().clear();
+                    let started = self.start_callback();
+                    job_queue.push_front(provider.on_first_with_layer(entity_id, layer, priority));
+                    self.end_callback(ProviderCallback::First, started);
+                    self.trace_first(entity_id, layer, priority, &job_queue[0]);
+                    continue;
+                }
+
+                if let Some(result) = result {
+                    job_queue.push_back(result);
+                }
+            }
+        }
+    }
+
+    fn trace_first(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        priority: u32,
+        job: &T::Job,
+    ) {
+        self.trace_recorder.record_first(
+            self.action_manager.current_tick(),
+            entity_id,
+            layer,
+            priority,
+            job,
+        );
+    }
+
+    fn record_checksum(&mut self, provider: &T) {
+        let hash_jobs = match &self.checksum_recorder {
+            None => return,
+            Some(recorder) => recorder.hash_jobs,
+        };
+        let checksum = self.checksum_with(provider, hash_jobs);
+        if let Some(recorder) = &mut self.checksum_recorder {
+            recorder.history.push(checksum);
+        }
+    }
+}
+
+// The provider whose jobs can be evaluated on the worker threads
+impl<T> JobManager<T>
+where
+    T: ParallelHordeInterface + 'static,
+    T::Job: Send,
+{
+    // Runs the same as run, but the jobs of the vacated layers are evaluated on the thread pool.
+    // The results are the same whatever the number of the threads is.
+    pub fn run_parallel(
+        &mut self,
+        provider: &mut T,
+        thread_pool: &ThreadPool,
+        delta: TimeMilliseconds,
+    ) {
+        self.run_with(provider, delta, |manager, provider, vacated_layers| {
+            manager.evaluate_jobs_parallel(provider, thread_pool, vacated_layers);
+        });
+    }
+
+    fn evaluate_jobs_parallel(
+        &mut self,
+        provider: &mut T,
+        thread_pool: &ThreadPool,
+        vacated_layers: BTreeSet<(EntityId, ActionLayer)>,
+    ) {
+        if vacated_layers.is_empty() {
+            return;
+        }
+        let view = provider.view();
+        let format_job = self.trace_recorder.format_job();
+        let timed = self.metrics_recorder.is_enabled();
+        let (sender, receiver) = mpsc::channel();
+        for (entity_id, layer) in vacated_layers.iter().copied() {
+            let buffer = ActionBuffer::new(entity_id, layer, &self.action_manager);
+            let view = Arc::clone(&view);
+            let sender = sender.clone();
+            thread_pool.execute(move || {
+                let evaluation = evaluate_jobs::<T>(&view, buffer, format_job, timed);
+                let _ = sender.send(((entity_id, layer), evaluation));
+            });
+        }
+        drop(sender);
 
-pub struct JobManager<T: HordeInterface> {
+        // Commits in the order of the entity ids regardless of the finished order
+        let mut evaluations = BTreeMap::new();
+        for _ in 0..vacated_layers.len() {
+            let (key, evaluation) = receiver.recv().expect("The job worker has panicked");
+            evaluations.insert(key, evaluation);
+        }
+        for ((entity_id, layer), evaluation) in evaluations {
+            self.metrics_recorder
+                .record_escalations(evaluation.priority as u64);
+            #[cfg(feature = "metrics")]
+            if let Some(callbacks) = evaluation.callbacks {
+                self.metrics_recorder.record_callbacks(callbacks);
+            }
+            #[cfg(feature = "trace")]
+            for kind in evaluation.traces {
+                self.trace_recorder.record(TraceEvent {
+                    tick: self.action_manager.current_tick(),
+                    entity_id,
+                    layer,
+                    kind,
+                });
+            }
+            self.jobs.insert((entity_id, layer), evaluation.jobs);
+            evaluation
+                .buffer
+                .commit(&mut self.action_manager.controller());
+        }
+    }
+}
+
+// The replicated client of the JobManager in the server, which plays the actions committed by
+// the server without running any jobs. The clock waits for the deltas, so the client plays the same
+// ticks as the server behind the latency.
+pub struct JobClient<T: HordeInterface> {
     action_manager: ActionManager,
-    jobs: HashMap<EntityId, Vec<T::Job>>,
+    jobs: BTreeMap<(EntityId, ActionLayer), Vec<T::Job>>, // The client never runs the jobs
+    server_tick: Tick,                                    // The tick of the last delta
 }
 
-impl<T> Default for JobManager<T>
+impl<T> Default for JobClient<T>
 where
     T: HordeInterface,
 {
     fn default() -> Self {
-        JobManager {
+        JobClient {
             action_manager: Default::default(),
             jobs: Default::default(),
+            server_tick: 0,
         }
     }
 }
 
-impl<T> JobManager<T>
+impl<T> JobClient<T>
 where
     T: HordeInterface,
 {
-    pub fn run(&mut self, provider: &mut T, delta: TimeMilliseconds) {
-        while !self.action_manager.get_vacated_entities().is_empty() {
-            self.run_actions(provider);
+    pub fn with_tick_duration(tick_duration: TimeMilliseconds) -> Self {
+        JobClient {
+            action_manager: ActionManager::with_tick_duration(tick_duration),
+            jobs: Default::default(),
+            server_tick: 0,
         }
-        self.action_manager.update(delta);
-        self.run_actions(provider);
-    }
-
-    #[inline]
-    pub fn attach(&mut self, entity_id: EntityId) {
-        self.controller().attach(entity_id);
-    }
-
-    #[inline]
-    pub fn detach(&mut self, entity_id: EntityId) {
-        self.controller().detach(entity_id);
     }
 
     #[inline]
-    pub fn interrupt<U>(&mut self, entity_id: EntityId, raw: Arc<U>, duration: TimeMilliseconds)
+    pub fn push_delta<U>(&mut self, delta: ReplicationDelta<U>)
     where
-        U: 'static,
+        U: HordeActions + Clone,
     {
-        self.action_manager.interrupt(entity_id, raw, duration);
-    }
-
-    pub fn to_data<U>(
-        &self,
-        converter0: fn(&TypedAnyActionMap, &ActionSessionValidator) -> Vec<Action<U>>,
-        converter1: fn(&TypedAnyActionMapGroupedByEntityId) -> Vec<Action<U>>,
-        converter2: fn(&TypeId, &AnyActionVec, &ActionSessionValidator) -> Vec<Action<U>>,
-    ) -> JobManagerData<U, T::Job> {
-        JobManagerData {
-            action_manager_data: self
-                .action_manager
-                .to_data(converter0, converter1, converter2),
-            jobs: self.jobs.clone(),
-        }
-    }
-
-    pub fn load_data<U>(
-        &mut self,
-        mut data: JobManagerData<U, T::Job>,
-        converter: fn(action: Action<U>, manager: &mut ActionManagerConverter),
-    ) -> Result<ActionRemapperToken, JobManagerError> {
-        let token = self
-            .action_manager
-            .load_data(data.action_manager_data, converter)
-            .map_err(JobManagerError::ActionManagerError)?;
-        for (entity_id, job) in data.jobs.drain() {
-            self.jobs.insert(ENTITY_REMAPPER.remap(entity_id), job);
-        }
-        Ok(token)
+        self.server_tick = self.server_tick.max(delta.tick);
+        self.action_manager
+            .apply_replication(delta, U::convert_from_actions);
     }
 
-    pub fn from_data<U>(
-        data: JobManagerData<U, T::Job>,
-        converter: fn(action: Action<U>, manager: &mut ActionManagerConverter),
-    ) -> Result<Self, JobManagerError> {
-        let action_manager = ActionManager::from_data(data.action_manager_data, converter)
-            .map_err(JobManagerError::ActionManagerError)?;
-        for (entity_id, _) in data.jobs.iter() {
-            if !action_manager.has_some_actions(*entity_id) {
-                return Err(JobManagerError::NotFoundEntity(*entity_id));
+    pub fn run(&mut self, provider: &mut T, delta: TimeMilliseconds) {
+        // The vacated layers are filled by the server
+        self.action_manager.pull_vacated_layers();
+        self.action_manager.update_until(delta, self.server_tick);
+        while let Some(result) = self.action_manager.pull_actions() {
+            provider.on_change_tick(
+                &result.map,
+                JobController {
+                    action_manager: &mut self.action_manager,
+                    jobs: &mut self.jobs,
+                },
+            );
+            if !result.timers.is_empty() {
+                provider.on_timer(
+                    &result.timers,
+                    JobController {
+                        action_manager: &mut self.action_manager,
+                        jobs: &mut self.jobs,
+                    },
+                );
             }
+            self.action_manager.pull_vacated_layers();
         }
-        Ok(JobManager {
-            action_manager,
-            jobs: data.jobs,
-        })
+        provider.on_change_time(
+            self.action_manager.pull_updates(),
+            self.action_manager.next_time(),
+        );
     }
 
     #[inline]
@@ -114,69 +1292,35 @@ where
         self.action_manager.current_tick()
     }
 
-    pub fn controller(&mut self) -> JobController<T::Job> {
-        JobController {
-            action_manager: &mut self.action_manager,
-            jobs: &mut self.jobs,
-        }
+    #[inline]
+    pub fn tick_duration(&self) -> TimeMilliseconds {
+        self.action_manager.tick_duration()
     }
 
-    fn run_actions(&mut self, provider: &mut T) {
-        // Loop for each tick
-        loop {
-            let result_or_none = self.action_manager.pull_actions();
-            if let Some(result) = &result_or_none {
-                provider.on_change_tick(
-                    &result.map,
-                    JobController {
-                        action_manager: &mut self.action_manager,
-                        jobs: &mut self.jobs,
-                    },
-                );
-
-                for entity_id in result.cancels.iter() {
-                    provider.on_cancel_job(
-                        *entity_id,
-                        std::mem::take(self.jobs.get_mut(entity_id).unwrap()),
-                    );
-                }
-            }
+    #[inline]
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.action_manager.interpolation_alpha()
+    }
 
-            let vacated_entities = self.action_manager.pull_vacated_entities();
-            if vacated_entities.is_empty() && result_or_none.is_none() {
-                break;
-            }
-            for entity_id in vacated_entities {
-                let mut priority = 0;
-                let mut job_queue: VecDeque<T::Job> = VecDeque::new();
-                self.jobs.get_mut(&entity_id).unwrap().clear();
-                job_queue.push_front(provider.on_first(entity_id, priority));
-
-                while let Some(job) = job_queue.pop_front() {
-                    self.jobs.get_mut(&entity_id).unwrap().push(job.clone());
-
-                    let result =
-                        provider.on_next(entity_id, job, &mut self.action_manager.controller());
-                    if job_queue.is_empty() && !self.action_manager.has_some_actions(entity_id) {
-                        // If the jobs and actions cannot be generated from the current job tree,
-                        // change the priority and recreate the first job
-                        priority += 1;
-                        self.jobs.get_mut(&entity_id).unwrap().clear();
-                        job_queue.push_front(provider.on_first(entity_id, priority));
-                        continue;
-                    }
+    #[inline]
+    pub fn evaluate_tween<U>(&self, action: &ArcAction<Tween<U>>) -> U
+    where
+        U: Tweenable,
+    {
+        self.action_manager.evaluate_tween(action)
+    }
 
-                    if let Some(result) = result {
-                        job_queue.push_back(result);
-                    }
-                }
-            }
-        }
+    #[inline]
+    pub fn upcoming_actions<U>(&self, entity_id: EntityId) -> impl Iterator<Item = &ArcAction<U>>
+    where
+        U: 'static,
+    {
+        self.action_manager.upcoming_actions(entity_id)
+    }
 
-        provider.on_change_time(
-            self.action_manager.pull_updates(),
-            self.action_manager.next_time(),
-        );
+    #[inline]
+    pub fn validator(&self) -> ActionSessionValidator<'_> {
+        self.action_manager.validator()
     }
 }
 
@@ -196,20 +1340,86 @@ impl<'a> JobActionController<'a> {
 
 pub struct JobController<'a, T> {
     action_manager: &'a mut ActionManager,
-    jobs: &'a mut HashMap<EntityId, Vec<T>>,
+    jobs: &'a mut BTreeMap<(EntityId, ActionLayer), Vec<T>>,
 }
 
 impl<'a, T> JobController<'a, T> {
     #[inline]
     pub fn attach(&mut self, entity_id: EntityId) {
-        self.action_manager.attach(entity_id);
-        self.jobs.insert(entity_id, Vec::new());
+        self.attach_layer(entity_id, DEFAULT_ACTION_LAYER);
     }
 
+    // Detaches all layers of the entity
     #[inline]
     pub fn detach(&mut self, entity_id: EntityId) {
         self.action_manager.detach(entity_id);
-        self.jobs.remove(&entity_id);
+        self.jobs
+            .retain(|(job_entity_id, _layer), _| *job_entity_id != entity_id);
+    }
+
+    #[inline]
+    pub fn attach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.action_manager.attach_layer(entity_id, layer);
+        self.jobs.insert((entity_id, layer), Vec::new());
+    }
+
+    #[inline]
+    pub fn detach_layer(&mut self, entity_id: EntityId, layer: ActionLayer) {
+        self.action_manager.detach_layer(entity_id, layer);
+        self.jobs.remove(&(entity_id, layer));
+    }
+
+    #[inline]
+    pub fn schedule_timer<U>(&mut self, raw: Arc<U>, delay: TimeMilliseconds) -> TimerId
+    where
+        U: 'static,
+    {
+        self.action_manager.schedule_timer(raw, delay)
+    }
+
+    #[inline]
+    pub fn schedule_timer_with_options<U>(
+        &mut self,
+        raw: Arc<U>,
+        delay: TimeMilliseconds,
+        options: TimerOptions,
+    ) -> TimerId
+    where
+        U: 'static,
+    {
+        self.action_manager
+            .schedule_timer_with_options(raw, delay, options)
+    }
+
+    #[inline]
+    pub fn cancel_timer(&mut self, timer_id: TimerId) -> bool {
+        self.action_manager.cancel_timer(timer_id)
+    }
+
+    #[inline]
+    pub fn reschedule(&mut self, entity_id: EntityId, end_tick: Tick) -> bool {
+        self.action_manager.reschedule(entity_id, end_tick)
+    }
+
+    #[inline]
+    pub fn reschedule_layer(
+        &mut self,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        end_tick: Tick,
+    ) -> bool {
+        self.action_manager
+            .reschedule_layer(entity_id, layer, end_tick)
+    }
+
+    #[inline]
+    pub fn freeze(&mut self, entity_id: EntityId) {
+        self.action_manager.freeze(entity_id);
+    }
+
+    #[inline]
+    pub fn unfreeze(&mut self, entity_id: EntityId) {
+        self.action_manager.unfreeze(entity_id);
     }
 
     pub fn validator(&self) -> ActionSessionValidator {
@@ -221,14 +1431,547 @@ impl<'a, T> JobController<'a, T> {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct JobManagerData<T, U> {
     action_manager_data: ActionManagerData<T>,
-    jobs: HashMap<EntityId, Vec<U>>,
+    jobs: HashMap<EntityId, Vec<U>>, // The jobs of the default layer
+    #[serde(default = "HashMap::new")] // Avoids the Default bound of U
+    layer_jobs: HashMap<EntityId, BTreeMap<ActionLayer, Vec<U>>>,
+}
+
+impl<T, U> JobManagerData<T, U> {
+    // Every layer of the jobs must have some actions in the data
+    pub fn new(
+        action_manager_data: ActionManagerData<T>,
+        jobs: BTreeMap<(EntityId, ActionLayer), Vec<U>>,
+    ) -> Self {
+        let mut default_jobs = HashMap::new();
+        let mut layer_jobs: HashMap<EntityId, BTreeMap<ActionLayer, Vec<U>>> = HashMap::new();
+        for ((entity_id, layer), entity_jobs) in jobs {
+            if layer == DEFAULT_ACTION_LAYER {
+                default_jobs.insert(entity_id, entity_jobs);
+            } else {
+                layer_jobs
+                    .entry(entity_id)
+                    .or_default()
+                    .insert(layer, entity_jobs);
+            }
+        }
+        JobManagerData {
+            action_manager_data,
+            jobs: default_jobs,
+            layer_jobs,
+        }
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.action_manager_data.current_tick()
+    }
+
+    pub fn retain_entities(&mut self, entity_ids: &BTreeSet<EntityId>) {
+        self.action_manager_data.retain_entities(entity_ids);
+        self.jobs
+            .retain(|entity_id, _| entity_ids.contains(entity_id));
+        self.layer_jobs
+            .retain(|entity_id, _| entity_ids.contains(entity_id));
+    }
+
+    // Takes out the actions and the jobs by the entity, used for the delta snapshots
+    pub fn split_entities(self) -> (Self, BTreeMap<EntityId, EntityData<T, U>>) {
+        let (action_manager_data, actions) = self.action_manager_data.split_entities();
+        let mut entities: BTreeMap<EntityId, EntityData<T, U>> = actions
+            .into_iter()
+            .map(|(entity_id, actions)| {
+                let entity = EntityData {
+                    actions,
+                    jobs: BTreeMap::new(),
+                };
+                (entity_id, entity)
+            })
+            .collect();
+        for ((entity_id, layer), jobs) in flatten_jobs(self.jobs, self.layer_jobs) {
+            entities
+                .entry(entity_id)
+                .or_default()
+                .jobs
+                .insert(layer, jobs);
+        }
+        (
+            JobManagerData::new(action_manager_data, BTreeMap::new()),
+            entities,
+        )
+    }
+
+    pub fn merge_entities(self, entities: BTreeMap<EntityId, EntityData<T, U>>) -> Self {
+        let mut jobs = flatten_jobs(self.jobs, self.layer_jobs);
+        let mut actions = BTreeMap::new();
+        for (entity_id, entity) in entities {
+            for (layer, entity_jobs) in entity.jobs {
+                jobs.insert((entity_id, layer), entity_jobs);
+            }
+            actions.insert(entity_id, entity.actions);
+        }
+        JobManagerData::new(self.action_manager_data.merge_entities(actions), jobs)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntityData<T, U> {
+    actions: EntityActionData<T>,
+    jobs: BTreeMap<ActionLayer, Vec<U>>,
+}
+
+impl<T, U> Default for EntityData<T, U> {
+    fn default() -> Self {
+        EntityData {
+            actions: EntityActionData::default(),
+            jobs: BTreeMap::new(),
+        }
+    }
+}
+
+fn flatten_jobs<T>(
+    jobs: HashMap<EntityId, Vec<T>>,
+    layer_jobs: HashMap<EntityId, BTreeMap<ActionLayer, Vec<T>>>,
+) -> BTreeMap<(EntityId, ActionLayer), Vec<T>> {
+    let mut flatten = jobs
+        .into_iter()
+        .map(|(entity_id, jobs)| ((entity_id, DEFAULT_ACTION_LAYER), jobs))
+        .collect::<BTreeMap<_, _>>();
+    for (entity_id, layers) in layer_jobs {
+        for (layer, jobs) in layers {
+            flatten.insert((entity_id, layer), jobs);
+        }
+    }
+    flatten
+}
+
+#[derive(Clone)]
+pub struct JobManagerSnapshot<T> {
+    action_manager: ActionManagerSnapshot,
+    jobs: BTreeMap<(EntityId, ActionLayer), Vec<T>>,
+}
+
+impl<T> JobManagerSnapshot<T> {
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.action_manager.current_tick()
+    }
+}
+
+// The broken invariant found by JobManager::verify
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum JobManagerViolation {
+    ActionManagerViolation(ActionManagerViolation),
+    // The job stack of the layer which isn't attached
+    NotAttached {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
+    NoJobStack {
+        entity_id: EntityId,
+        layer: ActionLayer,
+    },
 }
 
 #[derive(Debug)]
 pub enum JobManagerError {
     ActionManagerError(ActionManagerError),
     NotFoundEntity(EntityId),
+    InvalidJournalTick { expected: Tick, actual: Tick },
+    NotFoundSnapshot(Tick),
+    InvalidDeltaTick { expected: Tick, actual: Tick },
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::{ActionController, EnqueueOptions, RemapContext};
+    use crate::v2::action::replication::ReplicationDelta;
+    use crate::v2::action::{ActionLayer, CancelReason, DEFAULT_ACTION_LAYER};
+    use crate::v2::job::manager::{JobClient, JobController, JobManager, JobManagerData};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct EmoteState;
+
+    define_actions!(TestAction, (Move, MoveState), (Emote, EmoteState));
+
+    #[derive(Default)]
+    struct TestProvider {
+        logs: Vec<String>,
+        ticks: Vec<String>,
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = ActionLayer;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<u32>) {
+            let validator = controller.validator();
+            let mut actions = convert_actions_from_typed_action_any_map(map, &validator)
+                .into_iter()
+                .map(|action| {
+                    let name = match action.raw() {
+                        TestAction::Move(_) => "move",
+                        TestAction::Emote(_) => "emote",
+                    };
+                    format!(
+                        "{} {}:{} {:?}",
+                        name,
+                        action.entity_id(),
+                        action.layer(),
+                        action.ty()
+                    )
+                })
+                .collect::<Vec<_>>();
+            actions.sort();
+            self.ticks.append(&mut actions);
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, entity_id: EntityId, jobs: Vec<u32>, reason: CancelReason) {
+            self.on_cancel_job_with_layer(entity_id, DEFAULT_ACTION_LAYER, jobs, reason);
+        }
+
+        fn on_first(&self, entity_id: EntityId, priority: u32) -> u32 {
+            self.on_first_with_layer(entity_id, DEFAULT_ACTION_LAYER, priority)
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: u32,
+            controller: &mut ActionController,
+        ) -> Option<u32> {
+            self.on_next_with_layer(entity_id, DEFAULT_ACTION_LAYER, job, controller)
+        }
+
+        fn on_cancel_job_with_layer(
+            &mut self,
+            entity_id: EntityId,
+            layer: ActionLayer,
+            jobs: Vec<u32>,
+            _reason: CancelReason,
+        ) {
+            self.logs
+                .push(format!("cancel {} {} {:?}", entity_id, layer, jobs));
+        }
+
+        fn on_first_with_layer(
+            &self,
+            _entity_id: EntityId,
+            layer: ActionLayer,
+            _priority: u32,
+        ) -> u32 {
+            layer
+        }
+
+        fn on_next_with_layer(
+            &self,
+            entity_id: EntityId,
+            layer: ActionLayer,
+            _job: u32,
+            controller: &mut ActionController,
+        ) -> Option<u32> {
+            if layer == 0 {
+                controller.enqueue(entity_id, Arc::new(MoveState), 1000);
+            } else {
+                controller.enqueue_to_layer(
+                    entity_id,
+                    layer,
+                    Arc::new(EmoteState),
+                    400,
+                    EnqueueOptions::default(),
+                );
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut provider = TestProvider::default();
+        let mut manager: JobManager<TestProvider> = JobManager::default();
+        manager.attach(1);
+        manager.attach_layer(1, 3);
+
+        manager.run(&mut provider, 500);
+        let updates = manager.action_manager.pull_updates();
+        assert_eq!(updates.get::<MoveState>().unwrap().len(), 1);
+        assert_eq!(updates.get::<EmoteState>().unwrap().len(), 1);
+        assert_eq!(manager.jobs[&(1, 0)], vec![0]);
+        assert_eq!(manager.jobs[&(1, 3)], vec![3]);
+
+        manager.cancel_layer(1, 3, true);
+        manager.run(&mut provider, 0);
+        assert_eq!(provider.logs, vec!["cancel 1 3 [3]"]);
+        assert!(manager.action_manager.has_some_actions_on_layer(1, 3));
+
+        let data = manager.to_data::<TestAction>();
+        let data: JobManagerData<TestAction, u32> =
+            serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+        let new_manager: JobManager<TestProvider> = JobManager::from_data(data).unwrap();
+        assert_eq!(new_manager.jobs, manager.jobs);
+
+        manager.detach(1);
+        assert!(manager.jobs.is_empty());
+    }
+
+    #[test]
+    fn test_serialization_with_end_at_frozen_tick() {
+        let mut provider = TestProvider::default();
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        manager.attach(1);
+        manager.run(&mut provider, 300); // tick: 3
+        manager.freeze(1);
+        assert!(manager.reschedule(1, 0)); // The End is pending at the frozen tick
+        assert!(!manager.action_manager.has_some_actions_on_layer(1, 0));
+
+        let data = manager.to_data::<TestAction>();
+        let data: JobManagerData<TestAction, u32> =
+            serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+        let mut manager: JobManager<TestProvider> = JobManager::from_data(data).unwrap();
+        provider.ticks.clear();
+        manager.unfreeze(1);
+        manager.run(&mut provider, 0);
+        assert_eq!(
+            provider.ticks,
+            vec![
+                "move 1:0 End { start: 0, end: 3 }",
+                "move 1:0 Start { start: 3, end: 13, each: false }",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replication() {
+        let (sender, receiver) = channel::<String>();
+        let mut server_provider = TestProvider::default();
+        let mut server: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        let mut client_provider = TestProvider::default();
+        let mut client: JobClient<TestProvider> = JobClient::with_tick_duration(100);
+
+        server.enable_replication();
+        server.attach(1);
+        server.attach_layer(1, 3);
+        for frame in 0..30 {
+            if frame == 5 {
+                server.cancel_layer(1, 3, true);
+            }
+            if frame == 12 {
+                server.cancel(1, false);
+            }
+            if frame == 20 {
+                server.detach_layer(1, 3);
+            }
+            server.run(&mut server_provider, 100);
+            let delta = server.pull_replication::<TestAction>();
+            sender.send(serde_json::to_string(&delta).unwrap()).unwrap();
+
+            for message in receiver.try_iter() {
+                let delta: ReplicationDelta<TestAction> = serde_json::from_str(&message).unwrap();
+                client.push_delta(delta);
+            }
+            client.run(&mut client_provider, 100);
+            assert_eq!(client.current_tick(), server.current_tick());
+        }
+        assert!(!server_provider.ticks.is_empty());
+        assert_eq!(client_provider.ticks, server_provider.ticks);
+    }
+
+    #[test]
+    fn test_replication_with_latency() {
+        let (sender, receiver) = channel::<(usize, String)>();
+        let mut server_provider = TestProvider::default();
+        let mut server: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        let mut client_provider = TestProvider::default();
+        let mut client: JobClient<TestProvider> = JobClient::with_tick_duration(100);
+
+        server.enable_replication();
+        server.attach(1);
+        let mut pending = Vec::new();
+        for frame in 0..20 {
+            if frame == 4 {
+                server.cancel(1, true);
+            }
+            server.run(&mut server_provider, 100);
+            let delta = server.pull_replication::<TestAction>();
+            sender
+                .send((frame, serde_json::to_string(&delta).unwrap()))
+                .unwrap();
+
+            // The deltas arrive 2 frames later
+            pending.extend(receiver.try_iter());
+            while !pending.is_empty() && pending[0].0 + 2 <= frame {
+                let delta: ReplicationDelta<TestAction> =
+                    serde_json::from_str(&pending.remove(0).1).unwrap();
+                client.push_delta(delta);
+            }
+            client.run(&mut client_provider, 100);
+        }
+        // The client plays the same ticks 2 frames behind
+        assert_eq!(client.current_tick() + 2, server.current_tick());
+        assert_eq!(client_provider.ticks, server_provider.ticks);
+        assert_eq!(
+            server_provider.ticks,
+            vec![
+                "move 1:0 Start { start: 0, end: 10, each: false }",
+                "move 1:0 Start { start: 4, end: 14, each: false }",
+                "move 1:0 End { start: 4, end: 14 }",
+                "move 1:0 Start { start: 14, end: 24, each: false }",
+            ]
+        );
+    }
+
+    // The xorshift generator, which makes the random commands reproducible by the seed
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n.max(1)
+        }
+    }
+
+    #[test]
+    fn test_verify_with_random_commands() {
+        for seed in 1..=32u64 {
+            let mut random = Random(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut provider = TestProvider::default();
+            let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+            let mut entities: BTreeMap<EntityId, bool> = BTreeMap::new(); // value = layer 3
+            let mut freezes = BTreeSet::new();
+            let mut next_entity_id = 1;
+            let mut last = None;
+            for step in 0..200 {
+                let entity_id = entities
+                    .keys()
+                    .nth(random.next(entities.len() as u64) as usize)
+                    .copied();
+                let command = match (random.next(12), entity_id) {
+                    (0, _) | (_, None) => {
+                        manager.attach(next_entity_id);
+                        entities.insert(next_entity_id, false);
+                        next_entity_id += 1;
+                        format!("attach {}", next_entity_id - 1)
+                    }
+                    (1, Some(entity_id)) => {
+                        manager.detach(entity_id);
+                        entities.remove(&entity_id);
+                        freezes.remove(&entity_id);
+                        format!("detach {}", entity_id)
+                    }
+                    (2, Some(entity_id)) => {
+                        let duration = random.next(1500);
+                        manager.interrupt(entity_id, Arc::new(MoveState), duration);
+                        format!("interrupt {} {}", entity_id, duration)
+                    }
+                    (3, Some(entity_id)) => {
+                        let immediate = random.next(2) == 0;
+                        manager.cancel(entity_id, immediate);
+                        format!("cancel {} {}", entity_id, immediate)
+                    }
+                    (4, Some(entity_id)) => {
+                        let layer = entities.get_mut(&entity_id).unwrap();
+                        *layer = !*layer;
+                        if *layer {
+                            manager.attach_layer(entity_id, 3);
+                        } else {
+                            manager.detach_layer(entity_id, 3);
+                        }
+                        format!("toggle layer {}", entity_id)
+                    }
+                    (5, Some(entity_id)) => {
+                        if freezes.insert(entity_id) {
+                            manager.freeze(entity_id);
+                        } else {
+                            freezes.remove(&entity_id);
+                            manager.unfreeze(entity_id);
+                        }
+                        format!("toggle freeze {}", entity_id)
+                    }
+                    (6, Some(entity_id)) => {
+                        let end_tick = manager.current_tick() + random.next(20);
+                        manager.reschedule(entity_id, end_tick);
+                        format!("reschedule {} {}", entity_id, end_tick)
+                    }
+                    (7, Some(entity_id)) => {
+                        let duration = random.next(1500);
+                        manager
+                            .action_manager
+                            .enqueue(entity_id, Arc::new(EmoteState), duration);
+                        format!("enqueue {} {}", entity_id, duration)
+                    }
+                    (8, _) => {
+                        // Saves between the frames as to_data requires
+                        manager.run(&mut provider, 0);
+                        let data = manager.to_data::<TestAction>();
+                        let data: JobManagerData<TestAction, u32> =
+                            serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+                        manager = JobManager::from_data(data).unwrap();
+                        "save and load".to_string()
+                    }
+                    (9, _) => {
+                        manager.run(&mut provider, 0);
+                        let data = manager.to_data::<TestAction>();
+                        let data: JobManagerData<TestAction, u32> =
+                            serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+                        // The running manager shifts the loaded ticks back and forth
+                        let delta = random.next(1000);
+                        let mut context = RemapContext::default();
+                        manager = JobManager::with_tick_duration(100);
+                        manager.run(&mut provider, delta);
+                        manager.load_data(data, &mut context).unwrap();
+                        format!("save and load into the manager run {}", delta)
+                    }
+                    _ => {
+                        let delta = random.next(500);
+                        manager.run(&mut provider, delta);
+                        // The delta reproduces the data from the data of the last run
+                        let data = manager.to_data::<TestAction>();
+                        match manager.pull_delta::<TestAction>() {
+                            Some(delta) => {
+                                let next = delta.apply(last.take().unwrap()).unwrap();
+                                assert_eq!(
+                                    serde_json::to_value(&next).unwrap(),
+                                    serde_json::to_value(&data).unwrap(),
+                                    "seed: {}, step: {}",
+                                    seed,
+                                    step
+                                );
+                            }
+                            None => manager.enable_delta(),
+                        }
+                        last = Some(data);
+                        format!("run {}", delta)
+                    }
+                };
+                if let Err(violations) = manager.verify() {
+                    panic!(
+                        "seed: {}, step: {}, command: {}, violations: {:?}",
+                        seed, step, command, violations
+                    );
+                }
+            }
+        }
+    }
 }
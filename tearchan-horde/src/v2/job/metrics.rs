@@ -0,0 +1,306 @@
+use crate::v2::Tick;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+// The histogram with the buckets of the powers of two. The bucket 0 counts 0, and the bucket i
+// counts the values in 2^(i-1)..2^i.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    #[inline]
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_n(&mut self, value: u64, n: u64) {
+        if n == 0 {
+            return;
+        }
+        let index = (u64::BITS - value.leading_zeros()) as usize;
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += n;
+        self.count += n;
+        self.sum = self.sum.saturating_add(value.saturating_mul(n));
+        self.max = self.max.max(value);
+    }
+
+    // Iterates the non-empty buckets with the range of the values
+    pub fn iter(&self) -> impl Iterator<Item = (RangeInclusive<u64>, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| (bucket_range(index), *count))
+    }
+
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    #[inline]
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0f64;
+        }
+        self.sum as f64 / self.count as f64
+    }
+}
+
+fn bucket_range(index: usize) -> RangeInclusive<u64> {
+    match index {
+        0 => 0..=0,
+        64 => (1 << 63)..=u64::MAX,
+        index => (1 << (index - 1))..=((1 << index) - 1),
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ActionCounts {
+    pub started: u64,
+    pub ended: u64,
+    pub canceled: u64,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ProviderCallback {
+    ChangeTick,
+    Timer,
+    CancelJob,
+    First,
+    Next,
+    ChangeTime,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct CallbackTime {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+// The counters recorded by JobManager while the metrics are enabled
+#[derive(Debug, Clone, Default)]
+pub struct JobMetrics {
+    pub actions: BTreeMap<&'static str, ActionCounts>, // key = the type name of the action
+    // The first jobs recreated with the next priority because no action was generated
+    pub escalations: u64,
+    pub runs: u64,
+    pub ticks_per_run: Histogram,
+    // The callbacks on the worker threads of run_parallel are summed up on committing
+    pub callbacks: BTreeMap<ProviderCallback, CallbackTime>,
+    // The queued actions which haven't started by the ticks ahead of the current tick. It's
+    // counted when the metrics are read, and the frozen entities are excluded.
+    pub pending_actions: Histogram,
+}
+
+impl JobMetrics {
+    pub fn record_callback(&mut self, callback: ProviderCallback, elapsed: Duration) {
+        let time = self.callbacks.entry(callback).or_default();
+        time.calls += 1;
+        time.total += elapsed;
+        time.max = time.max.max(elapsed);
+    }
+
+    pub fn record_pending_actions<I>(&mut self, current_tick: Tick, pending_actions: I)
+    where
+        I: IntoIterator<Item = (Tick, usize)>,
+    {
+        self.pending_actions = Histogram::default();
+        for (tick, count) in pending_actions {
+            self.pending_actions
+                .record_n(tick.saturating_sub(current_tick), count as u64);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::CancelReason;
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::metrics::{ActionCounts, Histogram, ProviderCallback};
+    use crate::v2::job::HordeInterface;
+    use std::any::type_name;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    struct MoveState;
+
+    struct WaitState;
+
+    #[derive(Clone)]
+    enum TestJob {
+        Walk,
+        Wait,
+    }
+
+    struct TestProvider;
+
+    impl HordeInterface for TestProvider {
+        type Job = TestJob;
+
+        fn on_change_tick(
+            &mut self,
+            _map: &TypedAnyActionMap,
+            _controller: JobController<TestJob>,
+        ) {
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(
+            &mut self,
+            _entity_id: EntityId,
+            _jobs: Vec<TestJob>,
+            _reason: CancelReason,
+        ) {
+        }
+
+        fn on_first(&self, _entity_id: EntityId, priority: u32) -> TestJob {
+            match priority {
+                0 => TestJob::Walk,
+                _ => TestJob::Wait,
+            }
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: TestJob,
+            controller: &mut ActionController,
+        ) -> Option<TestJob> {
+            match job {
+                TestJob::Walk if entity_id == 1 => {
+                    controller.enqueue(entity_id, Arc::new(MoveState), 2000);
+                    Some(TestJob::Wait)
+                }
+                TestJob::Walk => None, // Falls back to the next priority
+                TestJob::Wait => {
+                    controller.enqueue(entity_id, Arc::new(WaitState), 500);
+                    None
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut provider = TestProvider;
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        assert!(manager.metrics().is_none());
+        manager.enable_metrics();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.run(&mut provider, 0);
+        manager.run(&mut provider, 1000); // tick: 10
+        manager.cancel(1, true);
+        manager.run(&mut provider, 100); // tick: 11
+
+        let metrics = manager.metrics().unwrap();
+        assert_eq!(
+            metrics.actions[type_name::<MoveState>()],
+            ActionCounts {
+                started: 2,
+                ended: 0,
+                canceled: 1,
+            }
+        );
+        assert_eq!(
+            metrics.actions[type_name::<WaitState>()],
+            ActionCounts {
+                started: 3,
+                ended: 2,
+                canceled: 0,
+            }
+        );
+        // The entity 2 falls back to the next priority whenever its wait ends
+        assert_eq!(metrics.escalations, 3);
+        assert_eq!(metrics.runs, 3);
+        assert_eq!(
+            metrics.ticks_per_run.iter().collect::<Vec<_>>(),
+            vec![(0..=0, 1), (1..=1, 1), (8..=15, 1)]
+        );
+        // The wait of the entity 1 is queued after the move of the tick 11-31
+        assert_eq!(
+            metrics.pending_actions.iter().collect::<Vec<_>>(),
+            vec![(16..=31, 1)]
+        );
+        // The first jobs of the 5 evaluations and the 3 escalations
+        assert_eq!(metrics.callbacks[&ProviderCallback::First].calls, 8);
+        assert_eq!(metrics.callbacks[&ProviderCallback::CancelJob].calls, 1);
+        assert!(!metrics.callbacks.contains_key(&ProviderCallback::Timer));
+
+        // The actions running at the reset are still counted when they end
+        manager.reset_metrics();
+        manager.run(&mut provider, 500); // tick: 16
+        let metrics = manager.disable_metrics().unwrap();
+        assert_eq!(metrics.runs, 1);
+        assert_eq!(
+            metrics.actions[type_name::<WaitState>()],
+            ActionCounts {
+                started: 1,
+                ended: 1,
+                canceled: 0,
+            }
+        );
+        assert!(!metrics.actions.contains_key(type_name::<MoveState>()));
+        assert!(manager.metrics().is_none());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        for value in [0, 1, 2, 3, 4, 7, 8, 1000] {
+            histogram.record(value);
+        }
+        histogram.record_n(5, 2);
+        histogram.record_n(u64::MAX, 0);
+
+        assert_eq!(
+            histogram.iter().collect::<Vec<_>>(),
+            vec![
+                (0..=0, 1),
+                (1..=1, 1),
+                (2..=3, 2),
+                (4..=7, 4),
+                (8..=15, 1),
+                (512..=1023, 1),
+            ]
+        );
+        assert_eq!(histogram.count(), 10);
+        assert_eq!(histogram.sum(), 1035);
+        assert_eq!(histogram.max(), 1000);
+        assert_eq!(histogram.mean(), 103.5f64);
+
+        histogram.record(u64::MAX);
+        assert_eq!(histogram.iter().last(), Some(((1 << 63)..=u64::MAX, 1)));
+        assert_eq!(histogram.sum(), u64::MAX);
+    }
+}
@@ -1,10 +1,25 @@
 use crate::action::manager::TimeMilliseconds;
-use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+use crate::v2::action::collection::{
+    TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId, TypedAnyTimerMap,
+};
 use crate::v2::action::manager::ActionController;
+use crate::v2::action::{ActionLayer, CancelReason};
+use crate::v2::checksum::StateHasher;
 use crate::v2::job::manager::JobController;
+use crate::v2::job::parallel::ActionBuffer;
+use std::sync::Arc;
 use tearchan_ecs::component::EntityId;
 
+pub mod behavior;
+pub mod delta;
+pub mod journal;
 pub mod manager;
+pub mod metrics;
+pub mod parallel;
+pub mod prefab;
+pub mod simulation;
+#[cfg(feature = "trace")]
+pub mod trace;
 
 pub trait HordeInterface {
     type Job: Clone;
@@ -13,7 +28,10 @@ pub trait HordeInterface {
 
     fn on_change_time(&mut self, map: &TypedAnyActionMapGroupedByEntityId, time: TimeMilliseconds);
 
-    fn on_cancel_job(&mut self, entity_id: EntityId, jobs: Vec<Self::Job>);
+    // Called with the timers fired on the tick, after on_change_tick
+    fn on_timer(&mut self, _timers: &TypedAnyTimerMap, _controller: JobController<Self::Job>) {}
+
+    fn on_cancel_job(&mut self, entity_id: EntityId, jobs: Vec<Self::Job>, reason: CancelReason);
 
     fn on_first(&self, entity_id: EntityId, priority: u32) -> Self::Job;
 
@@ -23,4 +41,61 @@ pub trait HordeInterface {
         job: Self::Job,
         controller: &mut ActionController,
     ) -> Option<Self::Job>;
+
+    // The manager calls the functions below for each layer of the entity. They run the jobs of
+    // all layers with the functions above by default, so override them to tell the layers apart.
+    fn on_cancel_job_with_layer(
+        &mut self,
+        entity_id: EntityId,
+        _layer: ActionLayer,
+        jobs: Vec<Self::Job>,
+        reason: CancelReason,
+    ) {
+        self.on_cancel_job(entity_id, jobs, reason);
+    }
+
+    fn on_first_with_layer(
+        &self,
+        entity_id: EntityId,
+        _layer: ActionLayer,
+        priority: u32,
+    ) -> Self::Job {
+        self.on_first(entity_id, priority)
+    }
+
+    fn on_next_with_layer(
+        &self,
+        entity_id: EntityId,
+        _layer: ActionLayer,
+        job: Self::Job,
+        controller: &mut ActionController,
+    ) -> Option<Self::Job> {
+        self.on_next(entity_id, job, controller)
+    }
+
+    fn on_checksum(&self, _entity_id: EntityId, _hasher: &mut StateHasher) {}
+}
+
+// The jobs evaluated on the worker threads by JobManager::run_parallel. The functions read the
+// world only through the view, and the actions are committed in the order of the entity ids.
+pub trait ParallelHordeInterface: HordeInterface {
+    type View: Send + Sync + 'static;
+
+    // Called once for each tick that has some vacated layers
+    fn view(&self) -> Arc<Self::View>;
+
+    fn on_first_parallel(
+        view: &Self::View,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        priority: u32,
+    ) -> Self::Job;
+
+    fn on_next_parallel(
+        view: &Self::View,
+        entity_id: EntityId,
+        layer: ActionLayer,
+        job: Self::Job,
+        buffer: &mut ActionBuffer,
+    ) -> Option<Self::Job>;
 }
@@ -0,0 +1,446 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{ActionController, ActionManager, EnqueueOptions};
+use crate::v2::action::ActionLayer;
+use crate::v2::job::metrics::ProviderCallback;
+#[cfg(feature = "trace")]
+use crate::v2::job::trace::TraceEventKind;
+use crate::v2::job::ParallelHordeInterface;
+use crate::v2::Tick;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::Arc;
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+use std::time::Instant;
+use tearchan_ecs::component::EntityId;
+
+type BufferedCommand = Box<dyn FnOnce(&mut ActionController) + Send>;
+
+// The actions enqueued by the job of an entity on the worker threads, which are committed after
+// all workers are finished
+pub struct ActionBuffer {
+    entity_id: EntityId,
+    layer: ActionLayer,
+    current_tick: Tick, // The tick of the entity
+    last_tick: Tick,    // The end of the buffered actions on the layer of the running job
+    tick_duration: TimeMilliseconds,
+    layers: BTreeSet<ActionLayer>,
+    commands: Vec<BufferedCommand>,
+}
+
+impl ActionBuffer {
+    pub fn new(entity_id: EntityId, layer: ActionLayer, action_manager: &ActionManager) -> Self {
+        let last_tick = action_manager
+            .last_tick_on_layer(entity_id, layer)
+            .unwrap_or_else(|| panic!("layer {} of entity {} is not attached", layer, entity_id));
+        ActionBuffer {
+            entity_id,
+            layer,
+            current_tick: action_manager.entity_tick(entity_id),
+            last_tick,
+            tick_duration: action_manager.tick_duration(),
+            layers: BTreeSet::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn entity_id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    // The layer of the running job
+    #[inline]
+    pub fn layer(&self) -> ActionLayer {
+        self.layer
+    }
+
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.current_tick
+    }
+
+    #[inline]
+    pub fn enqueue<T>(&mut self, raw: Arc<T>, duration: TimeMilliseconds)
+    where
+        T: 'static + Send + Sync,
+    {
+        self.enqueue_to_layer(self.layer, raw, duration, EnqueueOptions::default());
+    }
+
+    pub fn enqueue_to_layer<T>(
+        &mut self,
+        layer: ActionLayer,
+        raw: Arc<T>,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+    ) where
+        T: 'static + Send + Sync,
+    {
+        let entity_id = self.entity_id;
+        if layer == self.layer {
+            self.last_tick += duration / self.tick_duration;
+        }
+        self.layers.insert(layer);
+        self.commands.push(Box::new(move |controller| {
+            controller.enqueue_to_layer(entity_id, layer, raw, duration, options);
+        }));
+    }
+
+    #[inline]
+    pub fn has_actions_on_layer(&self, layer: ActionLayer) -> bool {
+        self.layers.contains(&layer)
+    }
+
+    // Same as ActionManager::has_some_actions_on_layer for the layer of the running job, so the
+    // actions which end on the current tick don't count
+    #[inline]
+    pub fn has_some_actions(&self) -> bool {
+        self.last_tick != self.current_tick
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn commit(self, controller: &mut ActionController) {
+        for command in self.commands {
+            command(controller);
+        }
+    }
+}
+
+// The result of the jobs of a vacated layer evaluated on a worker
+pub struct JobEvaluation<J> {
+    pub jobs: Vec<J>,
+    pub buffer: ActionBuffer,
+    #[cfg(feature = "trace")]
+    pub traces: Vec<TraceEventKind>,
+    // The elapsed time of the provider callbacks, which is None unless the metrics are enabled
+    #[cfg(feature = "metrics")]
+    pub callbacks: Option<Vec<(ProviderCallback, Duration)>>,
+    pub priority: u32, // The priority of the last first job, which is escalated from 0
+}
+
+#[cfg(feature = "metrics")]
+impl<J> JobEvaluation<J> {
+    // The clock is read only while the callbacks are timed
+    #[inline]
+    fn start_callback(&self) -> Option<Instant> {
+        self.callbacks.as_ref().map(|_| Instant::now())
+    }
+
+    #[inline]
+    fn end_callback(&mut self, callback: ProviderCallback, started: Option<Instant>) {
+        if let (Some(callbacks), Some(started)) = (&mut self.callbacks, started) {
+            callbacks.push((callback, started.elapsed()));
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl<J> JobEvaluation<J> {
+    #[inline]
+    fn start_callback(&self) -> Option<Instant> {
+        None
+    }
+
+    #[inline]
+    fn end_callback(&mut self, _callback: ProviderCallback, _started: Option<Instant>) {}
+}
+
+// Runs the same loop as JobManager::run on the view, except that the actions are buffered. The jobs
+// are formatted only with the trace feature, and the callbacks are timed only with the metrics
+// feature.
+#[cfg_attr(
+    not(all(feature = "trace", feature = "metrics")),
+    allow(unused_variables)
+)]
+pub fn evaluate_jobs<T>(
+    view: &T::View,
+    buffer: ActionBuffer,
+    format_job: Option<fn(&T::Job) -> String>,
+    timed: bool,
+) -> JobEvaluation<T::Job>
+where
+    T: ParallelHordeInterface,
+{
+    let entity_id = buffer.entity_id();
+    let layer = buffer.layer();
+    let mut evaluation = JobEvaluation {
+        jobs: Vec::new(),
+        buffer,
+        #[cfg(feature = "trace")]
+        traces: Vec::new(),
+        #[cfg(feature = "metrics")]
+        callbacks: timed.then(Vec::new),
+        priority: 0,
+    };
+    let mut priority = 0;
+    let mut job_queue: VecDeque<T::Job> = VecDeque::new();
+    let first = |priority: u32, evaluation: &mut JobEvaluation<T::Job>| {
+        let started = evaluation.start_callback();
+        let job = T::on_first_parallel(view, entity_id, layer, priority);
+        evaluation.end_callback(ProviderCallback::First, started);
+        #[cfg(feature = "trace")]
+        if let Some(format_job) = format_job {
+            evaluation.traces.push(TraceEventKind::First {
+                priority,
+                job: format_job(&job),
+            });
+        }
+        job
+    };
+    job_queue.push_front(first(priority, &mut evaluation));
+
+    while let Some(job) = job_queue.pop_front() {
+        evaluation.jobs.push(job.clone());
+
+        #[cfg(feature = "trace")]
+        let traced_job = format_job.map(|format_job| format_job(&job));
+        let started = evaluation.start_callback();
+        let result = T::on_next_parallel(view, entity_id, layer, job, &mut evaluation.buffer);
+        evaluation.end_callback(ProviderCallback::Next, started);
+        #[cfg(feature = "trace")]
+        if let (Some(format_job), Some(job)) = (format_job, traced_job) {
+            evaluation.traces.push(TraceEventKind::Next {
+                job,
+                result: result.as_ref().map(format_job),
+            });
+        }
+        if job_queue.is_empty() && !evaluation.buffer.has_some_actions() {
+            priority += 1;
+            evaluation.priority = priority;
+            evaluation.jobs.clear();
+            job_queue.push_front(first(priority, &mut evaluation));
+            continue;
+        }
+
+        if let Some(result) = result {
+            job_queue.push_back(result);
+        }
+    }
+    evaluation
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::{ActionLayer, CancelReason, DEFAULT_ACTION_LAYER};
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::parallel::ActionBuffer;
+    use crate::v2::job::{HordeInterface, ParallelHordeInterface};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+    use tearchan_util::thread::ThreadPool;
+
+    #[derive(Debug)]
+    struct MoveState;
+
+    #[derive(Debug)]
+    struct WaitState;
+
+    #[derive(Debug, Clone, Hash)]
+    enum TestJob {
+        Walk,
+        Rest,
+        Idle,
+    }
+
+    struct World {
+        speeds: BTreeMap<EntityId, u64>,
+    }
+
+    // The action to enqueue and the next job
+    fn plan(
+        world: &World,
+        entity_id: EntityId,
+        job: TestJob,
+    ) -> (Option<(bool, u64)>, Option<TestJob>) {
+        match job {
+            TestJob::Walk => match world.speeds.get(&entity_id) {
+                Some(speed) if *speed > 0 => (Some((true, 1200 / speed)), Some(TestJob::Rest)),
+                Some(_) => (Some((false, 0)), None), // Ends on the same tick, so it falls back
+                None => (None, None),                // Falls back to the next priority
+            },
+            TestJob::Rest => (Some((false, 100 * (entity_id % 3 + 1))), None),
+            TestJob::Idle => (Some((false, 500)), None),
+        }
+    }
+
+    struct TestProvider {
+        world: Arc<World>,
+        logs: Vec<String>,
+    }
+
+    impl TestProvider {
+        fn new() -> Self {
+            let speeds = (1..=16)
+                .map(|entity_id| (entity_id, entity_id % 4))
+                .collect();
+            TestProvider {
+                world: Arc::new(World { speeds }),
+                logs: Vec::new(),
+            }
+        }
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = TestJob;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<TestJob>) {
+            let validator = controller.validator();
+            let mut logs = map
+                .get::<MoveState>(&validator)
+                .unwrap_or_default()
+                .iter()
+                .map(|action| {
+                    format!(
+                        "[{}] {:?} {:?}",
+                        controller.current_tick(),
+                        action.entity_id(),
+                        action.ty()
+                    )
+                })
+                .collect::<Vec<_>>();
+            logs.sort();
+            self.logs.append(&mut logs);
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(
+            &mut self,
+            _entity_id: EntityId,
+            _jobs: Vec<TestJob>,
+            _reason: CancelReason,
+        ) {
+        }
+
+        fn on_first(&self, entity_id: EntityId, priority: u32) -> TestJob {
+            TestProvider::on_first_parallel(&self.world, entity_id, DEFAULT_ACTION_LAYER, priority)
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: TestJob,
+            controller: &mut ActionController,
+        ) -> Option<TestJob> {
+            let (action, next) = plan(&self.world, entity_id, job);
+            match action {
+                Some((true, duration)) => {
+                    controller.enqueue(entity_id, Arc::new(MoveState), duration)
+                }
+                Some((false, duration)) => {
+                    controller.enqueue(entity_id, Arc::new(WaitState), duration)
+                }
+                None => {}
+            }
+            next
+        }
+    }
+
+    impl ParallelHordeInterface for TestProvider {
+        type View = World;
+
+        fn view(&self) -> Arc<World> {
+            Arc::clone(&self.world)
+        }
+
+        fn on_first_parallel(
+            _view: &World,
+            _entity_id: EntityId,
+            _layer: ActionLayer,
+            priority: u32,
+        ) -> TestJob {
+            match priority {
+                0 => TestJob::Walk,
+                _ => TestJob::Idle,
+            }
+        }
+
+        fn on_next_parallel(
+            view: &World,
+            entity_id: EntityId,
+            _layer: ActionLayer,
+            job: TestJob,
+            buffer: &mut ActionBuffer,
+        ) -> Option<TestJob> {
+            let (action, next) = plan(view, entity_id, job);
+            match action {
+                Some((true, duration)) => buffer.enqueue(Arc::new(MoveState), duration),
+                Some((false, duration)) => buffer.enqueue(Arc::new(WaitState), duration),
+                None => {}
+            }
+            next
+        }
+    }
+
+    fn simulate(thread_pool: Option<ThreadPool>) -> (Vec<String>, u64) {
+        let mut provider = TestProvider::new();
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        for entity_id in 1..=16 {
+            manager.attach(entity_id);
+        }
+        for _ in 0..30 {
+            match &thread_pool {
+                None => manager.run(&mut provider, 100),
+                Some(thread_pool) => manager.run_parallel(&mut provider, thread_pool, 100),
+            }
+        }
+        let checksum = manager.checksum(&provider).hash();
+        (provider.logs, checksum)
+    }
+
+    #[test]
+    fn test_run_parallel() {
+        let (logs, checksum) = simulate(None);
+        assert!(!logs.is_empty());
+        for threads in [1, 4] {
+            let (parallel_logs, parallel_checksum) = simulate(Some(ThreadPool::new(threads)));
+            assert_eq!(parallel_logs, logs);
+            assert_eq!(parallel_checksum, checksum);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_run_parallel_metrics() {
+        use crate::v2::job::metrics::{JobMetrics, ProviderCallback};
+
+        let simulate = |thread_pool: Option<ThreadPool>| -> JobMetrics {
+            let mut provider = TestProvider::new();
+            let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+            manager.enable_metrics();
+            for entity_id in 1..=16 {
+                manager.attach(entity_id);
+            }
+            for _ in 0..30 {
+                match &thread_pool {
+                    None => manager.run(&mut provider, 100),
+                    Some(thread_pool) => manager.run_parallel(&mut provider, thread_pool, 100),
+                }
+            }
+            manager.disable_metrics().unwrap()
+        };
+        let metrics = simulate(None);
+        let parallel_metrics = simulate(Some(ThreadPool::new(4)));
+        for callback in [ProviderCallback::First, ProviderCallback::Next] {
+            assert!(metrics.callbacks[&callback].calls > 0);
+            assert_eq!(
+                parallel_metrics.callbacks[&callback].calls,
+                metrics.callbacks[&callback].calls
+            );
+        }
+        assert_eq!(parallel_metrics.escalations, metrics.escalations);
+    }
+}
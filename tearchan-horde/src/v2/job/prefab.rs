@@ -0,0 +1,241 @@
+use crate::v2::action::manager::RemapContext;
+use crate::v2::job::manager::{JobManager, JobManagerData, JobManagerError};
+use crate::v2::job::HordeInterface;
+use crate::v2::{HordeActions, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tearchan_ecs::component::EntityId;
+use tearchan_ecs::entity::manager::{EntityManager, EntityManagerData};
+
+// The entities captured from a running world with their components, queued actions and jobs.
+// It can be instantiated many times, and each instance gets the fresh entity ids and the ticks
+// rebased to the current tick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Prefab<T, U, C> {
+    entity_ids: BTreeSet<EntityId>,
+    data: JobManagerData<T, U>,
+    components: BTreeMap<EntityId, C>,
+}
+
+impl<T, U, C> Prefab<T, U, C> {
+    // The timers and the joints with the other entities aren't captured
+    pub fn capture<V, F>(
+        manager: &JobManager<V>,
+        entity_ids: BTreeSet<EntityId>,
+        mut capture_components: F,
+    ) -> Self
+    where
+        T: HordeActions,
+        V: HordeInterface<Job = U>,
+        F: FnMut(EntityId) -> C,
+    {
+        let mut data = manager.to_data::<T>();
+        data.retain_entities(&entity_ids);
+        let components = entity_ids
+            .iter()
+            .map(|entity_id| (*entity_id, capture_components(*entity_id)))
+            .collect();
+        Prefab {
+            entity_ids,
+            data,
+            components,
+        }
+    }
+
+    #[inline]
+    pub fn entity_ids(&self) -> &BTreeSet<EntityId> {
+        &self.entity_ids
+    }
+
+    // The tick that the prefab was captured at
+    #[inline]
+    pub fn current_tick(&self) -> Tick {
+        self.data.current_tick()
+    }
+
+    #[inline]
+    pub fn instantiate<V>(
+        &self,
+        entity_manager: &EntityManager,
+        manager: &mut JobManager<V>,
+    ) -> Result<PrefabInstance<C>, JobManagerError>
+    where
+        T: HordeActions + Clone,
+        U: Clone,
+        C: Clone,
+        V: HordeInterface<Job = U>,
+    {
+        self.instantiate_with(entity_manager, manager, |_entity_id, _components| {})
+    }
+
+    // Overrides the components of each instance with the entity ids of the prefab
+    pub fn instantiate_with<V, F>(
+        &self,
+        entity_manager: &EntityManager,
+        manager: &mut JobManager<V>,
+        mut override_components: F,
+    ) -> Result<PrefabInstance<C>, JobManagerError>
+    where
+        T: HordeActions + Clone,
+        U: Clone,
+        C: Clone,
+        V: HordeInterface<Job = U>,
+        F: FnMut(EntityId, &mut C),
+    {
+        let mut context = RemapContext::default();
+        entity_manager.load_data(
+            EntityManagerData::new(self.entity_ids.clone()),
+            context.entities_mut(),
+        );
+        if let Err(error) = manager.load_data(self.data.clone(), &mut context) {
+            for entity_id in self.entity_ids.iter() {
+                entity_manager.free(context.remap_entity(*entity_id));
+            }
+            return Err(error);
+        }
+
+        let components = self
+            .components
+            .iter()
+            .map(|(entity_id, components)| {
+                let mut components = components.clone();
+                override_components(*entity_id, &mut components);
+                (context.remap_entity(*entity_id), components)
+            })
+            .collect();
+        Ok(PrefabInstance {
+            context,
+            components,
+        })
+    }
+}
+
+pub struct PrefabInstance<C> {
+    // Remaps the entity ids and the ticks of the prefab referred by the components
+    pub context: RemapContext,
+    pub components: BTreeMap<EntityId, C>, // key = the new entity id
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::{ActionType, CancelReason};
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::prefab::Prefab;
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+    use tearchan_ecs::entity::manager::EntityManager;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState(i32);
+
+    define_actions!(TestAction, (Move, MoveState));
+
+    #[derive(Default)]
+    struct TestWorld {
+        speeds: BTreeMap<EntityId, i32>,
+        positions: BTreeMap<EntityId, i32>,
+    }
+
+    impl HordeInterface for TestWorld {
+        type Job = i32;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<i32>) {
+            let validator = controller.validator();
+            for action in map.get::<MoveState>(&validator).unwrap_or_default() {
+                if let ActionType::End { .. } = action.ty() {
+                    *self.positions.entry(action.entity_id()).or_default() += action.raw().0;
+                }
+            }
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, _entity_id: EntityId, _jobs: Vec<i32>, _reason: CancelReason) {}
+
+        fn on_first(&self, entity_id: EntityId, _priority: u32) -> i32 {
+            self.speeds[&entity_id]
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: i32,
+            controller: &mut ActionController,
+        ) -> Option<i32> {
+            controller.enqueue(entity_id, Arc::new(MoveState(job)), 300);
+            None
+        }
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let entity_manager = EntityManager::default();
+        let mut world = TestWorld::default();
+        let mut manager: JobManager<TestWorld> = JobManager::with_tick_duration(100);
+        let entity_ids = [
+            entity_manager.gen(),
+            entity_manager.gen(),
+            entity_manager.gen(),
+        ];
+        for (i, entity_id) in entity_ids.iter().enumerate() {
+            world.speeds.insert(*entity_id, i as i32 + 1);
+            manager.attach(*entity_id);
+        }
+        manager.run(&mut world, 500); // tick: 5
+
+        let prefab: Prefab<TestAction, i32, i32> = Prefab::capture(
+            &manager,
+            entity_ids[..2].iter().copied().collect(),
+            |entity_id| world.speeds[&entity_id],
+        );
+        let prefab: Prefab<TestAction, i32, i32> =
+            serde_json::from_str(&serde_json::to_string(&prefab).unwrap()).unwrap();
+        assert_eq!(prefab.current_tick(), 5);
+        assert_eq!(prefab.entity_ids().len(), 2);
+
+        manager.run(&mut world, 1000); // tick: 15
+        let mut instances = Vec::new();
+        for scale in [1, 10] {
+            let instance = prefab
+                .instantiate_with(&entity_manager, &mut manager, |_entity_id, speed| {
+                    *speed *= scale
+                })
+                .unwrap();
+            assert_eq!(instance.context.remap_tick(5), 15);
+            world.speeds.extend(instance.components.iter());
+            instances.push(instance);
+        }
+        manager.run(&mut world, 1000); // tick: 25
+
+        for instance in instances.iter() {
+            for entity_id in entity_ids.iter() {
+                assert!(!instance.components.contains_key(entity_id));
+            }
+        }
+        let positions = instances
+            .iter()
+            .map(|instance| {
+                entity_ids[..2]
+                    .iter()
+                    .map(|entity_id| world.positions[&instance.context.remap_entity(*entity_id)])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // The moves running at the capture end at the tick 16, and then the 3 moves with the
+        // overridden speeds follow
+        assert_eq!(positions, vec![vec![4, 8], vec![31, 62]]);
+        assert_eq!(world.positions[&entity_ids[0]], 8);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::v2::job::journal::{JobCommand, JobCommandRecord};
+use crate::v2::job::manager::JobManager;
+use crate::v2::job::HordeInterface;
+use crate::v2::{HordeActions, Tick};
+use serde::{Deserialize, Serialize};
+
+// The scripted inputs of a simulation, which are executed at the beginning of their ticks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimulationScript<T> {
+    records: Vec<JobCommandRecord<T>>,
+}
+
+impl<T> Default for SimulationScript<T> {
+    fn default() -> Self {
+        SimulationScript {
+            records: Vec::new(),
+        }
+    }
+}
+
+impl<T> SimulationScript<T> {
+    // The commands on the same tick are executed in the pushed order
+    pub fn push(&mut self, tick: Tick, command: JobCommand<T>) {
+        let index = self.records.partition_point(|record| record.tick <= tick);
+        self.records
+            .insert(index, JobCommandRecord { tick, command });
+    }
+
+    pub fn with(mut self, tick: Tick, command: JobCommand<T>) -> Self {
+        self.push(tick, command);
+        self
+    }
+
+    #[inline]
+    pub fn records(&self) -> &[JobCommandRecord<T>] {
+        &self.records
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+}
+
+// Drives the job manager and the world at full speed without any window or renderer. Each step
+// advances the time by the tick duration, so the same script always produces the same results.
+pub struct Simulation<T: HordeInterface> {
+    manager: JobManager<T>,
+    provider: T,
+    tick: Tick, // The number of the steps from the tick 0
+}
+
+impl<T> Simulation<T>
+where
+    T: HordeInterface,
+{
+    pub fn new(manager: JobManager<T>, provider: T) -> Self {
+        let tick = manager.current_tick();
+        Simulation {
+            manager,
+            provider,
+            tick,
+        }
+    }
+
+    // Runs the ticks and returns the observations after each tick. The commands of the script
+    // before the current tick are skipped, and JobCommand::Run is ignored since the simulation
+    // advances the time by itself.
+    pub fn run<U, O, F>(
+        &mut self,
+        script: &SimulationScript<U>,
+        ticks: Tick,
+        mut observe: F,
+    ) -> Vec<(Tick, O)>
+    where
+        U: HordeActions + Clone,
+        F: FnMut(Tick, &JobManager<T>, &T) -> O,
+    {
+        let tick_duration = self.manager.tick_duration();
+        let mut index = script
+            .records
+            .partition_point(|record| record.tick < self.tick);
+        let mut observations = Vec::with_capacity(ticks as usize);
+        for _ in 0..ticks {
+            while let Some(record) = script.records.get(index) {
+                if self.tick < record.tick {
+                    break;
+                }
+                index += 1;
+                if let JobCommand::Run { .. } = record.command {
+                    continue;
+                }
+                self.manager.execute(
+                    &mut self.provider,
+                    record.command.clone(),
+                    U::convert_and_enqueue_action,
+                );
+            }
+            self.manager.run(&mut self.provider, tick_duration);
+            self.tick += 1;
+            observations.push((self.tick, observe(self.tick, &self.manager, &self.provider)));
+        }
+        observations
+    }
+
+    #[inline]
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    #[inline]
+    pub fn manager(&self) -> &JobManager<T> {
+        &self.manager
+    }
+
+    #[inline]
+    pub fn manager_mut(&mut self) -> &mut JobManager<T> {
+        &mut self.manager
+    }
+
+    #[inline]
+    pub fn provider(&self) -> &T {
+        &self.provider
+    }
+
+    #[inline]
+    pub fn provider_mut(&mut self) -> &mut T {
+        &mut self.provider
+    }
+
+    pub fn into_parts(self) -> (JobManager<T>, T) {
+        (self.manager, self.provider)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::{ActionType, CancelReason};
+    use crate::v2::job::journal::JobCommand;
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::simulation::{Simulation, SimulationScript};
+    use crate::v2::job::HordeInterface;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState(i32);
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct StunState;
+
+    define_actions!(TestAction, (Move, MoveState), (Stun, StunState));
+
+    #[derive(Default)]
+    struct TestWorld {
+        positions: BTreeMap<EntityId, i32>,
+        cancels: u32,
+    }
+
+    impl HordeInterface for TestWorld {
+        type Job = i32;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<i32>) {
+            let validator = controller.validator();
+            for action in map.get::<MoveState>(&validator).unwrap_or_default() {
+                if let ActionType::End { .. } = action.ty() {
+                    *self.positions.entry(action.entity_id()).or_default() += action.raw().0;
+                }
+            }
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, _entity_id: EntityId, _jobs: Vec<i32>, _reason: CancelReason) {
+            self.cancels += 1;
+        }
+
+        fn on_first(&self, entity_id: EntityId, _priority: u32) -> i32 {
+            let position = self.positions.get(&entity_id).copied().unwrap_or_default();
+            if position < 10 {
+                entity_id as i32
+            } else {
+                -(entity_id as i32)
+            }
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: i32,
+            controller: &mut ActionController,
+        ) -> Option<i32> {
+            controller.enqueue(entity_id, Arc::new(MoveState(job)), 200 * entity_id);
+            None
+        }
+    }
+
+    fn simulate() -> Vec<(u64, Vec<(EntityId, i32)>)> {
+        let script = SimulationScript::default()
+            .with(0, JobCommand::Attach { entity_id: 1 })
+            .with(0, JobCommand::Attach { entity_id: 2 })
+            .with(12, JobCommand::Attach { entity_id: 3 })
+            .with(
+                20,
+                JobCommand::Interrupt {
+                    entity_id: 1,
+                    layer: 0,
+                    action: TestAction::Stun(Arc::new(StunState)),
+                    duration: 1000,
+                    reason: CancelReason::default(),
+                },
+            )
+            .with(25, JobCommand::Run { delta: 10000 })
+            .with(
+                35,
+                JobCommand::Cancel {
+                    entity_id: 2,
+                    layer: 0,
+                    immediate: true,
+                    reason: CancelReason::Unspecified,
+                },
+            );
+        let mut simulation =
+            Simulation::new(JobManager::with_tick_duration(100), TestWorld::default());
+        let mut observations = simulation.run(&script, 30, |_tick, _manager, world| {
+            world.positions.iter().map(|(k, v)| (*k, *v)).collect()
+        });
+        observations.append(&mut simulation.run(&script, 30, |_tick, _manager, world| {
+            world.positions.iter().map(|(k, v)| (*k, *v)).collect()
+        }));
+        assert_eq!(simulation.tick(), 60);
+        assert_eq!(simulation.provider().cancels, 2);
+        observations
+    }
+
+    #[test]
+    fn test_simulation() {
+        let observations = simulate();
+        assert_eq!(observations.len(), 60);
+        assert_eq!(observations, simulate());
+        insta::assert_debug_snapshot!(observations
+            .iter()
+            .filter(|(tick, _)| tick % 10 == 0)
+            .collect::<Vec<_>>());
+    }
+}
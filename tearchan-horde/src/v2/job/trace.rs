@@ -0,0 +1,364 @@
+use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::manager::{ActionTrace, ActionTraceKind};
+use crate::v2::action::{ActionLayer, ActionSessionId};
+use crate::v2::Tick;
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashSet};
+use tearchan_ecs::component::EntityId;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TraceEventKind {
+    First {
+        priority: u32,
+        job: String,
+    },
+    Next {
+        job: String,
+        result: Option<String>,
+    },
+    ActionStart {
+        session_id: ActionSessionId,
+        type_name: &'static str,
+    },
+    ActionEnd {
+        session_id: ActionSessionId,
+    },
+    ActionCancel {
+        session_id: ActionSessionId,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEvent {
+    pub tick: Tick,
+    pub entity_id: EntityId,
+    pub layer: ActionLayer,
+    pub kind: TraceEventKind,
+}
+
+// The timeline of the jobs and actions recorded by JobManager while tracing is enabled
+#[derive(Debug, Clone)]
+pub struct JobTracer {
+    tick_duration: TimeMilliseconds,
+    events: Vec<TraceEvent>,
+}
+
+impl JobTracer {
+    pub fn new(tick_duration: TimeMilliseconds) -> Self {
+        JobTracer {
+            tick_duration,
+            events: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn record(&mut self, event: TraceEvent) {
+        self.events.push(event);
+    }
+
+    pub fn record_action(&mut self, trace: ActionTrace) {
+        let ActionTrace {
+            tick,
+            entity_id,
+            layer,
+            session_id,
+            kind,
+        } = trace;
+        let kind = match kind {
+            ActionTraceKind::Start { type_name } => TraceEventKind::ActionStart {
+                session_id,
+                type_name,
+            },
+            ActionTraceKind::End => TraceEventKind::ActionEnd { session_id },
+            ActionTraceKind::Cancel => TraceEventKind::ActionCancel { session_id },
+        };
+        self.record(TraceEvent {
+            tick,
+            entity_id,
+            layer,
+            kind,
+        });
+    }
+
+    // Exports the events in the Trace Event Format, which is loadable by chrome://tracing and
+    // Perfetto. Each entity is shown as a process and each layer as a thread of it.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut trace_events = Vec::new();
+        let entities: BTreeSet<EntityId> =
+            self.events.iter().map(|event| event.entity_id).collect();
+        for entity_id in entities {
+            trace_events.push(json!({
+                "name": "process_name",
+                "ph": "M",
+                "pid": entity_id,
+                "args": { "name": format!("entity {}", entity_id) },
+            }));
+        }
+
+        let mut running = HashSet::new();
+        for event in self.events.iter() {
+            let key = (event.entity_id, event.layer);
+            let ts = event.tick * self.tick_duration * 1000;
+            let mut push = |name: &str, ph: &str, args: Value| {
+                let mut value = json!({
+                    "name": name,
+                    "cat": "horde",
+                    "ph": ph,
+                    "ts": ts,
+                    "pid": event.entity_id,
+                    "tid": event.layer,
+                    "args": args,
+                });
+                if ph == "i" {
+                    value["s"] = json!("t");
+                }
+                trace_events.push(value);
+            };
+            match &event.kind {
+                TraceEventKind::First { priority, job } => {
+                    push("first", "i", json!({ "priority": priority, "job": job }));
+                }
+                TraceEventKind::Next { job, result } => {
+                    push("next", "i", json!({ "job": job, "result": result }));
+                }
+                TraceEventKind::ActionStart {
+                    session_id,
+                    type_name,
+                } => {
+                    running.insert(key);
+                    push(
+                        &short_type_name(type_name),
+                        "B",
+                        json!({ "session_id": session_id.to_string() }),
+                    );
+                }
+                TraceEventKind::ActionEnd { session_id } => {
+                    if running.remove(&key) {
+                        push("", "E", json!({ "session_id": session_id.to_string() }));
+                    }
+                }
+                TraceEventKind::ActionCancel { session_id } => {
+                    // The canceled action never ends, so its slice is closed here
+                    if running.remove(&key) {
+                        push("", "E", json!({}));
+                    }
+                    push(
+                        "cancel",
+                        "i",
+                        json!({ "session_id": session_id.to_string() }),
+                    );
+                }
+            }
+        }
+
+        json!({
+            "traceEvents": trace_events,
+            "displayTimeUnit": "ms",
+        })
+        .to_string()
+    }
+
+    // Exports the events as one line per event, which is stable enough for snapshot tests
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for event in self.events.iter() {
+            let description = match &event.kind {
+                TraceEventKind::First { priority, job } => {
+                    format!("first priority={} job={}", priority, job)
+                }
+                TraceEventKind::Next { job, result } => match result {
+                    None => format!("next job={} -> None", job),
+                    Some(result) => format!("next job={} -> {}", job, result),
+                },
+                TraceEventKind::ActionStart {
+                    session_id,
+                    type_name,
+                } => format!("start #{} {}", session_id, short_type_name(type_name)),
+                TraceEventKind::ActionEnd { session_id } => format!("end #{}", session_id),
+                TraceEventKind::ActionCancel { session_id } => format!("cancel #{}", session_id),
+            };
+            text.push_str(&format!(
+                "{:>6} {}:{} {}\n",
+                event.tick, event.entity_id, event.layer, description
+            ));
+        }
+        text
+    }
+}
+
+// Removes the module paths from the type name, e.g. "a::B<c::D>" into "B<D>"
+fn short_type_name(type_name: &str) -> String {
+    let mut short = String::new();
+    let mut path = String::new();
+    for c in type_name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+            continue;
+        }
+        short.push_str(path.rsplit("::").next().unwrap_or_default());
+        path.clear();
+        short.push(c);
+    }
+    short.push_str(path.rsplit("::").next().unwrap_or_default());
+    short
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::CancelReason;
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::trace::short_type_name;
+    use crate::v2::job::HordeInterface;
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Debug)]
+    struct MoveState;
+
+    #[derive(Debug)]
+    struct WaitState;
+
+    #[derive(Debug, Clone)]
+    enum TestJob {
+        Walk { distance: u32 },
+        Wait,
+    }
+
+    struct TestProvider;
+
+    impl HordeInterface for TestProvider {
+        type Job = TestJob;
+
+        fn on_change_tick(
+            &mut self,
+            _map: &TypedAnyActionMap,
+            _controller: JobController<TestJob>,
+        ) {
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(
+            &mut self,
+            _entity_id: EntityId,
+            _jobs: Vec<TestJob>,
+            _reason: CancelReason,
+        ) {
+        }
+
+        fn on_first(&self, _entity_id: EntityId, priority: u32) -> TestJob {
+            match priority {
+                0 => TestJob::Walk { distance: 2 },
+                _ => TestJob::Wait,
+            }
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            job: TestJob,
+            controller: &mut ActionController,
+        ) -> Option<TestJob> {
+            match job {
+                TestJob::Walk { distance } if entity_id == 1 => {
+                    controller.enqueue(entity_id, Arc::new(MoveState), 1000 * distance as u64);
+                    Some(TestJob::Wait)
+                }
+                TestJob::Walk { .. } => None, // Falls back to the next priority
+                TestJob::Wait => {
+                    controller.enqueue(entity_id, Arc::new(WaitState), 500);
+                    None
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        let mut provider = TestProvider;
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        manager.enable_tracing();
+        manager.attach(1);
+        manager.attach(2);
+
+        manager.run(&mut provider, 0);
+        manager.run(&mut provider, 1000);
+        manager.cancel(1, true);
+        manager.run(&mut provider, 100);
+
+        let text = manager.tracer().unwrap().to_text();
+        insta::assert_snapshot!(text);
+
+        manager.disable_tracing();
+        manager.run(&mut provider, 1000);
+        assert!(manager.tracer().is_none());
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let mut provider = TestProvider;
+        let mut manager: JobManager<TestProvider> = JobManager::with_tick_duration(100);
+        manager.enable_tracing();
+        manager.attach(1);
+        manager.run(&mut provider, 0);
+        manager.cancel(1, true);
+        manager.run(&mut provider, 300);
+
+        let trace: serde_json::Value =
+            serde_json::from_str(&manager.tracer().unwrap().to_chrome_trace()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let phases = events
+            .iter()
+            .map(|event| {
+                format!(
+                    "{} {} {}",
+                    event["ph"].as_str().unwrap(),
+                    event["ts"],
+                    event["name"].as_str().unwrap()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            vec![
+                "M null process_name",
+                "i 0 first",
+                "i 0 next",
+                "i 0 next",
+                "B 0 MoveState",
+                "E 0 ",
+                "i 0 cancel",
+                "i 0 first",
+                "i 0 next",
+                "i 0 next",
+                "B 0 MoveState",
+            ]
+        );
+        assert_eq!(events[4]["pid"], 1);
+        assert_eq!(events[4]["tid"], 0);
+        assert_eq!(events[4]["args"]["session_id"], "1");
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name("a::b::C"), "C");
+        assert_eq!(short_type_name("a::B<c::D, e::F>"), "B<D, F>");
+        assert_eq!(short_type_name("u32"), "u32");
+    }
+}
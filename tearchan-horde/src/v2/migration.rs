@@ -0,0 +1,280 @@
+use crate::action::manager::{ActionManagerData as V1ActionManagerData, TimeMilliseconds};
+use crate::v2::action::manager::ActionManagerData;
+use crate::v2::action::{Action, ActionType, DEFAULT_ACTION_LAYER};
+use crate::v2::job::manager::JobManagerData;
+use crate::v2::Tick;
+use std::collections::{BTreeMap, BTreeSet};
+use tearchan_ecs::component::EntityId;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MigrationLoss {
+    Quantized, // The start or the end isn't on the tick boundary, so it's rounded to the nearest
+    Collapsed, // The action doesn't last for a tick after rounded, so it's dropped
+    Overlapped, // The action starts before the previous action of the entity ends, so it's dropped
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LossyAction {
+    pub entity_id: EntityId,
+    pub start_time: TimeMilliseconds,
+    pub end_time: TimeMilliseconds,
+    pub loss: MigrationLoss,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MigrationReport {
+    pub lossy_actions: Vec<LossyAction>,
+    // The data cannot have the entities without any actions, so they must be attached again
+    // after loading
+    pub idle_entities: Vec<EntityId>,
+}
+
+impl MigrationReport {
+    #[inline]
+    pub fn is_lossless(&self) -> bool {
+        self.lossy_actions.is_empty()
+    }
+}
+
+// Converts the v1 data which stores the times in milliseconds into the v2 data.
+// The times including the current time are rounded to the nearest tick, and all actions are
+// placed on the default layer.
+pub fn migrate_action_manager_data<T, U>(
+    data: &V1ActionManagerData<T>,
+    tick_duration: TimeMilliseconds,
+    converter: fn(&T) -> U,
+) -> (ActionManagerData<U>, MigrationReport)
+where
+    U: Clone,
+{
+    assert_ne!(tick_duration, 0, "Tick duration must be greater than 0");
+    let to_tick = |time: TimeMilliseconds| -> Tick { (time + tick_duration / 2) / tick_duration };
+    let current_tick = to_tick(data.current_time());
+
+    let mut v1_actions = data
+        .actions()
+        .iter()
+        .filter(|action| data.entity_ids().contains(&action.entity_id()))
+        .collect::<Vec<_>>();
+    v1_actions.sort_by_key(|action| (action.entity_id(), action.start_time()));
+
+    let mut report = MigrationReport::default();
+    let mut actions = Vec::new();
+    let mut last_ticks: BTreeMap<EntityId, Tick> = BTreeMap::new();
+    for v1_action in v1_actions {
+        if v1_action.end_time() < data.current_time() {
+            continue; // Already ended before saving
+        }
+        let entity_id = v1_action.entity_id();
+        let start = to_tick(v1_action.start_time());
+        let end = to_tick(v1_action.end_time());
+        let loss = if matches!(last_ticks.get(&entity_id), Some(last_tick) if start < *last_tick) {
+            Some(MigrationLoss::Overlapped)
+        } else if end <= start.max(current_tick) {
+            Some(MigrationLoss::Collapsed)
+        } else if v1_action.start_time() % tick_duration != 0
+            || v1_action.end_time() % tick_duration != 0
+        {
+            Some(MigrationLoss::Quantized)
+        } else {
+            None
+        };
+        if let Some(loss) = loss {
+            report.lossy_actions.push(LossyAction {
+                entity_id,
+                start_time: v1_action.start_time(),
+                end_time: v1_action.end_time(),
+                loss,
+            });
+            if loss != MigrationLoss::Quantized {
+                continue;
+            }
+        }
+        last_ticks.insert(entity_id, end);
+
+        let raw = converter(v1_action.inner());
+        let ty = if v1_action.start_time() < data.current_time() {
+            // The running action
+            ActionType::Update {
+                start: start * tick_duration,
+                end: end * tick_duration,
+            }
+        } else {
+            ActionType::Start {
+                start,
+                end,
+                each: false,
+            }
+        };
+        let action_of = |ty| Action::with_layer(raw.clone(), entity_id, DEFAULT_ACTION_LAYER, ty);
+        actions.push(action_of(ty));
+        actions.push(action_of(ActionType::End { start, end }));
+    }
+
+    report.idle_entities = data
+        .entity_ids()
+        .iter()
+        .filter(|entity_id| !last_ticks.contains_key(entity_id))
+        .copied()
+        .collect();
+    (
+        ActionManagerData::new(actions, tick_duration, current_tick),
+        report,
+    )
+}
+
+// The v1 data doesn't have the jobs, so the entities start from the first job when their actions
+// are finished
+pub fn migrate_job_manager_data<T, U, J>(
+    data: &V1ActionManagerData<T>,
+    tick_duration: TimeMilliseconds,
+    converter: fn(&T) -> U,
+) -> (JobManagerData<U, J>, MigrationReport)
+where
+    U: Clone,
+{
+    let (action_manager_data, report) = migrate_action_manager_data(data, tick_duration, converter);
+    let idle_entities = report.idle_entities.iter().collect::<BTreeSet<_>>();
+    let jobs = data
+        .entity_ids()
+        .iter()
+        .filter(|entity_id| !idle_entities.contains(entity_id))
+        .map(|entity_id| ((*entity_id, DEFAULT_ACTION_LAYER), Vec::new()))
+        .collect();
+    (JobManagerData::new(action_manager_data, jobs), report)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::ActionManagerData as V1ActionManagerData;
+    use crate::define_actions;
+    use crate::v2::action::manager::ActionManager;
+    use crate::v2::migration::{
+        migrate_action_manager_data, migrate_job_manager_data, LossyAction, MigrationLoss,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JumpState;
+
+    define_actions!(TestAction, (Move, MoveState), (Jump, JumpState));
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    enum V1State {
+        Move,
+        Jump,
+    }
+
+    fn convert(state: &V1State) -> TestAction {
+        match state {
+            V1State::Move => TestAction::Move(Arc::new(MoveState)),
+            V1State::Jump => TestAction::Jump(Arc::new(JumpState)),
+        }
+    }
+
+    fn create_v1_data() -> V1ActionManagerData<V1State> {
+        let action = |entity_id: u32, start: u64, end: u64, inner: &str| {
+            format!(
+                r#"{{"entityId":{},"startTime":{},"endTime":{},"inner":"{}"}}"#,
+                entity_id, start, end, inner
+            )
+        };
+        let actions = [
+            action(1, 900, 1500, "Move"),
+            action(1, 1500, 2050, "Jump"),
+            action(1, 2050, 2060, "Move"),
+            action(2, 1000, 1040, "Move"),
+            action(4, 500, 800, "Move"),
+            action(4, 1200, 1800, "Move"),
+            action(4, 1700, 1900, "Jump"),
+        ];
+        serde_json::from_str(&format!(
+            r#"{{"actions":[{}],"entityIds":[1,2,3,4],"currentTime":1000}}"#,
+            actions.join(",")
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_action_manager_data() {
+        let (data, report) = migrate_action_manager_data(&create_v1_data(), 100, convert);
+        assert_eq!(
+            report.lossy_actions,
+            vec![
+                LossyAction {
+                    entity_id: 1,
+                    start_time: 1500,
+                    end_time: 2050,
+                    loss: MigrationLoss::Quantized,
+                },
+                LossyAction {
+                    entity_id: 1,
+                    start_time: 2050,
+                    end_time: 2060,
+                    loss: MigrationLoss::Collapsed,
+                },
+                LossyAction {
+                    entity_id: 2,
+                    start_time: 1000,
+                    end_time: 1040,
+                    loss: MigrationLoss::Collapsed,
+                },
+                LossyAction {
+                    entity_id: 4,
+                    start_time: 1700,
+                    end_time: 1900,
+                    loss: MigrationLoss::Overlapped,
+                },
+            ]
+        );
+        assert!(!report.is_lossless());
+        assert_eq!(report.idle_entities, vec![2, 3]);
+
+        let data = serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+        let mut manager = ActionManager::from_data(data, convert_from_actions).unwrap();
+        assert_eq!(manager.current_tick(), 10);
+        assert_eq!(manager.pull_updates().get::<MoveState>().unwrap().len(), 1);
+
+        let mut logs = Vec::new();
+        manager.update(1500);
+        while let Some(result) = manager.pull_actions() {
+            let validator = manager.validator();
+            let mut actions = convert_actions_from_typed_action_any_map(&result.map, &validator)
+                .into_iter()
+                .map(|action| format!("{} {:?}", action.entity_id(), action.ty()))
+                .collect::<Vec<_>>();
+            actions.sort();
+            logs.append(&mut actions);
+        }
+        assert_eq!(
+            logs,
+            vec![
+                "4 Start { start: 12, end: 18, each: false }",
+                "1 End { start: 9, end: 15 }",
+                "1 Start { start: 15, end: 21, each: false }",
+                "4 End { start: 12, end: 18 }",
+                "1 End { start: 15, end: 21 }",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_job_manager_data() {
+        let (data, report) = migrate_job_manager_data::<_, _, u32>(&create_v1_data(), 100, convert);
+        assert_eq!(report.idle_entities, vec![2, 3]);
+
+        let value = serde_json::to_value(&data).unwrap();
+        let mut entities = value["jobs"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        entities.sort();
+        assert_eq!(entities, vec!["1", "4"]);
+    }
+}
@@ -1,9 +1,14 @@
 pub mod action;
+pub mod checksum;
+pub mod easing;
 pub mod job;
+pub mod migration;
+pub mod rollback;
 pub use serde;
 
 pub type Lazy<T> = once_cell::sync::Lazy<T>;
 pub type Tick = u64;
+pub type EntityId = tearchan_ecs::component::EntityId;
 
 pub fn calc_ratio_f32_from_ms(
     start: TimeMilliseconds,
@@ -39,38 +44,69 @@ pub fn calc_ratio_f32_from_tick(start: Tick, end: Tick, value: Tick) -> f32 {
     v as f32 / d as f32
 }
 
+// Converts the actions between the typed states and the enum of them. Derive it with
+// #[derive(HordeActions)] rather than implementing by hand.
+pub trait HordeActions: Sized {
+    fn convert_actions_from_typed_action_any_map(
+        map: &TypedAnyActionMap,
+        validator: &ActionSessionValidator,
+    ) -> Vec<Action<Self>> {
+        map.iter()
+            .flat_map(|(type_id, vec)| {
+                Self::convert_actions_from_any_action_vec(type_id, vec, validator)
+            })
+            .collect()
+    }
+
+    fn convert_actions_from_any_action_vec(
+        type_id: &TypeId,
+        vec: &AnyActionVec,
+        validator: &ActionSessionValidator,
+    ) -> Vec<Action<Self>>;
+
+    fn convert_actions_from_typed_any_action_map(
+        map: &TypedAnyActionMapGroupedByEntityId,
+    ) -> Vec<Action<Self>>;
+
+    fn convert_and_enqueue_action(
+        entity_id: EntityId,
+        layer: ActionLayer,
+        action: Self,
+        duration: TimeMilliseconds,
+        controller: &mut ActionController,
+    );
+
+    fn convert_and_enqueue_joint_action(
+        participants: &[(EntityId, ActionLayer)],
+        action: Self,
+        duration: TimeMilliseconds,
+        options: EnqueueOptions,
+        controller: &mut ActionController,
+    );
+
+    fn convert_and_schedule_timer(
+        action: Self,
+        delay: TimeMilliseconds,
+        options: TimerOptions,
+        controller: &mut ActionController,
+    ) -> TimerId;
+
+    fn convert_from_actions(action: Action<Self>, converter: &mut ActionManagerConverter);
+}
+
+// Kept for the existing users. It defines the enum with #[derive(HordeActions)] and the free
+// functions calling the trait, so only one action set can be defined per module.
 #[macro_export]
 macro_rules! define_actions {
     ($name:tt, $(($member:tt, $struct:tt)),*) => {
-        type Mapper0 = std::collections::HashMap<std::any::TypeId, fn(any: &$crate::v2::action::collection::AnyActionVec, validator: &$crate::v2::action::manager::ActionSessionValidator) -> Vec<$crate::v2::action::Action<$name>>>;
-        type Mapper1 = std::collections::HashMap<std::any::TypeId, fn(any: &$crate::v2::action::collection::AnyVec) -> Vec<$crate::v2::action::Action<$name>>>;
-        static MAPPER0: $crate::v2::Lazy<Mapper0> = $crate::v2::Lazy::new(|| {
-            let mut map: Mapper0 = std::collections::HashMap::new();
-            $(
-            map.insert(std::any::TypeId::of::<$struct>(), |vec, validator| {
-                vec.cast::<$struct>(validator)
-                    .iter()
-                    .map(|action| action.replace($name::$member(action.raw().clone())))
-                    .collect::<Vec<_>>()
-            });
-            )*
-            map
-        });
-        static MAPPER1: $crate::v2::Lazy<Mapper1> = $crate::v2::Lazy::new(|| {
-            let mut map: Mapper1 = std::collections::HashMap::new();
-            $(
-            map.insert(std::any::TypeId::of::<$struct>(), |vec| {
-                vec.cast::<$crate::v2::action::ArcAction<$struct>>()
-                    .iter()
-                    .map(|action| action.replace($name::$member(action.raw().clone())))
-                    .collect::<Vec<_>>()
-            });
-            )*
-            map
-        });
-
         #[allow(dead_code)]
-        #[derive(Clone, Debug, $crate::v2::serde::Serialize, $crate::v2::serde::Deserialize)]
+        #[derive(
+            Clone,
+            Debug,
+            $crate::v2::serde::Serialize,
+            $crate::v2::serde::Deserialize,
+            $crate::v2::HordeActions,
+        )]
         pub enum $name {
             $(
                 $member(std::sync::Arc<$struct>),
@@ -82,20 +118,14 @@ macro_rules! define_actions {
             map: &$crate::v2::action::collection::TypedAnyActionMap,
             validator: &$crate::v2::action::manager::ActionSessionValidator
         ) -> Vec<$crate::v2::action::Action<$name>> {
-            map.iter()
-                .filter_map(|(key, value)| MAPPER0.get(&key).map(|f| f(value, validator)))
-                .flatten()
-                .collect()
+            <$name as $crate::v2::HordeActions>::convert_actions_from_typed_action_any_map(map, validator)
         }
 
         #[allow(dead_code)]
         fn convert_actions_from_typed_any_action_map(
             map: &$crate::v2::action::collection::TypedAnyActionMapGroupedByEntityId
         ) -> Vec<$crate::v2::action::Action<$name>> {
-            map.iter()
-                .filter_map(|(key, value)| MAPPER1.get(&key).map(|f| f(value)))
-                .flatten()
-                .collect()
+            <$name as $crate::v2::HordeActions>::convert_actions_from_typed_any_action_map(map)
         }
 
         #[allow(dead_code)]
@@ -104,29 +134,66 @@ macro_rules! define_actions {
             vec: &$crate::v2::action::collection::AnyActionVec,
             validator: &$crate::v2::action::manager::ActionSessionValidator
         ) -> Vec<$crate::v2::action::Action<$name>> {
-            MAPPER0.get(type_id).map(|f| f(vec, validator)).unwrap_or_else(Vec::new)
+            <$name as $crate::v2::HordeActions>::convert_actions_from_any_action_vec(type_id, vec, validator)
+        }
+
+        #[allow(dead_code)]
+        fn convert_and_enqueue_action(
+            entity_id: $crate::v2::EntityId,
+            layer: $crate::v2::action::ActionLayer,
+            action: $name,
+            duration: $crate::action::manager::TimeMilliseconds,
+            controller: &mut $crate::v2::action::manager::ActionController,
+        ) {
+            <$name as $crate::v2::HordeActions>::convert_and_enqueue_action(entity_id, layer, action, duration, controller)
+        }
+
+        #[allow(dead_code)]
+        fn convert_and_enqueue_joint_action(
+            participants: &[($crate::v2::EntityId, $crate::v2::action::ActionLayer)],
+            action: $name,
+            duration: $crate::action::manager::TimeMilliseconds,
+            options: $crate::v2::action::manager::EnqueueOptions,
+            controller: &mut $crate::v2::action::manager::ActionController,
+        ) {
+            <$name as $crate::v2::HordeActions>::convert_and_enqueue_joint_action(participants, action, duration, options, controller)
+        }
+
+        #[allow(dead_code)]
+        fn convert_and_schedule_timer(
+            action: $name,
+            delay: $crate::action::manager::TimeMilliseconds,
+            options: $crate::v2::action::timer::TimerOptions,
+            controller: &mut $crate::v2::action::manager::ActionController,
+        ) -> $crate::v2::action::timer::TimerId {
+            <$name as $crate::v2::HordeActions>::convert_and_schedule_timer(action, delay, options, controller)
         }
 
         #[allow(dead_code)]
         fn convert_from_actions(action: $crate::v2::action::Action<$name>, converter: &mut $crate::v2::action::manager::ActionManagerConverter) {
-            match action.raw() {
-            $(
-                $name::$member(state) => {
-                    converter.load(action.replace(std::sync::Arc::clone(state)));
-                },
-            )*
-            }
+            <$name as $crate::v2::HordeActions>::convert_from_actions(action, converter)
         }
     }
 }
 use crate::action::manager::TimeMilliseconds;
+use crate::v2::action::collection::{
+    AnyActionVec, TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId,
+};
+use crate::v2::action::manager::{
+    ActionController, ActionManagerConverter, ActionSessionValidator, EnqueueOptions,
+};
+use crate::v2::action::timer::{TimerId, TimerOptions};
+use crate::v2::action::{Action, ActionLayer};
 pub use define_actions;
+use std::any::TypeId;
+pub use tearchan_horde_derive::HordeActions;
 
 #[cfg(test)]
 mod test {
     use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
     use crate::v2::action::manager::ActionSessionValidator;
     use crate::v2::action::{Action, ActionSessionId, ActionType};
+    use crate::v2::HordeActions;
     use serde::{Deserialize, Serialize};
     use std::sync::Arc;
 
@@ -136,8 +203,23 @@ mod test {
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct JumpState;
 
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct FlashState;
+
     define_actions!(TestAction, (Move, MoveState), (Jump, JumpState));
 
+    // Several action sets can be derived in the same module
+    #[derive(Clone, Debug, Deserialize, Serialize, HordeActions)]
+    pub enum UnitAction {
+        Move(Arc<MoveState>),
+        Jump(Arc<JumpState>),
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, HordeActions)]
+    pub enum EffectAction {
+        Flash(std::sync::Arc<FlashState>),
+    }
+
     #[test]
     fn test_macro_0() {
         let validator = ActionSessionValidator::default();
@@ -186,4 +268,39 @@ mod test {
         assert_eq!(move_states.unwrap().len(), 1);
         assert_eq!(actions.len(), 1);
     }
+
+    #[test]
+    fn test_derive() {
+        let validator = ActionSessionValidator::default();
+        let start = ActionType::Start {
+            start: 0,
+            end: 1,
+            each: false,
+        };
+        let mut collections = TypedAnyActionMap::default();
+        collections.push(
+            Action::new(Arc::new(MoveState), 1, start),
+            ActionSessionId::default(),
+        );
+        collections.push(
+            Action::new(Arc::new(JumpState), 2, start),
+            ActionSessionId::default(),
+        );
+        collections.push(
+            Action::new(Arc::new(FlashState), 3, start),
+            ActionSessionId::default(),
+        );
+
+        let mut actions =
+            UnitAction::convert_actions_from_typed_action_any_map(&collections, &validator);
+        actions.sort_by_key(|x| x.entity_id());
+        assert_eq!(actions.len(), 2);
+        assert!(matches!(actions[0].raw(), UnitAction::Move(_)));
+        assert!(matches!(actions[1].raw(), UnitAction::Jump(_)));
+
+        let actions =
+            EffectAction::convert_actions_from_typed_action_any_map(&collections, &validator);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].entity_id(), 3);
+    }
 }
@@ -0,0 +1,237 @@
+use crate::v2::Tick;
+use std::collections::VecDeque;
+
+// Ring buffer of the snapshots ordered by tick.
+// Only the first snapshot of each tick is kept, that is the state before any input of the tick.
+pub struct RollbackBuffer<T> {
+    capacity: usize,
+    snapshots: VecDeque<(Tick, T)>,
+}
+
+impl<T> RollbackBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        RollbackBuffer {
+            capacity,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, tick: Tick, snapshot: T) {
+        if let Some((last_tick, _)) = self.snapshots.back() {
+            if *last_tick >= tick {
+                return;
+            }
+        }
+        self.snapshots.push_back((tick, snapshot));
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // Returns the latest snapshot at or before the tick
+    pub fn find(&self, tick: Tick) -> Option<(Tick, &T)> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|(snapshot_tick, _)| *snapshot_tick <= tick)
+            .map(|(snapshot_tick, snapshot)| (*snapshot_tick, snapshot))
+    }
+
+    pub fn truncate_after(&mut self, tick: Tick) {
+        while let Some((last_tick, _)) = self.snapshots.back() {
+            if *last_tick <= tick {
+                break;
+            }
+            self.snapshots.pop_back();
+        }
+    }
+
+    #[inline]
+    pub fn oldest_tick(&self) -> Option<Tick> {
+        self.snapshots.front().map(|(tick, _)| *tick)
+    }
+
+    #[inline]
+    pub fn latest_tick(&self) -> Option<Tick> {
+        self.snapshots.back().map(|(tick, _)| *tick)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::action::manager::TimeMilliseconds;
+    use crate::define_actions;
+    use crate::v2::action::collection::{TypedAnyActionMap, TypedAnyActionMapGroupedByEntityId};
+    use crate::v2::action::manager::ActionController;
+    use crate::v2::action::CancelReason;
+    use crate::v2::job::journal::{JobCommand, JobJournal};
+    use crate::v2::job::manager::{JobController, JobManager};
+    use crate::v2::job::HordeInterface;
+    use crate::v2::rollback::RollbackBuffer;
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use tearchan_ecs::component::EntityId;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct MoveState;
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct JumpState;
+
+    define_actions!(TestAction, (Move, MoveState), (Jump, JumpState));
+
+    #[derive(Default)]
+    struct TestProvider {
+        logs: Vec<String>,
+    }
+
+    impl HordeInterface for TestProvider {
+        type Job = u32;
+
+        fn on_change_tick(&mut self, map: &TypedAnyActionMap, controller: JobController<u32>) {
+            let validator = controller.validator();
+            let mut logs = Vec::new();
+            for action in map.get::<MoveState>(&validator).unwrap_or_default() {
+                logs.push(format!("{:?}", action));
+            }
+            for action in map.get::<JumpState>(&validator).unwrap_or_default() {
+                logs.push(format!("{:?}", action));
+            }
+            logs.sort();
+            self.logs.append(&mut logs);
+        }
+
+        fn on_change_time(
+            &mut self,
+            _map: &TypedAnyActionMapGroupedByEntityId,
+            _time: TimeMilliseconds,
+        ) {
+        }
+
+        fn on_cancel_job(&mut self, entity_id: EntityId, jobs: Vec<u32>, _reason: CancelReason) {
+            self.logs.push(format!("cancel {} {:?}", entity_id, jobs));
+        }
+
+        fn on_first(&self, _entity_id: EntityId, priority: u32) -> u32 {
+            priority
+        }
+
+        fn on_next(
+            &self,
+            entity_id: EntityId,
+            _job: u32,
+            controller: &mut ActionController,
+        ) -> Option<u32> {
+            controller.enqueue(entity_id, Arc::new(MoveState), 300 * entity_id);
+            None
+        }
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut buffer = RollbackBuffer::new(3);
+        buffer.push(0, "a");
+        buffer.push(0, "b");
+        buffer.push(2, "c");
+        buffer.push(5, "d");
+        buffer.push(7, "e");
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.oldest_tick(), Some(2));
+        assert_eq!(buffer.find(1), None);
+        assert_eq!(buffer.find(4), Some((2, &"c")));
+        assert_eq!(buffer.find(5), Some((5, &"d")));
+        assert_eq!(buffer.find(100), Some((7, &"e")));
+
+        buffer.truncate_after(5);
+        assert_eq!(buffer.latest_tick(), Some(5));
+        buffer.push(6, "f");
+        assert_eq!(buffer.find(100), Some((6, &"f")));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let inputs = |late: bool| {
+            let mut commands = vec![JobCommand::Run { delta: 500 }; 10];
+            let interrupt = JobCommand::Interrupt {
+                entity_id: 2,
+                layer: 0,
+                action: TestAction::Jump(Arc::new(JumpState)),
+                duration: 400,
+                reason: CancelReason::default(),
+            };
+            commands.insert(if late { 6 } else { 4 }, interrupt);
+            commands
+        };
+
+        // The authoritative simulation which received the input in time
+        let mut provider = TestProvider::default();
+        let mut manager: JobManager<TestProvider> = JobManager::default();
+        manager.attach(1);
+        manager.attach(2);
+        let mut journal = JobJournal::default();
+        let mut log_indices = Vec::new();
+        for command in inputs(false) {
+            log_indices.push((manager.current_tick(), provider.logs.len()));
+            journal.record(
+                &mut manager,
+                &mut provider,
+                command,
+                convert_and_enqueue_action,
+            );
+        }
+
+        // The simulation which received the input late
+        let mut late_provider = TestProvider::default();
+        let mut late_manager: JobManager<TestProvider> = JobManager::default();
+        late_manager.enable_rollback(8);
+        late_manager.attach(1);
+        late_manager.attach(2);
+        for command in inputs(true) {
+            late_manager.execute(&mut late_provider, command, convert_and_enqueue_action);
+        }
+        assert_ne!(
+            manager.checksum(&provider),
+            late_manager.checksum(&late_provider)
+        );
+
+        let input_tick = journal.records()[4].tick;
+        let rewound_tick = late_manager.rewind_to(input_tick).unwrap();
+        assert_eq!(rewound_tick, input_tick);
+
+        let (_, log_index) = log_indices
+            .iter()
+            .find(|(tick, _)| *tick == rewound_tick)
+            .unwrap();
+        late_provider.logs.clear();
+        journal
+            .since(rewound_tick)
+            .replay(
+                &mut late_manager,
+                &mut late_provider,
+                convert_and_enqueue_action,
+            )
+            .unwrap();
+
+        assert_eq!(provider.logs[*log_index..], late_provider.logs[..]);
+        assert_eq!(
+            manager.checksum(&provider),
+            late_manager.checksum(&late_provider)
+        );
+    }
+}